@@ -0,0 +1,479 @@
+#![allow(unused)]
+use std::collections::HashMap;
+
+use drowsed_math::{FVec3, TransformQuaternion3D};
+use fbxcel_dom::fbxcel::tree::v7400::NodeHandle;
+
+use super::error::ModelLoadError;
+use super::model_loader::{NodeAttributes, Connection};
+use super::scene::{SceneGraph, ModelTransform};
+use super::transform::{self, affine_inverse};
+
+/// FBX stores time as `KTime`, an integer amount of these per second.
+pub const FBX_TICKS_PER_SECOND: i64 = 46_186_158_000;
+
+fn ticks_to_seconds(ticks: i64) -> f64 {
+    ticks as f64 / FBX_TICKS_PER_SECOND as f64
+}
+
+/// How the value of a curve gets from one key to the next.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyInterpolation {
+    /// Holds the value of the key until the next one.
+    Constant,
+    #[default]
+    Linear,
+    /// Hermite curve using the slopes the file stores next to the key.
+    Cubic,
+}
+impl KeyInterpolation {
+    /// The interpolation bits of a `KeyAttrFlags` entry.
+    pub fn from_fbx(flags: i32) -> Self {
+        if flags & 0x02 != 0 {
+            Self::Constant
+        } else if flags & 0x08 != 0 {
+            Self::Cubic
+        } else {
+            Self::Linear
+        }
+    }
+}
+
+/// # AnimationCurve
+///
+/// One animated number, usually a single component of a translation, rotation or scale.
+/// `times` are in seconds on the timeline of the file. Every key has an interpolation
+/// that decides how to get to the key after it and two slopes (units per second)
+/// that only cubic keys use, the one leaving this key and the one arriving at the next.
+#[derive(Default, Debug, Clone)]
+pub struct AnimationCurve {
+    pub default: f32,
+    pub times: Vec<f64>,
+    pub values: Vec<f32>,
+    pub interpolation: Vec<KeyInterpolation>,
+    pub slopes: Vec<[f32; 2]>,
+}
+impl AnimationCurve {
+    pub(crate) fn parse(node: &NodeHandle) -> Result<(i64, AnimationCurve), ModelLoadError> {
+        let collection_id = node.attr_i64(0)?;
+        let mut curve = AnimationCurve::default();
+        let mut flags: Vec<i32> = vec![];
+        let mut data: Vec<f32> = vec![];
+        let mut ref_counts: Vec<i32> = vec![];
+        for child in node.children() {
+            match child.name() {
+                "Default" => curve.default = child.attr_f64(0)? as f32,
+                "KeyTime" => curve.times = child.attr_arr_i64(0)?.iter().map(|t| ticks_to_seconds(*t)).collect(),
+                "KeyValueFloat" => curve.values = child.attr_arr_f32(0)?,
+                "KeyAttrFlags" => flags = child.attr_arr_i32(0)?.to_vec(),
+                "KeyAttrDataFloat" => data = child.attr_arr_f32(0)?,
+                "KeyAttrRefCount" => ref_counts = child.attr_arr_i32(0)?.to_vec(),
+                _ => {}
+            }
+        }
+        if curve.times.len() != curve.values.len() {
+            return Err(ModelLoadError::IndexOutOfRange {
+                path: format!("{}/KeyValueFloat", node.path()),
+                index: curve.times.len(),
+                len: curve.values.len(),
+            });
+        }
+        // Keys share their attributes, KeyAttrRefCount says how many keys in a row use each one.
+        let keys = curve.times.len();
+        let mut attributes: Vec<usize> = Vec::with_capacity(keys);
+        for (attribute, count) in ref_counts.iter().enumerate() {
+            attributes.extend(std::iter::repeat(attribute).take((*count).max(0) as usize));
+        }
+        attributes.resize(keys, attributes.last().copied().unwrap_or(0));
+
+        for key in 0..keys {
+            let attribute = attributes[key];
+            curve.interpolation.push(flags.get(attribute).map(|f| KeyInterpolation::from_fbx(*f)).unwrap_or_default());
+            let slopes = match data.get(attribute * 4..attribute * 4 + 2) {
+                Some(slopes) => [slopes[0], slopes[1]],
+                // Without slopes a cubic key just behaves like a linear one.
+                None => {
+                    let secant = match (curve.times.get(key + 1), curve.values.get(key + 1)) {
+                        (Some(t), Some(v)) if *t > curve.times[key] => (v - curve.values[key]) / (t - curve.times[key]) as f32,
+                        _ => 0.0,
+                    };
+                    [secant, secant]
+                }
+            };
+            curve.slopes.push(slopes);
+        }
+        Ok((collection_id, curve))
+    }
+    /// Time of the first and last key, `None` when the curve has no keys.
+    pub fn range(&self) -> Option<(f64, f64)> {
+        Some((*self.times.first()?, *self.times.last()?))
+    }
+    /// Value of the curve at `time` seconds. Before the first and after the last key
+    /// the curve holds the value of that key.
+    pub fn evaluate(&self, time: f64) -> f32 {
+        let (Some(first), Some(last)) = (self.values.first(), self.values.last()) else {
+            return self.default;
+        };
+        if time <= self.times[0] {
+            return *first;
+        }
+        let next = self.times.partition_point(|t| *t <= time);
+        if next >= self.times.len() {
+            return *last;
+        }
+        let key = next - 1;
+        let (t0, t1) = (self.times[key], self.times[next]);
+        let (v0, v1) = (self.values[key], self.values[next]);
+        let dt = t1 - t0;
+        if dt <= 0.0 {
+            return v1;
+        }
+        let s = ((time - t0) / dt) as f32;
+        match self.interpolation[key] {
+            KeyInterpolation::Constant => v0,
+            KeyInterpolation::Linear => v0 + (v1 - v0) * s,
+            KeyInterpolation::Cubic => {
+                let [right, next_left] = self.slopes[key];
+                let (s2, s3) = (s * s, s * s * s);
+                (2.0 * s3 - 3.0 * s2 + 1.0) * v0
+                    + (s3 - 2.0 * s2 + s) * right * dt as f32
+                    + (-2.0 * s3 + 3.0 * s2) * v1
+                    + (s3 - s2) * next_left * dt as f32
+            }
+        }
+    }
+}
+
+/// Which property of its target an `AnimationCurveNode` drives.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AnimatedProperty {
+    Translation,
+    /// Euler angles in degrees, in the `RotationOrder` of the model.
+    Rotation,
+    Scaling,
+    /// Anything else, like the `DeformPercent` of a blend shape channel.
+    Other(String),
+}
+impl AnimatedProperty {
+    pub fn from_fbx(name: &str) -> Self {
+        match name {
+            "Lcl Translation" | "Translation" => Self::Translation,
+            "Lcl Rotation" | "Rotation" => Self::Rotation,
+            "Lcl Scaling" | "Scaling" => Self::Scaling,
+            other => Self::Other(other.into()),
+        }
+    }
+}
+
+/// One channel of a curve node. `default` is what the channel is when it has no curve.
+#[derive(Debug, Clone)]
+pub struct CurveChannel {
+    /// Name without the `d|` prefix, `X`, `Y`, `Z` or things like `DeformPercent`.
+    pub name: String,
+    pub default: f32,
+    pub curve: Option<AnimationCurve>,
+}
+impl CurveChannel {
+    pub fn evaluate(&self, time: f64) -> f32 {
+        self.curve.as_ref().map(|curve| curve.evaluate(time)).unwrap_or(self.default)
+    }
+}
+
+/// # AnimationCurveNode
+///
+/// Groups the curves that animate one property of one object, for example the
+/// x, y and z curves of `Lcl Translation`. `target` is the index of the animated model in
+/// the `SceneGraph`, it is `None` when the target isnt a model (blend shape channels and such),
+/// `target_id` always has the FBX id of whatever is animated.
+#[derive(Debug, Clone)]
+pub struct AnimationCurveNode {
+    pub tag: String,
+    pub property: AnimatedProperty,
+    pub target: Option<usize>,
+    pub target_id: i64,
+    pub channels: Vec<CurveChannel>,
+}
+impl AnimationCurveNode {
+    pub(crate) fn parse(node: &NodeHandle) -> Result<(i64, AnimationCurveNode), ModelLoadError> {
+        let collection_id = node.attr_i64(0)?;
+        let mut curve_node = AnimationCurveNode {
+            tag: node.attr_str(1)?.into(),
+            property: AnimatedProperty::Other(String::new()),
+            target: None,
+            target_id: 0,
+            channels: vec![],
+        };
+        for child in node.children_by_name("Properties70") {
+            for property in child.children() {
+                if let Some(name) = property.attr_str(0)?.strip_prefix("d|") {
+                    curve_node.channels.push(CurveChannel {
+                        name: name.into(),
+                        default: property.attr_f64(4)? as f32,
+                        curve: None,
+                    });
+                }
+            }
+        }
+        Ok((collection_id, curve_node))
+    }
+    pub fn channel(&self, name: &str) -> Option<&CurveChannel> {
+        self.channels.iter().find(|channel| channel.name == name)
+    }
+    /// Value of one channel at `time` seconds, `None` if the node has no such channel.
+    pub fn evaluate(&self, name: &str, time: f64) -> Option<f32> {
+        self.channel(name).map(|channel| channel.evaluate(time))
+    }
+    /// The `X`, `Y` and `Z` channels at `time` seconds. Missing ones come from `fallback`.
+    pub fn evaluate_fvec3(&self, time: f64, fallback: FVec3) -> FVec3 {
+        FVec3::new(
+            self.evaluate("X", time).unwrap_or(fallback.x),
+            self.evaluate("Y", time).unwrap_or(fallback.y),
+            self.evaluate("Z", time).unwrap_or(fallback.z),
+        )
+    }
+    fn channel_mut(&mut self, name: &str) -> &mut CurveChannel {
+        let index = match self.channels.iter().position(|channel| channel.name == name) {
+            Some(index) => index,
+            None => {
+                self.channels.push(CurveChannel { name: name.into(), default: 0.0, curve: None });
+                self.channels.len() - 1
+            }
+        };
+        &mut self.channels[index]
+    }
+}
+
+/// # AnimationLayer
+///
+/// The curve nodes of one layer of a stack. `weight` goes from 0 to 1, layers get
+/// blended over the ones before them by it. Additive layers are treated the same way.
+#[derive(Debug, Clone)]
+pub struct AnimationLayer {
+    pub tag: String,
+    pub weight: f32,
+    pub mute: bool,
+    pub curve_nodes: Vec<AnimationCurveNode>,
+}
+impl AnimationLayer {
+    pub(crate) fn parse(node: &NodeHandle) -> Result<(i64, AnimationLayer), ModelLoadError> {
+        let collection_id = node.attr_i64(0)?;
+        let mut layer = AnimationLayer {
+            tag: node.attr_str(1)?.into(),
+            weight: 1.0,
+            mute: false,
+            curve_nodes: vec![],
+        };
+        for child in node.children_by_name("Properties70") {
+            for property in child.children() {
+                match property.attr_str(0)? {
+                    // Stored in percent.
+                    "Weight" => layer.weight = property.attr_f64(4)? as f32 / 100.0,
+                    "Mute" => layer.mute = property.attr_i64(4)? != 0,
+                    _ => {}
+                }
+            }
+        }
+        Ok((collection_id, layer))
+    }
+}
+
+/// # AnimationStack
+///
+/// One take/clip of the file. `start` and `stop` are in seconds on the timeline of the
+/// file, the `sample` functions take time relative to `start` instead so the first
+/// frame of every clip is at 0.
+#[derive(Debug, Clone)]
+pub struct AnimationStack {
+    pub tag: String,
+    pub start: f64,
+    pub stop: f64,
+    pub layers: Vec<AnimationLayer>,
+}
+impl AnimationStack {
+    pub(crate) fn parse(node: &NodeHandle) -> Result<(i64, AnimationStack), ModelLoadError> {
+        let collection_id = node.attr_i64(0)?;
+        let mut stack = AnimationStack {
+            tag: node.attr_str(1)?.into(),
+            start: 0.0,
+            stop: 0.0,
+            layers: vec![],
+        };
+        for child in node.children_by_name("Properties70") {
+            for property in child.children() {
+                match property.attr_str(0)? {
+                    "LocalStart" => stack.start = ticks_to_seconds(property.attr_i64(4)?),
+                    "LocalStop" => stack.stop = ticks_to_seconds(property.attr_i64(4)?),
+                    _ => {}
+                }
+            }
+        }
+        Ok((collection_id, stack))
+    }
+    /// Length of the clip in seconds.
+    pub fn duration(&self) -> f64 {
+        (self.stop - self.start).max(0.0)
+    }
+    /// Some exporters leave `LocalStart` and `LocalStop` at 0, so the range gets taken
+    /// from the keys in that case.
+    fn fit_range_to_keys(&mut self) {
+        if self.stop > self.start {
+            return;
+        }
+        let ranges = self.curve_nodes().flat_map(|node| node.channels.iter()).filter_map(|channel| channel.curve.as_ref()?.range());
+        let (start, stop) = ranges.fold((f64::MAX, f64::MIN), |(start, stop), (first, last)| (start.min(first), stop.max(last)));
+        if start <= stop {
+            self.start = start;
+            self.stop = stop;
+        }
+    }
+    /// Every curve node of every layer that isnt muted.
+    pub fn curve_nodes(&self) -> impl Iterator<Item = &AnimationCurveNode> {
+        self.layers.iter().filter(|layer| !layer.mute).flat_map(|layer| layer.curve_nodes.iter())
+    }
+    /// # sample_model_transforms
+    ///
+    /// The `ModelTransform` of every node of `graph` at `time` seconds into the clip.
+    /// Nodes that arent animated keep the transform they have in the graph.
+    pub fn sample_model_transforms(&self, graph: &SceneGraph, time: f64) -> Vec<ModelTransform> {
+        let time = self.start + time;
+        let mut transforms: Vec<ModelTransform> = graph.nodes.iter().map(|node| node.transform).collect();
+        for layer in self.layers.iter().filter(|layer| !layer.mute) {
+            for curve_node in layer.curve_nodes.iter() {
+                let Some(target) = curve_node.target else {
+                    continue;
+                };
+                let transform = &mut transforms[target];
+                let value = match curve_node.property {
+                    AnimatedProperty::Translation => &mut transform.translation,
+                    AnimatedProperty::Rotation => &mut transform.rotation,
+                    AnimatedProperty::Scaling => &mut transform.scaling,
+                    AnimatedProperty::Other(_) => continue,
+                };
+                let sampled = curve_node.evaluate_fvec3(time, *value);
+                *value = *value + (sampled - *value) * layer.weight;
+            }
+        }
+        transforms
+    }
+    /// # sample
+    ///
+    /// Local transform of every node of `graph` at `time` seconds into the clip, in the
+    /// same space as `StandardModelData::transform`.
+    pub fn sample(&self, graph: &SceneGraph, time: f64) -> Vec<TransformQuaternion3D> {
+        let inverse = affine_inverse(&graph.conversion);
+        self.sample_model_transforms(graph, time).iter()
+            .map(|transform| transform::decompose(&(graph.conversion * transform.local_matrix() * inverse)))
+            .collect()
+    }
+    /// Local transform of one node at `time` seconds into the clip.
+    pub fn sample_node(&self, graph: &SceneGraph, node: usize, time: f64) -> TransformQuaternion3D {
+        let local = self.sample_model_transforms(graph, time)[node].local_matrix();
+        transform::decompose(&(graph.conversion * local * affine_inverse(&graph.conversion)))
+    }
+    /// `DeformPercent` (0 to 100) of the blend shape channel with FBX id `channel` at
+    /// `time` seconds into the clip, `None` when no layer animates it.
+    pub fn sample_deform_percent(&self, channel: i64, time: f64) -> Option<f32> {
+        let time = self.start + time;
+        let mut percent: Option<f32> = None;
+        for layer in self.layers.iter().filter(|layer| !layer.mute) {
+            for curve_node in layer.curve_nodes.iter().filter(|node| node.target_id == channel) {
+                let Some(sampled) = curve_node.evaluate("DeformPercent", time) else {
+                    continue;
+                };
+                percent = Some(match percent {
+                    Some(current) => current + (sampled - current) * layer.weight,
+                    None => sampled,
+                });
+            }
+        }
+        percent
+    }
+    /// Poses `graph` at `time` seconds into the clip and updates the world transforms.
+    /// This overwrites the transforms in the graph, keep a copy around to go back to the rest pose.
+    pub fn apply(&self, graph: &mut SceneGraph, time: f64) {
+        let transforms = self.sample_model_transforms(graph, time);
+        for (node, transform) in graph.nodes.iter_mut().zip(transforms) {
+            node.transform = transform;
+        }
+        graph.update_world();
+    }
+}
+
+/// Everything animation related `parse_objects` finds, waiting to be linked.
+/// `stack_order` keeps the stacks in the order the file lists them.
+#[derive(Default)]
+pub(crate) struct AnimationObjects {
+    stacks: HashMap<i64, AnimationStack>,
+    stack_order: Vec<i64>,
+    layers: HashMap<i64, AnimationLayer>,
+    curve_nodes: HashMap<i64, AnimationCurveNode>,
+    curves: HashMap<i64, AnimationCurve>,
+}
+impl AnimationObjects {
+    /// Parses `node` if it is one of the animation objects, returns false otherwise.
+    pub(crate) fn parse(&mut self, node: &NodeHandle) -> Result<bool, ModelLoadError> {
+        match node.name() {
+            "AnimationStack" => {
+                let (id, stack) = AnimationStack::parse(node)?;
+                if self.stacks.insert(id, stack).is_none() {
+                    self.stack_order.push(id);
+                }
+            }
+            "AnimationLayer" => {
+                let (id, layer) = AnimationLayer::parse(node)?;
+                self.layers.insert(id, layer);
+            }
+            "AnimationCurveNode" => {
+                let (id, curve_node) = AnimationCurveNode::parse(node)?;
+                self.curve_nodes.insert(id, curve_node);
+            }
+            "AnimationCurve" => {
+                let (id, curve) = AnimationCurve::parse(node)?;
+                self.curves.insert(id, curve);
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+    /// # link
+    ///
+    /// Animation goes curve -> curve node -> layer -> stack in `Connections`, and every
+    /// curve node is also OP connected to the property it drives. `lookup` maps model ids
+    /// to their index in the `SceneGraph`.
+    pub(crate) fn link(mut self, connections: &[Connection], lookup: &HashMap<i64, usize>) -> Vec<AnimationStack> {
+        for connection in connections.iter() {
+            if let Some(curve_node) = self.curve_nodes.get_mut(&connection.parent) {
+                if let (Some(curve), Some(property)) = (self.curves.get(&connection.child), &connection.property) {
+                    let name = property.strip_prefix("d|").unwrap_or(property);
+                    curve_node.channel_mut(name).curve = Some(curve.clone());
+                }
+            } else if let Some(curve_node) = self.curve_nodes.get_mut(&connection.child) {
+                if let Some(property) = &connection.property {
+                    curve_node.property = AnimatedProperty::from_fbx(property);
+                    curve_node.target_id = connection.parent;
+                    curve_node.target = lookup.get(&connection.parent).copied();
+                }
+            }
+        }
+        for connection in connections.iter() {
+            if !self.layers.contains_key(&connection.parent) || connection.property.is_some() {
+                continue;
+            }
+            if let Some(curve_node) = self.curve_nodes.remove(&connection.child) {
+                self.layers.get_mut(&connection.parent).unwrap().curve_nodes.push(curve_node);
+            }
+        }
+        for connection in connections.iter() {
+            if !self.stacks.contains_key(&connection.parent) {
+                continue;
+            }
+            if let Some(layer) = self.layers.remove(&connection.child) {
+                self.stacks.get_mut(&connection.parent).unwrap().layers.push(layer);
+            }
+        }
+        self.stack_order.iter().filter_map(|id| self.stacks.remove(id)).map(|mut stack| {
+            stack.fit_range_to_keys();
+            stack
+        }).collect()
+    }
+}
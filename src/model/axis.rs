@@ -0,0 +1,141 @@
+#![allow(unused)]
+use drowsed_math::{FMat4, FVec3, SquareMatrix};
+use fbxcel_dom::fbxcel::tree::v7400::NodeHandle;
+
+use super::error::ModelLoadError;
+use super::model_loader::NodeAttributes;
+use super::transform::{affine_inverse, scaling_matrix};
+
+/// A signed coordinate axis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    PositiveX,
+    NegativeX,
+    PositiveY,
+    NegativeY,
+    PositiveZ,
+    NegativeZ,
+}
+impl Axis {
+    pub fn vector(self) -> FVec3 {
+        match self {
+            Axis::PositiveX => FVec3::new(1.0, 0.0, 0.0),
+            Axis::NegativeX => FVec3::new(-1.0, 0.0, 0.0),
+            Axis::PositiveY => FVec3::new(0.0, 1.0, 0.0),
+            Axis::NegativeY => FVec3::new(0.0, -1.0, 0.0),
+            Axis::PositiveZ => FVec3::new(0.0, 0.0, 1.0),
+            Axis::NegativeZ => FVec3::new(0.0, 0.0, -1.0),
+        }
+    }
+    /// FBX stores an axis as an index (0 = x, 1 = y, 2 = z) and a sign of 1 or -1.
+    pub fn from_fbx(axis: i64, sign: i64) -> Option<Self> {
+        let positive = sign >= 0;
+        match (axis, positive) {
+            (0, true) => Some(Axis::PositiveX),
+            (0, false) => Some(Axis::NegativeX),
+            (1, true) => Some(Axis::PositiveY),
+            (1, false) => Some(Axis::NegativeY),
+            (2, true) => Some(Axis::PositiveZ),
+            (2, false) => Some(Axis::NegativeZ),
+            _ => None,
+        }
+    }
+    /// The other way around, axis index and sign.
+    pub fn to_fbx(self) -> (i32, i32) {
+        match self {
+            Axis::PositiveX => (0, 1),
+            Axis::NegativeX => (0, -1),
+            Axis::PositiveY => (1, 1),
+            Axis::NegativeY => (1, -1),
+            Axis::PositiveZ => (2, 1),
+            Axis::NegativeZ => (2, -1),
+        }
+    }
+}
+
+/// # AxisSystem
+///
+/// Which way is right, up and towards the viewer in a coordinate system, and how
+/// many meters one unit is. Models get converted from the system of the file into
+/// the one in `LoadOptions::target_axes`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AxisSystem {
+    pub right: Axis,
+    pub up: Axis,
+    /// Points from the scene towards someone looking at it from the front.
+    pub front: Axis,
+    /// Meters per unit.
+    pub unit_scale: f32,
+}
+impl AxisSystem {
+    /// What the engine uses: y up, meters, and the camera looks down +z like
+    /// `Camera::set_perspective_projection` expects, so the front is -z.
+    pub const ENGINE: Self = Self {
+        right: Axis::PositiveX,
+        up: Axis::PositiveY,
+        front: Axis::NegativeZ,
+        unit_scale: 1.0,
+    };
+    /// What FBX assumes when a file has no `GlobalSettings`, Maya style y up in centimeters.
+    pub const FBX_DEFAULT: Self = Self {
+        right: Axis::PositiveX,
+        up: Axis::PositiveY,
+        front: Axis::PositiveZ,
+        unit_scale: 0.01,
+    };
+    /// Reads `UpAxis`, `FrontAxis`, `CoordAxis` (with their signs) and `UnitScaleFactor`
+    /// from a `GlobalSettings` node. Anything missing keeps the FBX default.
+    pub fn from_global_settings(node: &NodeHandle) -> Result<Self, ModelLoadError> {
+        let mut axes = [(1, 1), (2, 1), (0, 1)];
+        let mut unit_scale_factor = 1.0;
+        for child in node.children() {
+            if child.name() != "Properties70" {
+                continue;
+            }
+            for property in child.children() {
+                match property.attr_str(0)? {
+                    "UpAxis" => axes[0].0 = property.attr_i64(4)?,
+                    "UpAxisSign" => axes[0].1 = property.attr_i64(4)?,
+                    "FrontAxis" => axes[1].0 = property.attr_i64(4)?,
+                    "FrontAxisSign" => axes[1].1 = property.attr_i64(4)?,
+                    "CoordAxis" => axes[2].0 = property.attr_i64(4)?,
+                    "CoordAxisSign" => axes[2].1 = property.attr_i64(4)?,
+                    "UnitScaleFactor" => unit_scale_factor = property.attr_f64(4)?,
+                    _ => {}
+                }
+            }
+        }
+        let axis = |(axis, sign): (i64, i64)| Axis::from_fbx(axis, sign).ok_or_else(|| ModelLoadError::IndexOutOfRange {
+            path: format!("{}/Properties70", node.path()),
+            index: axis as usize,
+            len: 3,
+        });
+        Ok(Self {
+            up: axis(axes[0])?,
+            front: axis(axes[1])?,
+            right: axis(axes[2])?,
+            // UnitScaleFactor is centimeters per unit.
+            unit_scale: unit_scale_factor as f32 / 100.0,
+        })
+    }
+    /// Matrix whose columns are the right, up and front axis.
+    fn basis(&self) -> FMat4 {
+        let mut m = FMat4::identity();
+        let (r, u, f) = (self.right.vector(), self.up.vector(), self.front.vector());
+        m.x.x = r.x;
+        m.x.y = r.y;
+        m.x.z = r.z;
+        m.y.x = u.x;
+        m.y.y = u.y;
+        m.y.z = u.z;
+        m.z.x = f.x;
+        m.z.y = f.y;
+        m.z.z = f.z;
+        m
+    }
+    /// Matrix that takes positions from `from` into `to`, units included.
+    pub fn conversion(from: &Self, to: &Self) -> FMat4 {
+        let scale = from.unit_scale / to.unit_scale;
+        scaling_matrix(FVec3::from(scale)) * to.basis() * affine_inverse(&from.basis())
+    }
+}
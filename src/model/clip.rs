@@ -0,0 +1,437 @@
+#![allow(unused)]
+use drowsed_math::{FMat4, FVec3, SquareMatrix, TransformQuaternion3D, complex::quaternion::Quaternion};
+
+use super::animation::AnimationStack;
+use super::scene::SceneGraph;
+use super::skin::Skin;
+use super::transform::{self, quaternion_dot, quaternion_normalize, slerp};
+use crate::vk_obj::buffer::raw::Buffer;
+
+/// How a track gets from one key to the next.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Holds the value of a key until the next one.
+    Step,
+    /// Lerp for vectors, slerp for rotations.
+    #[default]
+    Linear,
+    /// Hermite spline through the keys using `in_tangents` and `out_tangents`,
+    /// works the same as glTF's `CUBICSPLINE`.
+    Cubic,
+}
+
+/// What happens when a clip gets sampled past its end.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WrapMode {
+    /// Starts over from the beginning.
+    #[default]
+    Loop,
+    /// Holds the first and last frame.
+    Clamp,
+}
+impl WrapMode {
+    /// Puts `time` into `0..=duration`.
+    pub fn apply(self, time: f32, duration: f32) -> f32 {
+        if duration <= 0.0 {
+            return 0.0;
+        }
+        match self {
+            WrapMode::Loop => time.rem_euclid(duration),
+            WrapMode::Clamp => time.clamp(0.0, duration),
+        }
+    }
+}
+
+/// Values a `Keyframes` can hold.
+pub trait Keyframe: Copy {
+    fn lerp(a: Self, b: Self, t: f32) -> Self;
+    /// Multiplies every component, used to turn tangents per second into tangents per key.
+    fn scale(self, s: f32) -> Self;
+    /// Cubic hermite between `a` and `b`. The tangents are already scaled by the time between the keys.
+    fn hermite(a: Self, a_out: Self, b: Self, b_in: Self, t: f32) -> Self;
+}
+fn hermite_weights(t: f32) -> [f32; 4] {
+    let (t2, t3) = (t * t, t * t * t);
+    [2.0 * t3 - 3.0 * t2 + 1.0, t3 - 2.0 * t2 + t, -2.0 * t3 + 3.0 * t2, t3 - t2]
+}
+impl Keyframe for FVec3 {
+    fn lerp(a: Self, b: Self, t: f32) -> Self {
+        a + (b - a) * t
+    }
+    fn scale(self, s: f32) -> Self {
+        self * s
+    }
+    fn hermite(a: Self, a_out: Self, b: Self, b_in: Self, t: f32) -> Self {
+        let [h0, h1, h2, h3] = hermite_weights(t);
+        a * h0 + a_out * h1 + b * h2 + b_in * h3
+    }
+}
+impl Keyframe for Quaternion {
+    fn lerp(a: Self, b: Self, t: f32) -> Self {
+        slerp(a, b, t)
+    }
+    fn scale(self, s: f32) -> Self {
+        Quaternion { x: self.x * s, y: self.y * s, z: self.z * s, w: self.w * s }
+    }
+    fn hermite(a: Self, a_out: Self, b: Self, b_in: Self, t: f32) -> Self {
+        let [h0, h1, h2, h3] = hermite_weights(t);
+        quaternion_normalize(Quaternion {
+            x: a.x * h0 + a_out.x * h1 + b.x * h2 + b_in.x * h3,
+            y: a.y * h0 + a_out.y * h1 + b.y * h2 + b_in.y * h3,
+            z: a.z * h0 + a_out.z * h1 + b.z * h2 + b_in.z * h3,
+            w: a.w * h0 + a_out.w * h1 + b.w * h2 + b_in.w * h3,
+        })
+    }
+}
+
+/// # Keyframes
+///
+/// Keys of one animated value. `times` are in seconds and sorted. The tangents are
+/// only read by `Interpolation::Cubic` and have one entry per key when it is used,
+/// they are in units per second.
+#[derive(Debug, Clone)]
+pub struct Keyframes<T: Keyframe> {
+    pub interpolation: Interpolation,
+    pub times: Vec<f32>,
+    pub values: Vec<T>,
+    pub in_tangents: Vec<T>,
+    pub out_tangents: Vec<T>,
+}
+impl<T: Keyframe> Keyframes<T> {
+    pub fn new(interpolation: Interpolation, times: Vec<f32>, values: Vec<T>) -> Self {
+        Self { interpolation, times, values, in_tangents: vec![], out_tangents: vec![] }
+    }
+    /// Time of the last key.
+    pub fn end(&self) -> f32 {
+        self.times.last().copied().unwrap_or(0.0)
+    }
+    /// Value at `time` seconds, holds the first and last key outside of the range of the keys.
+    /// `None` when there are no keys.
+    pub fn sample(&self, time: f32) -> Option<T> {
+        let first = *self.values.first()?;
+        let count = self.times.len().min(self.values.len());
+        if count <= 1 || time <= self.times[0] {
+            return Some(first);
+        }
+        let next = self.times[..count].partition_point(|t| *t <= time);
+        if next >= count {
+            return Some(self.values[count - 1]);
+        }
+        let key = next - 1;
+        let dt = self.times[next] - self.times[key];
+        if dt <= 0.0 {
+            return Some(self.values[next]);
+        }
+        let t = (time - self.times[key]) / dt;
+        let (a, b) = (self.values[key], self.values[next]);
+        Some(match self.interpolation {
+            Interpolation::Step => a,
+            Interpolation::Linear => T::lerp(a, b, t),
+            Interpolation::Cubic => match (self.out_tangents.get(key), self.in_tangents.get(next)) {
+                (Some(a_out), Some(b_in)) => T::hermite(a, a_out.scale(dt), b, b_in.scale(dt), t),
+                _ => T::lerp(a, b, t),
+            },
+        })
+    }
+}
+
+/// # Track
+///
+/// Animates one node of a `SceneGraph`. Parts without keys keep whatever
+/// the pose had before sampling.
+#[derive(Debug, Clone)]
+pub struct Track {
+    pub node: usize,
+    pub translation: Option<Keyframes<FVec3>>,
+    pub rotation: Option<Keyframes<Quaternion>>,
+    pub scale: Option<Keyframes<FVec3>>,
+}
+impl Track {
+    pub fn sample_into(&self, time: f32, transform: &mut TransformQuaternion3D) {
+        if let Some(translation) = self.translation.as_ref().and_then(|keys| keys.sample(time)) {
+            transform.translation = translation;
+        }
+        if let Some(rotation) = self.rotation.as_ref().and_then(|keys| keys.sample(time)) {
+            transform.rotation = rotation;
+        }
+        if let Some(scale) = self.scale.as_ref().and_then(|keys| keys.sample(time)) {
+            transform.scale = scale;
+        }
+    }
+    fn end(&self) -> f32 {
+        let translation = self.translation.as_ref().map(|keys| keys.end()).unwrap_or(0.0);
+        let rotation = self.rotation.as_ref().map(|keys| keys.end()).unwrap_or(0.0);
+        let scale = self.scale.as_ref().map(|keys| keys.end()).unwrap_or(0.0);
+        translation.max(rotation).max(scale)
+    }
+}
+
+/// # AnimationClip
+///
+/// Keyframed local transforms for the nodes of a `SceneGraph`, the runtime side of
+/// animation. A pose is one `TransformQuaternion3D` per node, in the same space as
+/// `StandardModelData::transform`.
+#[derive(Debug, Clone, Default)]
+pub struct AnimationClip {
+    pub name: String,
+    /// Seconds.
+    pub duration: f32,
+    pub tracks: Vec<Track>,
+}
+impl AnimationClip {
+    pub fn new(name: &str, tracks: Vec<Track>) -> Self {
+        let duration = tracks.iter().map(|track| track.end()).fold(0.0, f32::max);
+        Self { name: name.into(), duration, tracks }
+    }
+    /// # from_stack
+    ///
+    /// Bakes an FBX `AnimationStack` into linear keys, `frame_rate` of them per second.
+    /// FBX animates euler angles with pivots and pre rotations so resampling is
+    /// the only way to get quaternion keys that look the same.
+    pub fn from_stack(stack: &AnimationStack, graph: &SceneGraph, frame_rate: f32) -> Self {
+        let mut animated: Vec<usize> = stack.curve_nodes().filter_map(|node| node.target).collect();
+        animated.sort_unstable();
+        animated.dedup();
+
+        let duration = stack.duration() as f32;
+        let frames = (duration * frame_rate.max(1.0)).ceil().max(1.0) as usize;
+        let times: Vec<f32> = (0..=frames).map(|frame| duration * frame as f32 / frames as f32).collect();
+        let mut translations = vec![Vec::with_capacity(times.len()); animated.len()];
+        let mut rotations: Vec<Vec<Quaternion>> = vec![Vec::with_capacity(times.len()); animated.len()];
+        let mut scales = vec![Vec::with_capacity(times.len()); animated.len()];
+        for time in times.iter() {
+            let pose = stack.sample(graph, *time as f64);
+            for (track, node) in animated.iter().enumerate() {
+                let transform = pose[*node];
+                // Neighbouring keys in opposite hemispheres would make slerp go the long way around.
+                let rotation = match rotations[track].last() {
+                    Some(previous) if quaternion_dot(*previous, transform.rotation) < 0.0 => Keyframe::scale(transform.rotation, -1.0),
+                    _ => transform.rotation,
+                };
+                translations[track].push(transform.translation);
+                rotations[track].push(rotation);
+                scales[track].push(transform.scale);
+            }
+        }
+        let tracks = animated.iter().enumerate().map(|(track, node)| Track {
+            node: *node,
+            translation: Some(Keyframes::new(Interpolation::Linear, times.clone(), std::mem::take(&mut translations[track]))),
+            rotation: Some(Keyframes::new(Interpolation::Linear, times.clone(), std::mem::take(&mut rotations[track]))),
+            scale: Some(Keyframes::new(Interpolation::Linear, times.clone(), std::mem::take(&mut scales[track]))),
+        }).collect();
+        Self { name: stack.tag.clone(), duration, tracks }
+    }
+    /// Writes the animated nodes of `pose` at `time` seconds, everything else is left alone.
+    /// Start `pose` off as the rest pose, for example from `rest_pose`.
+    pub fn sample_into(&self, time: f32, wrap: WrapMode, pose: &mut [TransformQuaternion3D]) {
+        let time = wrap.apply(time, self.duration);
+        for track in self.tracks.iter() {
+            if let Some(transform) = pose.get_mut(track.node) {
+                track.sample_into(time, transform);
+            }
+        }
+    }
+    pub fn sample(&self, time: f32, wrap: WrapMode, rest: &[TransformQuaternion3D]) -> Vec<TransformQuaternion3D> {
+        let mut pose = rest.to_vec();
+        self.sample_into(time, wrap, &mut pose);
+        pose
+    }
+}
+
+/// Local transform of every node of `graph` as it is in the file.
+pub fn rest_pose(graph: &SceneGraph) -> Vec<TransformQuaternion3D> {
+    (0..graph.nodes.len()).map(|node| transform::decompose(&graph.local_matrix(node))).collect()
+}
+/// Lerps translation and scale and slerps rotation, `weight` 0 is all `a` and 1 all `b`.
+pub fn blend_transform(a: &TransformQuaternion3D, b: &TransformQuaternion3D, weight: f32) -> TransformQuaternion3D {
+    TransformQuaternion3D {
+        translation: <FVec3 as Keyframe>::lerp(a.translation, b.translation, weight),
+        rotation: slerp(a.rotation, b.rotation, weight),
+        scale: <FVec3 as Keyframe>::lerp(a.scale, b.scale, weight),
+    }
+}
+/// Blends two poses node by node into `out`, for crossfading one clip into another.
+pub fn blend_poses(a: &[TransformQuaternion3D], b: &[TransformQuaternion3D], weight: f32, out: &mut Vec<TransformQuaternion3D>) {
+    out.clear();
+    out.extend(a.iter().zip(b.iter()).map(|(a, b)| blend_transform(a, b, weight)));
+}
+/// World matrix of every node for a pose, parents are always done before their children.
+pub fn world_matrices(graph: &SceneGraph, pose: &[TransformQuaternion3D]) -> Vec<FMat4> {
+    let mut world = vec![FMat4::identity(); graph.nodes.len()];
+    let mut stack: Vec<(usize, FMat4)> = graph.roots.iter().map(|root| (*root, FMat4::identity())).collect();
+    while let Some((node, parent_world)) = stack.pop() {
+        world[node] = parent_world * transform::transform_matrix(&pose[node]);
+        for child in graph.nodes[node].children.iter() {
+            stack.push((*child, world[node]));
+        }
+    }
+    world
+}
+
+/// # SkinningPalette
+///
+/// One matrix per joint of a `Skin`, `world of the bone * inverse bind`. The vertices of a
+/// skinned model times their palette matrices end up in world space, so the shader doesnt
+/// need the model matrix on top. `FMat4` is column major like GLSL wants it, the matrices
+/// can go straight into a `std430` storage buffer.
+#[derive(Debug, Clone, Default)]
+pub struct SkinningPalette {
+    pub matrices: Vec<FMat4>,
+}
+impl SkinningPalette {
+    pub fn new(skin: &Skin, world: &[FMat4]) -> Self {
+        let mut palette = Self::default();
+        palette.update(skin, world);
+        palette
+    }
+    /// Recomputes the matrices from the output of `world_matrices`, reuses the allocation.
+    pub fn update(&mut self, skin: &Skin, world: &[FMat4]) {
+        self.matrices.clear();
+        self.matrices.extend(skin.joints.iter().map(|joint| world[joint.node] * joint.inverse_bind));
+    }
+    pub fn size_in_bytes(&self) -> usize {
+        self.matrices.len() * std::mem::size_of::<FMat4>()
+    }
+    /// Copies the palette into a mapped buffer, for example one made with
+    /// `Buffer::from_vec(device, vk::BufferUsageFlags::STORAGE_BUFFER, HOST_VISIBLE | HOST_COHERENT, &palette.matrices)`.
+    /// Panics when the palette has grown past what the buffer was made for.
+    pub fn upload(&self, buffer: &mut Buffer<FMat4>) {
+        buffer.write(&self.matrices);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::scene::ModelTransform;
+    use crate::model::skin::Joint;
+    use crate::model::transform::{affine_inverse, transform_point};
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+    fn assert_vec(a: FVec3, b: FVec3) {
+        assert!(close(a.x, b.x) && close(a.y, b.y) && close(a.z, b.z), "{:?} != {:?}", a, b);
+    }
+    fn identity() -> Quaternion {
+        Quaternion { x: 0.0, y: 0.0, z: 0.0, w: 1.0 }
+    }
+    /// `degrees` around the y axis.
+    fn around_y(degrees: f32) -> Quaternion {
+        let half = degrees.to_radians() * 0.5;
+        Quaternion { x: 0.0, y: half.sin(), z: 0.0, w: half.cos() }
+    }
+    fn transform(translation: FVec3) -> TransformQuaternion3D {
+        TransformQuaternion3D { translation, rotation: identity(), scale: FVec3::from(1.0) }
+    }
+    fn keys(interpolation: Interpolation) -> Keyframes<FVec3> {
+        Keyframes::new(interpolation, vec![0.0, 1.0], vec![FVec3::new(0.0, 0.0, 0.0), FVec3::new(2.0, 0.0, 0.0)])
+    }
+
+    #[test]
+    fn step_holds_the_previous_key() {
+        let keys = keys(Interpolation::Step);
+        assert_vec(keys.sample(0.0).unwrap(), FVec3::new(0.0, 0.0, 0.0));
+        assert_vec(keys.sample(0.99).unwrap(), FVec3::new(0.0, 0.0, 0.0));
+        assert_vec(keys.sample(1.0).unwrap(), FVec3::new(2.0, 0.0, 0.0));
+    }
+    #[test]
+    fn linear_interpolates_and_holds_outside_the_keys() {
+        let keys = keys(Interpolation::Linear);
+        assert_vec(keys.sample(0.25).unwrap(), FVec3::new(0.5, 0.0, 0.0));
+        assert_vec(keys.sample(-1.0).unwrap(), FVec3::new(0.0, 0.0, 0.0));
+        assert_vec(keys.sample(5.0).unwrap(), FVec3::new(2.0, 0.0, 0.0));
+        assert!(Keyframes::<FVec3>::new(Interpolation::Linear, vec![], vec![]).sample(0.0).is_none());
+    }
+    #[test]
+    fn cubic_uses_the_tangents() {
+        // flat tangents ease in and out
+        let mut keys = keys(Interpolation::Cubic);
+        keys.in_tangents = vec![FVec3::from(0.0); 2];
+        keys.out_tangents = vec![FVec3::from(0.0); 2];
+        assert_vec(keys.sample(0.25).unwrap(), FVec3::new(0.3125, 0.0, 0.0));
+        assert_vec(keys.sample(0.5).unwrap(), FVec3::new(1.0, 0.0, 0.0));
+        // tangents matching the slope give a straight line
+        keys.in_tangents = vec![FVec3::new(2.0, 0.0, 0.0); 2];
+        keys.out_tangents = vec![FVec3::new(2.0, 0.0, 0.0); 2];
+        assert_vec(keys.sample(0.25).unwrap(), FVec3::new(0.5, 0.0, 0.0));
+    }
+    #[test]
+    fn rotations_slerp() {
+        let keys = Keyframes::new(Interpolation::Linear, vec![0.0, 1.0], vec![identity(), around_y(90.0)]);
+        let halfway = keys.sample(0.5).unwrap();
+        let expected = around_y(45.0);
+        assert!(close(quaternion_dot(halfway, expected).abs(), 1.0), "{:?}", halfway);
+    }
+    #[test]
+    fn slerp_takes_the_short_way() {
+        let flipped = around_y(90.0).scale(-1.0);
+        let halfway = slerp(identity(), flipped, 0.5);
+        assert!(close(quaternion_dot(halfway, around_y(45.0)).abs(), 1.0), "{:?}", halfway);
+    }
+    #[test]
+    fn wrap_modes() {
+        assert!(close(WrapMode::Loop.apply(2.5, 2.0), 0.5));
+        assert!(close(WrapMode::Loop.apply(-0.5, 2.0), 1.5));
+        assert!(close(WrapMode::Clamp.apply(2.5, 2.0), 2.0));
+        assert!(close(WrapMode::Clamp.apply(-0.5, 2.0), 0.0));
+        assert!(close(WrapMode::Loop.apply(3.0, 0.0), 0.0));
+
+        let clip = AnimationClip::new("move", vec![Track { node: 0, translation: Some(keys(Interpolation::Linear)), rotation: None, scale: None }]);
+        assert!(close(clip.duration, 1.0));
+        let rest = [transform(FVec3::new(0.0, 5.0, 0.0))];
+        let looped = clip.sample(1.25, WrapMode::Loop, &rest);
+        assert_vec(looped[0].translation, FVec3::new(0.5, 0.0, 0.0));
+        let clamped = clip.sample(1.25, WrapMode::Clamp, &rest);
+        assert_vec(clamped[0].translation, FVec3::new(2.0, 0.0, 0.0));
+    }
+    #[test]
+    fn blending_poses() {
+        let a = [transform(FVec3::new(0.0, 0.0, 0.0)), transform(FVec3::new(1.0, 0.0, 0.0))];
+        let mut b = [transform(FVec3::new(2.0, 0.0, 0.0)), transform(FVec3::new(1.0, 4.0, 0.0))];
+        b[1].rotation = around_y(90.0);
+        let mut out = vec![];
+        blend_poses(&a, &b, 0.5, &mut out);
+        assert_eq!(out.len(), 2);
+        assert_vec(out[0].translation, FVec3::new(1.0, 0.0, 0.0));
+        assert_vec(out[1].translation, FVec3::new(1.0, 2.0, 0.0));
+        assert!(close(quaternion_dot(out[1].rotation, around_y(45.0)).abs(), 1.0));
+    }
+    #[test]
+    fn palette_of_a_two_joint_chain() {
+        let graph = SceneGraph::build(
+            vec![(1, "root".into(), ModelTransform::default()), (2, "child".into(), ModelTransform::default())],
+            &[(2, 1)],
+            FMat4::identity(),
+        );
+        // bind pose: root at the origin, child one unit up
+        let bind = [transform(FVec3::new(0.0, 0.0, 0.0)), transform(FVec3::new(0.0, 1.0, 0.0))];
+        let bind_world = world_matrices(&graph, &bind);
+        let joints = (0..2).map(|node| Joint {
+            tag: graph.nodes[node].tag.clone(),
+            node,
+            parent: graph.nodes[node].parent,
+            bind_pose: bind_world[node],
+            inverse_bind: affine_inverse(&bind_world[node]),
+        }).collect();
+        let skin = Skin { joints, joint_indices: vec![], joint_weights: vec![] };
+
+        // the bind pose itself doesnt move anything
+        let palette = SkinningPalette::new(&skin, &bind_world);
+        assert_eq!(palette.matrices.len(), 2);
+        assert_eq!(palette.size_in_bytes(), 2 * std::mem::size_of::<FMat4>());
+        for matrix in palette.matrices.iter() {
+            assert_vec(transform_point(matrix, FVec3::new(0.3, 1.5, -2.0)), FVec3::new(0.3, 1.5, -2.0));
+        }
+
+        // turning the root 90 degrees around y carries the child with it
+        let mut pose = bind;
+        pose[0].rotation = around_y(90.0);
+        let world = world_matrices(&graph, &pose);
+        assert_vec(transform_point(&world[1], FVec3::from(0.0)), FVec3::new(0.0, 1.0, 0.0));
+        let palette = SkinningPalette::new(&skin, &world);
+        // a vertex sitting at the child bone, one unit in front of it
+        assert_vec(transform_point(&palette.matrices[1], FVec3::new(1.0, 1.0, 0.0)), FVec3::new(0.0, 1.0, -1.0));
+        assert_vec(transform_point(&palette.matrices[0], FVec3::new(1.0, 0.0, 0.0)), FVec3::new(0.0, 0.0, -1.0));
+    }
+}
@@ -0,0 +1,279 @@
+#![allow(unused)]
+//! Turning a loaded `StandardModelData` into one of the vertex types in `vertex.rs` so it
+//! can go into a `RenderBatchBuilder`. `to_mesh` is the only way there, every vertex type
+//! says which model attributes it reads and a model that doesnt have them, or has more
+//! vertices than the index type can reach, is an error instead of zeroes or a panic.
+use std::fmt;
+use std::marker::PhantomData;
+
+use drowsed_math::{FMat4, FVec2, FVec3, FVec4, Vector, EuclideanGeometry};
+
+use super::error::ModelLoadError;
+use super::model_loader::{StandardModelData, safe_normalize};
+use super::transform;
+use super::vertex::{Vertex2D, Vertex3D, Vertex3DRGB, Vertex3DTexture, Vertex3DNormalUV};
+use crate::vk_obj::rendering::mesh::{Mesh, SubMesh, Vertex, VulkanIndexable};
+
+/// Per vertex data a model may or may not have. Positions are always there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VertexAttribute {
+    Position,
+    Normal,
+    Uv,
+    Color,
+}
+impl fmt::Display for VertexAttribute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            VertexAttribute::Position => "positions",
+            VertexAttribute::Normal => "normals",
+            VertexAttribute::Uv => "uvs",
+            VertexAttribute::Color => "vertex colors",
+        })
+    }
+}
+
+/// Everything one vertex of a model has. Attributes the model is missing are left at
+/// zero, `FromModelVertex::from_model` only ever gets called when its `REQUIRED` ones are there.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ModelVertex {
+    pub position: FVec3,
+    pub normal: FVec3,
+    pub uv: FVec2,
+    pub color: FVec4,
+}
+
+/// # FromModelVertex
+///
+/// A vertex type that can be built from a model. Implement this for your own vertex
+/// layouts to use them with `StandardModelData::to_mesh`.
+pub trait FromModelVertex: Vertex {
+    const REQUIRED: &'static [VertexAttribute];
+    fn from_model(vertex: &ModelVertex) -> Self;
+}
+impl FromModelVertex for Vertex2D {
+    const REQUIRED: &'static [VertexAttribute] = &[VertexAttribute::Position];
+    /// z gets dropped, put the model in the xy plane first.
+    fn from_model(vertex: &ModelVertex) -> Self {
+        Self { coords: FVec2::new(vertex.position.x, vertex.position.y) }
+    }
+}
+impl FromModelVertex for Vertex3D {
+    const REQUIRED: &'static [VertexAttribute] = &[VertexAttribute::Position];
+    fn from_model(vertex: &ModelVertex) -> Self {
+        Self { coords: vertex.position }
+    }
+}
+impl FromModelVertex for Vertex3DRGB {
+    const REQUIRED: &'static [VertexAttribute] = &[VertexAttribute::Position, VertexAttribute::Color];
+    fn from_model(vertex: &ModelVertex) -> Self {
+        Self { coords: vertex.position, rgb: FVec3::new(vertex.color.x, vertex.color.y, vertex.color.z) }
+    }
+}
+impl FromModelVertex for Vertex3DTexture {
+    const REQUIRED: &'static [VertexAttribute] = &[VertexAttribute::Position, VertexAttribute::Uv];
+    fn from_model(vertex: &ModelVertex) -> Self {
+        Self { coords: vertex.position, text_coords: vertex.uv }
+    }
+}
+impl FromModelVertex for Vertex3DNormalUV {
+    const REQUIRED: &'static [VertexAttribute] = &[VertexAttribute::Position, VertexAttribute::Normal, VertexAttribute::Uv];
+    fn from_model(vertex: &ModelVertex) -> Self {
+        Self { pos: vertex.position, normal: vertex.normal, uv: vertex.uv }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ConvertOptions {
+    /// Bakes `StandardModelData::world` into the vertices so models from one file can share
+    /// a batch and still end up where they belong. Otherwise they stay in model space.
+    pub world_space: bool,
+    /// Which entry of `StandardModelData::uvs` goes into the uv of the vertex.
+    pub uv_set: usize,
+}
+impl Default for ConvertOptions {
+    fn default() -> Self {
+        Self { world_space: false, uv_set: 0 }
+    }
+}
+
+/// # ModelMesh
+///
+/// A model converted into `V` with its indices already in `I`, from `StandardModelData::to_mesh`.
+/// Submeshes are kept so materials still line up after it is pushed into a batch.
+#[derive(Debug, Clone)]
+pub struct ModelMesh<V: Vertex, I: VulkanIndexable> {
+    pub vertices: Vec<V>,
+    pub indices: Vec<I>,
+    pub submeshes: Vec<SubMesh>,
+}
+impl<V: Vertex, I: VulkanIndexable> Mesh<V, I> for ModelMesh<V, I> {
+    fn vertices(&self) -> Vec<V> {
+        self.vertices.clone()
+    }
+    fn indices(&self) -> Vec<I> {
+        self.indices.clone()
+    }
+    fn submeshes(&self) -> Vec<SubMesh> {
+        self.submeshes.clone()
+    }
+}
+
+impl StandardModelData {
+    /// Attributes `V` needs that this model doesnt have, empty when it can be converted.
+    pub fn missing_attributes<V: FromModelVertex>(&self, options: &ConvertOptions) -> Vec<VertexAttribute> {
+        let count = self.vertices.len();
+        V::REQUIRED.iter().copied().filter(|attribute| match attribute {
+            VertexAttribute::Position => false,
+            VertexAttribute::Normal => self.normals.len() != count,
+            VertexAttribute::Uv => self.uvs.get(options.uv_set).map_or(true, |set| set.uvs.len() != count),
+            VertexAttribute::Color => self.colors.len() != count,
+        }).collect()
+    }
+    /// # to_vertices
+    ///
+    /// Interleaves the model into `V`, or `ModelLoadError::MissingAttributes` listing
+    /// everything `V` wanted but didnt get.
+    pub fn to_vertices<V: FromModelVertex>(&self, options: &ConvertOptions) -> Result<Vec<V>, ModelLoadError> {
+        let missing = self.missing_attributes::<V>(options);
+        if !missing.is_empty() {
+            return Err(ModelLoadError::MissingAttributes { tag: self.tag.clone(), missing });
+        }
+        let normal_matrix = transform::normal_matrix(&self.world);
+        let uvs = self.uvs.get(options.uv_set);
+        Ok(self.vertices.iter().enumerate().map(|(i, position)| {
+            let mut vertex = ModelVertex {
+                position: *position,
+                normal: self.normals.get(i).copied().unwrap_or_default(),
+                uv: uvs.and_then(|set| set.uvs.get(i)).copied().unwrap_or_default(),
+                color: self.colors.get(i).copied().unwrap_or_default(),
+            };
+            if options.world_space {
+                vertex.position = transform::transform_point(&self.world, vertex.position);
+                vertex.normal = safe_normalize(transform::transform_vector(&normal_matrix, vertex.normal));
+            }
+            V::from_model(&vertex)
+        }).collect())
+    }
+    /// # to_mesh
+    ///
+    /// The model as a `Mesh` that can be pushed into a `RenderBatchBuilder`. Fails when `V`
+    /// needs attributes the model is missing or when there are more vertices than `I` can index.
+    /// A mirroring world transform also flips the triangles, same as `apply_matrix`.
+    pub fn to_mesh<V: FromModelVertex, I: VulkanIndexable>(&self, options: &ConvertOptions) -> Result<ModelMesh<V, I>, ModelLoadError> {
+        let vertices = self.to_vertices::<V>(options)?;
+        let overflow = || ModelLoadError::IndexOverflow {
+            tag: self.tag.clone(),
+            vertices: self.vertices.len(),
+            index_type: std::any::type_name::<I>(),
+        };
+        let mut indices = self.indices.iter().map(|index| I::from_u32(*index).ok_or_else(overflow)).collect::<Result<Vec<I>, _>>()?;
+        if options.world_space && transform::is_mirroring(&self.world) {
+            for triangle in indices.chunks_exact_mut(3) {
+                triangle.swap(1, 2);
+            }
+        }
+        Ok(ModelMesh { vertices, indices, submeshes: self.submeshes.clone() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::geometry::triangle_normal;
+    use crate::model::model_loader::UVSet;
+    use crate::vk_obj::rendering::batcher::RenderBatchBuilder;
+
+    /// One triangle in the yz plane, wound so it faces +x the same way its normals do.
+    fn triangle() -> StandardModelData {
+        StandardModelData {
+            tag: String::from("triangle"),
+            vertices: vec![FVec3::new(0.0, 0.0, 0.0), FVec3::new(0.0, 1.0, 0.0), FVec3::new(0.0, 0.0, 1.0)],
+            normals: vec![FVec3::new(1.0, 0.0, 0.0); 3],
+            uvs: vec![UVSet { name: String::from("UVMap"), uvs: vec![FVec2::new(0.0, 0.0), FVec2::new(1.0, 0.0), FVec2::new(0.0, 1.0)] }],
+            indices: vec![0, 1, 2],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn missing_attributes_are_listed() {
+        let mut model = triangle();
+        model.uvs.clear();
+        match model.to_mesh::<Vertex3DNormalUV, u32>(&ConvertOptions::default()) {
+            Err(ModelLoadError::MissingAttributes { tag, missing }) => {
+                assert_eq!(tag, "triangle");
+                assert_eq!(missing, vec![VertexAttribute::Uv]);
+            }
+            other => panic!("expected missing uvs, got {:?}", other.map(|mesh| mesh.indices)),
+        }
+        model.normals.clear();
+        assert_eq!(model.missing_attributes::<Vertex3DNormalUV>(&ConvertOptions::default()), vec![VertexAttribute::Normal, VertexAttribute::Uv]);
+        // a uv set that isnt there is missing too
+        let options = ConvertOptions { uv_set: 1, ..Default::default() };
+        assert_eq!(triangle().missing_attributes::<Vertex3DTexture>(&options), vec![VertexAttribute::Uv]);
+        assert!(model.missing_attributes::<Vertex3D>(&ConvertOptions::default()).is_empty());
+    }
+
+    #[test]
+    fn u16_overflow_is_an_error() {
+        let model = StandardModelData {
+            tag: String::from("big"),
+            vertices: vec![FVec3::from(0.0); 65537],
+            indices: vec![0, 65535, 65536],
+            ..Default::default()
+        };
+        match model.to_mesh::<Vertex3D, u16>(&ConvertOptions::default()) {
+            Err(ModelLoadError::IndexOverflow { tag, vertices, .. }) => {
+                assert_eq!(tag, "big");
+                assert_eq!(vertices, 65537);
+            }
+            other => panic!("expected an overflow, got {:?}", other.map(|mesh| mesh.indices)),
+        }
+        let mesh = model.to_mesh::<Vertex3D, u32>(&ConvertOptions::default()).unwrap();
+        assert_eq!(mesh.indices, vec![0, 65535, 65536]);
+        // 65536 vertices still fit, u16::MAX is the last one
+        let model = StandardModelData { vertices: vec![FVec3::from(0.0); 65536], indices: vec![0, 1, 65535], ..model };
+        assert_eq!(model.to_mesh::<Vertex3D, u16>(&ConvertOptions::default()).unwrap().indices, vec![0, 1, u16::MAX]);
+    }
+
+    #[test]
+    fn mirrored_world_flips_winding() {
+        let model = StandardModelData { world: transform::scaling_matrix(FVec3::new(-2.0, 1.0, 1.0)), ..triangle() };
+        let local = model.to_mesh::<Vertex3DNormalUV, u32>(&ConvertOptions::default()).unwrap();
+        assert_eq!(local.indices, vec![0, 1, 2]);
+        assert_eq!(local.vertices[0].normal, FVec3::new(1.0, 0.0, 0.0));
+
+        let world = model.to_mesh::<Vertex3DNormalUV, u32>(&ConvertOptions { world_space: true, ..Default::default() }).unwrap();
+        assert_eq!(world.indices, vec![0, 2, 1]);
+        let [a, b, c] = [0, 2, 1].map(|i| world.vertices[i]);
+        let face = triangle_normal(a.pos, b.pos, c.pos);
+        for vertex in world.vertices.iter() {
+            // the inverse transpose scales x by -0.5, it has to come back out at unit length
+            assert!((vertex.normal.x + 1.0).abs() < 1e-6 && vertex.normal.y == 0.0 && vertex.normal.z == 0.0, "{:?}", vertex.normal);
+            assert!(face.dot(&vertex.normal) > 0.0);
+        }
+    }
+
+    #[test]
+    fn submeshes_survive_the_batch() {
+        let model = StandardModelData {
+            vertices: vec![FVec3::from(0.0); 4],
+            indices: vec![0, 1, 2, 0, 2, 3],
+            submeshes: vec![
+                SubMesh { material: 0, first_index: 0, index_count: 3 },
+                SubMesh { material: 1, first_index: 3, index_count: 3 },
+            ],
+            ..Default::default()
+        };
+        let mesh = model.to_mesh::<Vertex3D, u32>(&ConvertOptions::default()).unwrap();
+        assert_eq!(mesh.submeshes, model.submeshes);
+        let builder = RenderBatchBuilder::new().push(&mesh).push(&mesh);
+        assert_eq!(builder.submeshes(), &[
+            SubMesh { material: 0, first_index: 0, index_count: 3 },
+            SubMesh { material: 1, first_index: 3, index_count: 3 },
+            SubMesh { material: 0, first_index: 6, index_count: 3 },
+            SubMesh { material: 1, first_index: 9, index_count: 3 },
+        ]);
+    }
+}
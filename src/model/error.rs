@@ -0,0 +1,111 @@
+use std::fmt;
+
+use super::convert::VertexAttribute;
+
+/// # ModelLoadError
+///
+/// Everything that can go wrong while turning a model file into `StandardModelData`.
+/// Most variants carry the path of the node that was being read (something like
+/// `Objects/Geometry/Vertices`) so you can actually find the broken part of the file.
+#[derive(Debug)]
+pub enum ModelLoadError {
+    /// The file could not be opened or read.
+    Io(std::io::Error),
+    /// fbxcel could not make sense of the document at all.
+    Document(fbxcel_dom::any::Error),
+    /// The document parsed but it isn't an FBX 7400 document.
+    UnsupportedVersion,
+    /// The text of an ASCII FBX file doesn't follow the format.
+    AsciiSyntax { line: usize, message: String },
+    /// An ASCII FBX file parsed fine but fbxcel couldn't turn it into a document.
+    AsciiDocument(String),
+    /// fbxcel failed while writing an FBX file.
+    FbxWrite(fbxcel::writer::v7400::binary::Error),
+    /// The gltf crate couldn't read the file or one of the buffers it points at.
+    Gltf(gltf::Error),
+    /// A line of an OBJ file doesn't make sense.
+    ObjSyntax { line: usize, message: String },
+    /// A mesh cache file is broken, from another version or was written for a different vertex layout.
+    InvalidCache(String),
+    /// The vertex type wants attributes the model doesnt have.
+    MissingAttributes { tag: String, missing: Vec<VertexAttribute> },
+    /// The model has more vertices than the index type can point at.
+    IndexOverflow { tag: String, vertices: usize, index_type: &'static str },
+    /// A node that has to be there wasn't.
+    MissingNode { path: String },
+    /// The attribute exists but holds a different type than the one we need.
+    WrongAttributeType { path: String, index: usize, expected: &'static str },
+    /// An attribute or an index inside of an array points past the end.
+    IndexOutOfRange { path: String, index: usize, len: usize },
+    /// An array of vectors whose length isnt a multiple of the vector size, so the file is cut off or broken.
+    MalformedArray { path: String, len: usize, stride: usize },
+}
+
+impl fmt::Display for ModelLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModelLoadError::Io(e) => write!(f, "failed to read model file: {}", e),
+            ModelLoadError::Document(e) => write!(f, "failed to load document: {}", e),
+            ModelLoadError::UnsupportedVersion => write!(f, "got FBX document of unsupported version"),
+            ModelLoadError::AsciiSyntax { line, message } => write!(f, "invalid ASCII FBX on line {}: {}", line, message),
+            ModelLoadError::AsciiDocument(e) => write!(f, "failed to load ASCII document: {}", e),
+            ModelLoadError::FbxWrite(e) => write!(f, "failed to write FBX: {}", e),
+            ModelLoadError::Gltf(e) => write!(f, "failed to load glTF: {}", e),
+            ModelLoadError::ObjSyntax { line, message } => write!(f, "invalid OBJ on line {}: {}", line, message),
+            ModelLoadError::InvalidCache(message) => write!(f, "invalid mesh cache: {}", message),
+            ModelLoadError::MissingAttributes { tag, missing } => {
+                let names: Vec<String> = missing.iter().map(|attribute| attribute.to_string()).collect();
+                write!(f, "model `{}` is missing {} for this vertex type", tag, names.join(", "))
+            }
+            ModelLoadError::IndexOverflow { tag, vertices, index_type } => {
+                write!(f, "model `{}` has {} vertices, too many for {} indices", tag, vertices, index_type)
+            }
+            ModelLoadError::MissingNode { path } => write!(f, "missing node `{}`", path),
+            ModelLoadError::WrongAttributeType { path, index, expected } => {
+                write!(f, "attribute {} of `{}` is not of type {}", index, path, expected)
+            }
+            ModelLoadError::IndexOutOfRange { path, index, len } => {
+                write!(f, "index {} is out of range in `{}` (length {})", index, path, len)
+            }
+            ModelLoadError::MalformedArray { path, len, stride } => {
+                write!(f, "array `{}` has {} values, which isnt a multiple of {}", path, len, stride)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ModelLoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ModelLoadError::Io(e) => Some(e),
+            ModelLoadError::Document(e) => Some(e),
+            ModelLoadError::FbxWrite(e) => Some(e),
+            ModelLoadError::Gltf(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ModelLoadError {
+    fn from(e: std::io::Error) -> Self {
+        ModelLoadError::Io(e)
+    }
+}
+
+impl From<fbxcel_dom::any::Error> for ModelLoadError {
+    fn from(e: fbxcel_dom::any::Error) -> Self {
+        ModelLoadError::Document(e)
+    }
+}
+
+impl From<gltf::Error> for ModelLoadError {
+    fn from(e: gltf::Error) -> Self {
+        ModelLoadError::Gltf(e)
+    }
+}
+
+impl From<fbxcel::writer::v7400::binary::Error> for ModelLoadError {
+    fn from(e: fbxcel::writer::v7400::binary::Error) -> Self {
+        ModelLoadError::FbxWrite(e)
+    }
+}
@@ -0,0 +1,326 @@
+#![allow(unused)]
+//! Reader for ASCII FBX files. The text gets turned into the same `Tree` fbxcel
+//! builds from binary files, so everything after `load_document` doesnt care
+//! which of the two formats a model came from.
+use fbxcel_dom::{fbxcel::{tree::v7400::{NodeId, Tree}, low::v7400::AttributeValue}, v7400::{Document, Loader}};
+
+use super::error::ModelLoadError;
+use super::model_loader::NodeAttributes;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token<'a> {
+    /// `Name:` at the start of a node.
+    Key(&'a str),
+    Str(String),
+    Number(&'a str),
+    /// Bare words, ASCII files use `T`, `Y` and such for booleans.
+    Word(&'a str),
+    /// `*24`, the element count in front of an array.
+    Count,
+    Comma,
+    Open,
+    Close,
+}
+
+struct Tokenizer<'a> {
+    text: &'a str,
+    position: usize,
+    line: usize,
+    peeked: Option<Option<Token<'a>>>,
+}
+impl<'a> Tokenizer<'a> {
+    fn new(text: &'a str) -> Self {
+        Self { text, position: 0, line: 1, peeked: None }
+    }
+    fn error(&self, message: impl Into<String>) -> ModelLoadError {
+        ModelLoadError::AsciiSyntax { line: self.line, message: message.into() }
+    }
+    fn peek(&mut self) -> Result<Option<&Token<'a>>, ModelLoadError> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.read()?);
+        }
+        Ok(self.peeked.as_ref().unwrap().as_ref())
+    }
+    fn next(&mut self) -> Result<Option<Token<'a>>, ModelLoadError> {
+        match self.peeked.take() {
+            Some(token) => Ok(token),
+            None => self.read(),
+        }
+    }
+    fn read(&mut self) -> Result<Option<Token<'a>>, ModelLoadError> {
+        let bytes = self.text.as_bytes();
+        // Skip whitespace and `;` comments.
+        while let Some(byte) = bytes.get(self.position) {
+            match byte {
+                b'\n' => {
+                    self.line += 1;
+                    self.position += 1;
+                }
+                b';' => {
+                    while bytes.get(self.position).map_or(false, |b| *b != b'\n') {
+                        self.position += 1;
+                    }
+                }
+                b if b.is_ascii_whitespace() => self.position += 1,
+                _ => break,
+            }
+        }
+        let Some(&byte) = bytes.get(self.position) else {
+            return Ok(None);
+        };
+        let start = self.position;
+        self.position += 1;
+        let token = match byte {
+            b',' => Token::Comma,
+            b'{' => Token::Open,
+            b'}' => Token::Close,
+            b'*' => {
+                while bytes.get(self.position).map_or(false, |b| b.is_ascii_digit()) {
+                    self.position += 1;
+                }
+                Token::Count
+            }
+            b'"' => {
+                while bytes.get(self.position).map_or(false, |b| *b != b'"') {
+                    if bytes[self.position] == b'\n' {
+                        self.line += 1;
+                    }
+                    self.position += 1;
+                }
+                if self.position >= bytes.len() {
+                    return Err(self.error("string is never closed"));
+                }
+                self.position += 1;
+                Token::Str(self.text[start + 1..self.position - 1].replace("&quot;", "\""))
+            }
+            b'-' | b'+' | b'.' | b'0'..=b'9' => {
+                while bytes.get(self.position).map_or(false, |b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'+' | b'.')) {
+                    self.position += 1;
+                }
+                Token::Number(&self.text[start..self.position])
+            }
+            b if b.is_ascii_alphabetic() || b == b'_' => {
+                while bytes.get(self.position).map_or(false, |b| b.is_ascii_alphanumeric() || matches!(b, b'_' | b'|')) {
+                    self.position += 1;
+                }
+                let word = &self.text[start..self.position];
+                if bytes.get(self.position) == Some(&b':') {
+                    self.position += 1;
+                    Token::Key(word)
+                } else {
+                    Token::Word(word)
+                }
+            }
+            other => return Err(self.error(format!("unexpected character `{}`", other as char))),
+        };
+        Ok(Some(token))
+    }
+}
+
+/// Array nodes whose values are integers in binary files. Everything else
+/// is read as doubles, which is what binary files use for the rest.
+fn array_type(name: &str) -> &'static str {
+    match name {
+        "KeyTime" => "i64",
+        "KeyValueFloat" | "KeyAttrDataFloat" => "f32",
+        "PolygonVertexIndex" | "Edges" | "NormalsIndex" | "BinormalsIndex" | "TangentsIndex" | "UVIndex"
+        | "ColorIndex" | "Materials" | "Smoothing" | "Indexes" | "KeyAttrFlags" | "KeyAttrRefCount"
+        | "TextureId" | "Visibility" => "i32",
+        _ => "f64",
+    }
+}
+
+struct AsciiParser<'a> {
+    tokens: Tokenizer<'a>,
+    tree: Tree,
+}
+impl<'a> AsciiParser<'a> {
+    /// Reads nodes until the closing `}` of `parent`, or the end of the file for the root.
+    fn parse_children(&mut self, parent: NodeId, path: &[&'a str]) -> Result<(), ModelLoadError> {
+        loop {
+            match self.tokens.next()? {
+                None if path.is_empty() => return Ok(()),
+                None => return Err(self.tokens.error(format!("`{}` is never closed", path.join("/")))),
+                Some(Token::Close) if !path.is_empty() => return Ok(()),
+                Some(Token::Key(name)) => {
+                    let node = self.tree.append_new(parent, name);
+                    let mut path = path.to_vec();
+                    path.push(name);
+                    self.parse_node(node, &path)?;
+                }
+                Some(token) => return Err(self.tokens.error(format!("expected a node name, got {:?}", token))),
+            }
+        }
+    }
+    /// Everything after `Name:`, the attributes and then the children if there is a `{`.
+    fn parse_node(&mut self, node: NodeId, path: &[&'a str]) -> Result<(), ModelLoadError> {
+        let name = path[path.len() - 1];
+        let mut index = 0;
+        loop {
+            match self.tokens.peek()? {
+                Some(Token::Count) => {
+                    self.tokens.next()?;
+                    let array = self.parse_array(name)?;
+                    self.tree.append_attribute(node, array);
+                    return Ok(());
+                }
+                Some(Token::Open) => {
+                    self.tokens.next()?;
+                    return self.parse_children(node, path);
+                }
+                Some(Token::Str(_)) | Some(Token::Number(_)) | Some(Token::Word(_)) => {
+                    let token = self.tokens.next()?.unwrap();
+                    let value = self.attribute(token, path, index)?;
+                    self.tree.append_attribute(node, value);
+                    index += 1;
+                }
+                Some(Token::Comma) => {
+                    self.tokens.next()?;
+                    // `Content: ,` is an empty attribute followed by the real one.
+                    if index == 0 {
+                        self.tree.append_attribute(node, AttributeValue::String(String::new()));
+                        index += 1;
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+    fn attribute(&self, token: Token<'a>, path: &[&'a str], index: usize) -> Result<AttributeValue, ModelLoadError> {
+        let parent = path.get(path.len().wrapping_sub(2)).copied().unwrap_or("");
+        let name = path[path.len() - 1];
+        Ok(match token {
+            Token::Str(text) => {
+                // Binary files store `Class::Name` as `Name\0\x01Class`, fbxcel-dom expects that.
+                match (parent, index, text.split_once("::")) {
+                    ("Objects", 1, Some((class, object))) => AttributeValue::String(format!("{}\u{0}\u{1}{}", object, class)),
+                    _ => AttributeValue::String(text),
+                }
+            }
+            Token::Number(text) => {
+                // Ids of objects and connections are always 64 bit in binary files.
+                let id = (parent == "Objects" && index == 0) || (name == "C" && index > 0);
+                match text.parse::<i64>() {
+                    Ok(v) if id => AttributeValue::I64(v),
+                    Ok(v) => i32::try_from(v).map(AttributeValue::I32).unwrap_or(AttributeValue::I64(v)),
+                    Err(_) => AttributeValue::F64(text.parse().map_err(|_| self.tokens.error(format!("`{}` is not a number", text)))?),
+                }
+            }
+            Token::Word("T") | Token::Word("Y") => AttributeValue::Bool(true),
+            Token::Word("F") | Token::Word("N") => AttributeValue::Bool(false),
+            Token::Word(word) => AttributeValue::String(word.into()),
+            _ => unreachable!(),
+        })
+    }
+    /// `*N { a: v, v, v }`
+    fn parse_array(&mut self, name: &str) -> Result<AttributeValue, ModelLoadError> {
+        if self.tokens.next()? != Some(Token::Open) {
+            return Err(self.tokens.error(format!("expected `{{` after the size of `{}`", name)));
+        }
+        let mut values: Vec<&'a str> = vec![];
+        loop {
+            match self.tokens.next()? {
+                Some(Token::Key("a")) | Some(Token::Comma) => {}
+                Some(Token::Number(value)) => values.push(value),
+                Some(Token::Close) => break,
+                other => return Err(self.tokens.error(format!("unexpected {:?} in the array of `{}`", other, name))),
+            }
+        }
+        let number = |value: &str| value.parse::<f64>().map_err(|_| self.tokens.error(format!("`{}` is not a number", value)));
+        Ok(match array_type(name) {
+            "i64" => AttributeValue::ArrI64(values.iter().map(|v| number(v).map(|v| v as i64)).collect::<Result<_, _>>()?),
+            "i32" => AttributeValue::ArrI32(values.iter().map(|v| number(v).map(|v| v as i32)).collect::<Result<_, _>>()?),
+            "f32" => AttributeValue::ArrF32(values.iter().map(|v| number(v).map(|v| v as f32)).collect::<Result<_, _>>()?),
+            _ => AttributeValue::ArrF64(values.iter().map(|v| number(v)).collect::<Result<_, _>>()?),
+        })
+    }
+}
+
+/// Parses the text of an ASCII FBX file into a node tree.
+pub fn parse_tree(text: &str) -> Result<Tree, ModelLoadError> {
+    let mut parser = AsciiParser { tokens: Tokenizer::new(text), tree: Tree::default() };
+    let root = parser.tree.root_id();
+    parser.parse_children(root, &[])?;
+    Ok(parser.tree)
+}
+
+/// # load_document
+///
+/// Same as loading a binary file, but from the text of an ASCII one.
+/// Only FBX 7 files work, FBX 6 ASCII files have a different layout.
+pub fn load_document(text: &str) -> Result<Box<Document>, ModelLoadError> {
+    let tree = parse_tree(text)?;
+    let version = tree.root()
+        .first_child_by_name("FBXHeaderExtension")
+        .and_then(|header| header.first_child_by_name("FBXVersion"))
+        .map(|version| version.attr_i64(0))
+        .transpose()?;
+    if version.map_or(false, |version| version < 7000) {
+        return Err(ModelLoadError::UnsupportedVersion);
+    }
+    let document = Loader::new().load_from_tree(tree).map_err(|e| ModelLoadError::AsciiDocument(e.to_string()))?;
+    Ok(Box::new(document))
+}
+
+#[cfg(test)]
+mod tests {
+    use drowsed_math::{FVec2, FVec3};
+
+    use super::*;
+    use crate::model::model_loader::StandardModelData;
+
+    fn fixture(name: &str) -> String {
+        format!("{}/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+    fn assert_vec3(a: &[FVec3], b: &[FVec3], what: &str) {
+        assert_eq!(a.len(), b.len(), "{}", what);
+        for (a, b) in a.iter().zip(b.iter()) {
+            assert!((a.x - b.x).abs() < 1e-6 && (a.y - b.y).abs() < 1e-6 && (a.z - b.z).abs() < 1e-6, "{}: {:?} != {:?}", what, a, b);
+        }
+    }
+    fn assert_vec2(a: &[FVec2], b: &[FVec2], what: &str) {
+        assert_eq!(a.len(), b.len(), "{}", what);
+        for (a, b) in a.iter().zip(b.iter()) {
+            assert!((a.x - b.x).abs() < 1e-6 && (a.y - b.y).abs() < 1e-6, "{}: {:?} != {:?}", what, a, b);
+        }
+    }
+
+    #[test]
+    fn ascii_and_binary_cube_match() {
+        let binary = StandardModelData::try_new(&fixture("cube.fbx")).unwrap();
+        let ascii = StandardModelData::try_new(&fixture("cube_ascii.fbx")).unwrap();
+        assert_eq!(binary.len(), ascii.len());
+        assert!(!binary.is_empty());
+        for (binary, ascii) in binary.iter().zip(ascii.iter()) {
+            assert_eq!(binary.tag, ascii.tag);
+            assert!(!binary.vertices.is_empty());
+            assert_vec3(&binary.vertices, &ascii.vertices, "vertices");
+            assert_eq!(binary.indices, ascii.indices);
+            assert_vec3(&binary.normals, &ascii.normals, "normals");
+            assert_eq!(binary.uvs.len(), ascii.uvs.len());
+            for (binary, ascii) in binary.uvs.iter().zip(ascii.uvs.iter()) {
+                assert_eq!(binary.name, ascii.name);
+                assert_vec2(&binary.uvs, &ascii.uvs, "uvs");
+            }
+            assert_eq!(binary.submeshes, ascii.submeshes);
+            assert_vec3(&[binary.transform.translation], &[ascii.transform.translation], "translation");
+            assert_vec3(&[binary.transform.scale], &[ascii.transform.scale], "scale");
+            let (a, b) = (binary.transform.rotation, ascii.transform.rotation);
+            assert!((a.x - b.x).abs() < 1e-6 && (a.y - b.y).abs() < 1e-6 && (a.z - b.z).abs() < 1e-6 && (a.w - b.w).abs() < 1e-6);
+            for (a, b) in [(binary.world.x, ascii.world.x), (binary.world.y, ascii.world.y), (binary.world.z, ascii.world.z), (binary.world.w, ascii.world.w)] {
+                assert!((a.x - b.x).abs() < 1e-6 && (a.y - b.y).abs() < 1e-6 && (a.z - b.z).abs() < 1e-6 && (a.w - b.w).abs() < 1e-6);
+            }
+        }
+    }
+    #[test]
+    fn object_names_come_back_in_binary_form() {
+        let tree = parse_tree("Objects: {\n\tModel: 12, \"Model::Cube\", \"Mesh\" {\n\t}\n}\n").unwrap();
+        let model = tree.root().first_child_by_name("Objects").unwrap().first_child_by_name("Model").unwrap();
+        assert_eq!(model.attr_i64(0).unwrap(), 12);
+        assert_eq!(model.attr_str(1).unwrap(), "Cube\u{0}\u{1}Model");
+    }
+    #[test]
+    fn unclosed_nodes_are_an_error() {
+        assert!(matches!(parse_tree("Objects: {\n\tModel: 1 {\n}"), Err(ModelLoadError::AsciiSyntax { .. })));
+    }
+}
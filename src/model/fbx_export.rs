@@ -0,0 +1,490 @@
+#![allow(unused)]
+//! Writes `StandardModelData` back out as a binary FBX 7.4 file, so meshes made or
+//! edited in the engine can go back into Blender and friends.
+//!
+//! Only what `StandardModelData` holds gets written: the geometry with its normals,
+//! uv sets and colors, materials with their file textures, the local transform of every
+//! model and the parent/child connections. Skins, blend shapes and animations are left out.
+use std::{io::{Seek, Write}, rc::Rc};
+
+use drowsed_math::{FVec3, FVec4};
+use fbxcel::{low::{v7400::ArrayAttributeEncoding, FbxVersion}, writer::v7400::binary::{AttributesWriter, FbxFooter, Writer}};
+
+use super::axis::AxisSystem;
+use super::error::ModelLoadError;
+use super::material::{Material, MaterialTexture};
+use super::model_loader::StandardModelData;
+use super::transform::{matrix_euler, quaternion_matrix};
+
+type WriteResult = Result<(), fbxcel::writer::v7400::binary::Error>;
+
+/// # ExportOptions
+///
+/// `axes` should be whatever the models are in right now. The default matches what
+/// `LoadOptions::default` converts into, so loading the file back with default
+/// options doesnt move anything.
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    pub axes: AxisSystem,
+    /// Goes into the `Creator` of the header.
+    pub creator: String,
+}
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self { axes: AxisSystem::ENGINE, creator: "yum_mocha".into() }
+    }
+}
+
+pub fn export(filepath: &str, models: &[StandardModelData]) -> Result<(), ModelLoadError> {
+    export_with(filepath, models, &ExportOptions::default())
+}
+pub fn export_with(filepath: &str, models: &[StandardModelData], options: &ExportOptions) -> Result<(), ModelLoadError> {
+    let file = std::io::BufWriter::new(std::fs::File::create(filepath)?);
+    let mut file = write_to(file, models, options)?;
+    file.flush()?;
+    Ok(())
+}
+
+/// # write_to
+///
+/// Writes the whole document into `sink` and hands it back. `StandardModelData::parent`
+/// indexes into `models` and turns into a model to model connection, models without a
+/// parent get connected to the root. Materials and textures shared through the same `Rc`
+/// are only written once.
+pub fn write_to<W: Write + Seek>(sink: W, models: &[StandardModelData], options: &ExportOptions) -> Result<W, ModelLoadError> {
+    let mut exporter = Exporter { writer: Writer::new(sink, FbxVersion::V7_4)?, next_id: 1_000_000 };
+
+    // Hand out all the ids first, the connections need them.
+    let model_ids: Vec<i64> = models.iter().map(|_| exporter.id()).collect();
+    let geometry_ids: Vec<Option<i64>> = models.iter().map(|model| (!model.indices.is_empty()).then(|| exporter.id())).collect();
+    let mut materials: Vec<(i64, Rc<Material>)> = vec![];
+    let mut textures: Vec<(i64, Rc<MaterialTexture>)> = vec![];
+    for material in models.iter().flat_map(|model| model.materials.iter()) {
+        if materials.iter().any(|(_, known)| Rc::ptr_eq(known, material)) {
+            continue;
+        }
+        materials.push((exporter.id(), material.clone()));
+        for (_, texture) in material.textures() {
+            // Textures that only live inside the source file have nothing to point at.
+            let has_file = !texture.best_path().as_os_str().is_empty();
+            if has_file && !textures.iter().any(|(_, known)| Rc::ptr_eq(known, texture)) {
+                textures.push((exporter.id(), texture.clone()));
+            }
+        }
+    }
+    let material_id = |material: &Rc<Material>| materials.iter().find(|(_, known)| Rc::ptr_eq(known, material)).map(|(id, _)| *id).unwrap();
+
+    exporter.header(options)?;
+    exporter.global_settings(&options.axes)?;
+    exporter.definitions(models.len(), geometry_ids.iter().flatten().count(), materials.len(), textures.len())?;
+
+    exporter.writer.new_node("Objects")?;
+    for (i, model) in models.iter().enumerate() {
+        if let Some(id) = geometry_ids[i] {
+            exporter.geometry(id, model)?;
+        }
+        exporter.model(model_ids[i], model, geometry_ids[i].is_some())?;
+    }
+    for (id, material) in materials.iter() {
+        exporter.material(*id, material)?;
+    }
+    for (id, texture) in textures.iter() {
+        exporter.texture(*id, texture)?;
+    }
+    exporter.writer.close_node()?;
+
+    exporter.writer.new_node("Connections")?;
+    for (i, model) in models.iter().enumerate() {
+        let parent = model.parent.and_then(|parent| model_ids.get(parent)).copied().unwrap_or(0);
+        exporter.connection(model_ids[i], parent, None)?;
+    }
+    for (i, model) in models.iter().enumerate() {
+        if let Some(geometry) = geometry_ids[i] {
+            exporter.connection(geometry, model_ids[i], None)?;
+        }
+        // The material layer indexes the materials in the order they are connected.
+        for material in model.materials.iter() {
+            exporter.connection(material_id(material), model_ids[i], None)?;
+        }
+    }
+    for (id, material) in materials.iter() {
+        for (property, texture) in material.textures() {
+            if let Some((texture_id, _)) = textures.iter().find(|(_, known)| Rc::ptr_eq(known, texture)) {
+                exporter.connection(*texture_id, *id, Some(property))?;
+            }
+        }
+    }
+    exporter.writer.close_node()?;
+
+    Ok(exporter.writer.finalize(&FbxFooter::default())?)
+}
+
+/// Binary files store `Class::Name` as `Name\0\x01Class`. Tags read from FBX files
+/// already have the class on them, that gets cut off first.
+fn object_name(tag: &str, class: &str) -> String {
+    format!("{}\u{0}\u{1}{}", tag.split('\u{0}').next().unwrap_or(""), class)
+}
+
+struct Exporter<W: Write + Seek> {
+    writer: Writer<W>,
+    next_id: i64,
+}
+impl<W: Write + Seek> Exporter<W> {
+    fn id(&mut self) -> i64 {
+        self.next_id += 1;
+        self.next_id
+    }
+    /// Starts a node that gets children, the caller closes it.
+    fn open(&mut self, name: &str, attributes: impl FnOnce(&mut AttributesWriter<'_, W>) -> WriteResult) -> WriteResult {
+        attributes(&mut self.writer.new_node(name)?)
+    }
+    /// A node without children, `attributes` writes its values.
+    fn leaf(&mut self, name: &str, attributes: impl FnOnce(&mut AttributesWriter<'_, W>) -> WriteResult) -> WriteResult {
+        attributes(&mut self.writer.new_node(name)?)?;
+        self.writer.close_node()
+    }
+    fn leaf_i32(&mut self, name: &str, value: i32) -> WriteResult {
+        self.leaf(name, |a| a.append_i32(value))
+    }
+    fn leaf_str(&mut self, name: &str, value: &str) -> WriteResult {
+        self.leaf(name, |a| a.append_string_direct(value))
+    }
+    fn leaf_f64_array(&mut self, name: &str, values: impl IntoIterator<Item = f64>) -> WriteResult {
+        self.leaf(name, |a| a.append_arr_f64_from_iter(Some(ArrayAttributeEncoding::Zlib), values).map(|_| ()))
+    }
+    fn leaf_i32_array(&mut self, name: &str, values: impl IntoIterator<Item = i32>) -> WriteResult {
+        self.leaf(name, |a| a.append_arr_i32_from_iter(Some(ArrayAttributeEncoding::Zlib), values).map(|_| ()))
+    }
+    /// `P: "name", "type", "label", "flags"` followed by whatever `value` appends.
+    fn property(&mut self, name: &str, kind: &str, label: &str, flags: &str, value: impl FnOnce(&mut AttributesWriter<'_, W>) -> WriteResult) -> WriteResult {
+        self.leaf("P", |a| {
+            a.append_string_direct(name)?;
+            a.append_string_direct(kind)?;
+            a.append_string_direct(label)?;
+            a.append_string_direct(flags)?;
+            value(a)
+        })
+    }
+    fn property_i32(&mut self, name: &str, kind: &str, label: &str, value: i32) -> WriteResult {
+        self.property(name, kind, label, "", |a| a.append_i32(value))
+    }
+    fn property_f64(&mut self, name: &str, kind: &str, label: &str, value: f64) -> WriteResult {
+        self.property(name, kind, label, "", |a| a.append_f64(value))
+    }
+    fn property_fvec3(&mut self, name: &str, kind: &str, label: &str, flags: &str, value: FVec3) -> WriteResult {
+        self.property(name, kind, label, flags, |a| {
+            a.append_f64(value.x as f64)?;
+            a.append_f64(value.y as f64)?;
+            a.append_f64(value.z as f64)
+        })
+    }
+
+    fn header(&mut self, options: &ExportOptions) -> WriteResult {
+        self.writer.new_node("FBXHeaderExtension")?;
+        self.leaf_i32("FBXHeaderVersion", 1003)?;
+        self.leaf_i32("FBXVersion", 7400)?;
+        self.leaf_i32("EncryptionType", 0)?;
+        self.leaf_str("Creator", &options.creator)?;
+        self.writer.close_node()?;
+        self.leaf_str("Creator", &options.creator)
+    }
+    fn global_settings(&mut self, axes: &AxisSystem) -> WriteResult {
+        let (up, up_sign) = axes.up.to_fbx();
+        let (front, front_sign) = axes.front.to_fbx();
+        let (coord, coord_sign) = axes.right.to_fbx();
+        self.writer.new_node("GlobalSettings")?;
+        self.leaf_i32("Version", 1000)?;
+        self.writer.new_node("Properties70")?;
+        self.property_i32("UpAxis", "int", "Integer", up)?;
+        self.property_i32("UpAxisSign", "int", "Integer", up_sign)?;
+        self.property_i32("FrontAxis", "int", "Integer", front)?;
+        self.property_i32("FrontAxisSign", "int", "Integer", front_sign)?;
+        self.property_i32("CoordAxis", "int", "Integer", coord)?;
+        self.property_i32("CoordAxisSign", "int", "Integer", coord_sign)?;
+        // Centimeters per unit.
+        self.property_f64("UnitScaleFactor", "double", "Number", axes.unit_scale as f64 * 100.0)?;
+        self.writer.close_node()?;
+        self.writer.close_node()
+    }
+    /// Object counts per type, Blender uses these to find the property templates.
+    fn definitions(&mut self, models: usize, geometries: usize, materials: usize, textures: usize) -> WriteResult {
+        let types = [("GlobalSettings", 1), ("Model", models), ("Geometry", geometries), ("Material", materials), ("Texture", textures)];
+        let types: Vec<_> = types.into_iter().filter(|(_, count)| *count > 0).collect();
+        self.writer.new_node("Definitions")?;
+        self.leaf_i32("Version", 100)?;
+        self.leaf_i32("Count", types.iter().map(|(_, count)| *count as i32).sum())?;
+        for (name, count) in types {
+            self.open("ObjectType", |a| a.append_string_direct(name))?;
+            self.leaf_i32("Count", count as i32)?;
+            self.writer.close_node()?;
+        }
+        self.writer.close_node()
+    }
+    /// # geometry
+    ///
+    /// Every triangle becomes a polygon. Vertices that came from the same control point
+    /// get merged back into one so the mesh stays connected across uv seams, the normals,
+    /// uvs and colors are written per polygon vertex so the split comes back on import.
+    fn geometry(&mut self, id: i64, model: &StandardModelData) -> WriteResult {
+        let control_points: Vec<u32> = match model.control_points.len() == model.vertices.len() {
+            true => model.control_points.clone(),
+            false => (0..model.vertices.len() as u32).collect(),
+        };
+        let control_point_count = control_points.iter().max().map_or(0, |max| *max as usize + 1);
+        let mut positions = vec![FVec3::from(0.0); control_point_count];
+        for (vertex, control_point) in control_points.iter().enumerate() {
+            positions[*control_point as usize] = model.vertices[vertex];
+        }
+        let mut triangle_materials = vec![0; model.indices.len() / 3];
+        for submesh in model.submeshes.iter() {
+            let first = submesh.first_index as usize / 3;
+            let last = (first + submesh.index_count as usize / 3).min(triangle_materials.len());
+            triangle_materials[first..last].iter_mut().for_each(|material| *material = submesh.material as i32);
+        }
+        let corners = &model.indices[..triangle_materials.len() * 3];
+
+        self.open("Geometry", |a| {
+            a.append_i64(id)?;
+            a.append_string_direct(&object_name(&model.tag, "Geometry"))?;
+            a.append_string_direct("Mesh")
+        })?;
+        self.writer.new_node("Properties70")?;
+        self.writer.close_node()?;
+        self.leaf_i32("GeometryVersion", 124)?;
+        self.leaf_f64_array("Vertices", positions.iter().flat_map(|p| [p.x as f64, p.y as f64, p.z as f64]))?;
+        // The last corner of every polygon is stored as -index - 1.
+        self.leaf_i32_array("PolygonVertexIndex", corners.iter().enumerate().map(|(i, index)| {
+            let control_point = control_points[*index as usize] as i32;
+            if i % 3 == 2 { -control_point - 1 } else { control_point }
+        }))?;
+
+        let mut layers: Vec<Vec<(&str, i32)>> = vec![vec![]];
+        if !model.normals.is_empty() {
+            self.layer_element("LayerElementNormal", 0, "", "Direct")?;
+            self.leaf_f64_array("Normals", corners.iter().flat_map(|index| {
+                let n = model.normals[*index as usize];
+                [n.x as f64, n.y as f64, n.z as f64]
+            }))?;
+            self.writer.close_node()?;
+            layers[0].push(("LayerElementNormal", 0));
+        }
+        for (set, uvs) in model.uvs.iter().enumerate() {
+            self.layer_element("LayerElementUV", set as i32, &uvs.name, "IndexToDirect")?;
+            // Flipped back, the loader turns FBX's bottom left origin into a top left one.
+            self.leaf_f64_array("UV", uvs.uvs.iter().flat_map(|uv| [uv.x as f64, 1.0 - uv.y as f64]))?;
+            self.leaf_i32_array("UVIndex", corners.iter().map(|index| *index as i32))?;
+            self.writer.close_node()?;
+            if layers.len() <= set {
+                layers.push(vec![]);
+            }
+            layers[set].push(("LayerElementUV", set as i32));
+        }
+        if !model.colors.is_empty() {
+            self.layer_element("LayerElementColor", 0, "Col", "IndexToDirect")?;
+            self.leaf_f64_array("Colors", model.colors.iter().flat_map(|c| [c.x as f64, c.y as f64, c.z as f64, c.w as f64]))?;
+            self.leaf_i32_array("ColorIndex", corners.iter().map(|index| *index as i32))?;
+            self.writer.close_node()?;
+            layers[0].push(("LayerElementColor", 0));
+        }
+        if !model.materials.is_empty() {
+            let all_same = triangle_materials.iter().all(|material| *material == triangle_materials.first().copied().unwrap_or(0));
+            self.writer.new_node("LayerElementMaterial")?.append_i32(0)?;
+            self.leaf_i32("Version", 101)?;
+            self.leaf_str("Name", "")?;
+            self.leaf_str("MappingInformationType", if all_same { "AllSame" } else { "ByPolygon" })?;
+            self.leaf_str("ReferenceInformationType", "IndexToDirect")?;
+            match all_same {
+                true => self.leaf_i32_array("Materials", triangle_materials.first().copied().into_iter())?,
+                false => self.leaf_i32_array("Materials", triangle_materials.iter().copied())?,
+            }
+            self.writer.close_node()?;
+            layers[0].push(("LayerElementMaterial", 0));
+        }
+        for (i, elements) in layers.iter().enumerate() {
+            self.writer.new_node("Layer")?.append_i32(i as i32)?;
+            self.leaf_i32("Version", 100)?;
+            for (kind, typed_index) in elements.iter() {
+                self.writer.new_node("LayerElement")?;
+                self.leaf_str("Type", kind)?;
+                self.leaf_i32("TypedIndex", *typed_index)?;
+                self.writer.close_node()?;
+            }
+            self.writer.close_node()?;
+        }
+        self.writer.close_node()
+    }
+    /// Opens a `LayerElement*` node mapped by polygon vertex, the caller writes the data and closes it.
+    fn layer_element(&mut self, name: &str, index: i32, layer_name: &str, reference: &str) -> WriteResult {
+        self.writer.new_node(name)?.append_i32(index)?;
+        self.leaf_i32("Version", 101)?;
+        self.leaf_str("Name", layer_name)?;
+        self.leaf_str("MappingInformationType", "ByPolygonVertex")?;
+        self.leaf_str("ReferenceInformationType", reference)
+    }
+    /// Models without geometry are written as `Null` so they come back as empties.
+    fn model(&mut self, id: i64, model: &StandardModelData, has_geometry: bool) -> WriteResult {
+        let transform = &model.transform;
+        self.open("Model", |a| {
+            a.append_i64(id)?;
+            a.append_string_direct(&object_name(&model.tag, "Model"))?;
+            a.append_string_direct(if has_geometry { "Mesh" } else { "Null" })
+        })?;
+        self.leaf_i32("Version", 232)?;
+        self.writer.new_node("Properties70")?;
+        self.property_i32("RotationOrder", "enum", "", 0)?;
+        self.property_i32("InheritType", "enum", "", 1)?;
+        self.property_i32("DefaultAttributeIndex", "int", "Integer", 0)?;
+        self.property_fvec3("Lcl Translation", "Lcl Translation", "", "A", transform.translation)?;
+        self.property_fvec3("Lcl Rotation", "Lcl Rotation", "", "A", matrix_euler(&quaternion_matrix(transform.rotation)))?;
+        self.property_fvec3("Lcl Scaling", "Lcl Scaling", "", "A", transform.scale)?;
+        self.writer.close_node()?;
+        self.leaf("Shading", |a| a.append_bool(true))?;
+        self.leaf_str("Culling", "CullingOff")?;
+        self.writer.close_node()
+    }
+    /// Colors are written with their factors already multiplied in and the factors set to 1.
+    fn material(&mut self, id: i64, material: &Material) -> WriteResult {
+        self.open("Material", |a| {
+            a.append_i64(id)?;
+            a.append_string_direct(&object_name(material.tag(), "Material"))?;
+            a.append_string_direct("")
+        })?;
+        self.leaf_i32("Version", 102)?;
+        self.leaf_str("ShadingModel", "phong")?;
+        self.leaf_i32("MultiLayer", 0)?;
+        self.writer.new_node("Properties70")?;
+        self.property_fvec3("DiffuseColor", "Color", "", "A", material.diffuse())?;
+        self.property_f64("DiffuseFactor", "Number", "", 1.0)?;
+        self.property_fvec3("SpecularColor", "Color", "", "A", material.specular())?;
+        self.property_f64("SpecularFactor", "Number", "", 1.0)?;
+        self.property_f64("ShininessExponent", "Number", "", material.shininess() as f64)?;
+        self.property_fvec3("EmissiveColor", "Color", "", "A", material.emissive())?;
+        self.property_f64("EmissiveFactor", "Number", "", 1.0)?;
+        self.property_f64("TransparencyFactor", "Number", "", 1.0 - material.opacity() as f64)?;
+        self.property_f64("Opacity", "Number", "", material.opacity() as f64)?;
+        self.writer.close_node()?;
+        self.writer.close_node()
+    }
+    fn texture(&mut self, id: i64, texture: &MaterialTexture) -> WriteResult {
+        let name = object_name(&texture.tag, "Texture");
+        self.open("Texture", |a| {
+            a.append_i64(id)?;
+            a.append_string_direct(&name)?;
+            a.append_string_direct("")
+        })?;
+        self.leaf_str("Type", "TextureVideoClip")?;
+        self.leaf_i32("Version", 202)?;
+        self.leaf_str("TextureName", &name)?;
+        self.leaf_str("FileName", &texture.best_path().to_string_lossy())?;
+        self.leaf_str("RelativeFilename", &texture.relative_filename.to_string_lossy())?;
+        self.writer.close_node()
+    }
+    /// `C: "OO", child, parent`, or `"OP"` with the property a texture plugs into.
+    fn connection(&mut self, child: i64, parent: i64, property: Option<&str>) -> WriteResult {
+        self.leaf("C", |a| {
+            a.append_string_direct(if property.is_some() { "OP" } else { "OO" })?;
+            a.append_i64(child)?;
+            a.append_i64(parent)?;
+            match property {
+                Some(property) => a.append_string_direct(property),
+                None => Ok(()),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use drowsed_math::{FMat4, FVec2};
+    use fbxcel_dom::any::AnyDocument;
+
+    use super::*;
+    use crate::vk_obj::rendering::mesh::SubMesh;
+
+    fn fixture(name: &str) -> String {
+        format!("{}/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+    fn assert_vec3(a: &[FVec3], b: &[FVec3], epsilon: f32, what: &str) {
+        assert_eq!(a.len(), b.len(), "{}", what);
+        for (a, b) in a.iter().zip(b.iter()) {
+            assert!((a.x - b.x).abs() < epsilon && (a.y - b.y).abs() < epsilon && (a.z - b.z).abs() < epsilon, "{}: {:?} != {:?}", what, a, b);
+        }
+    }
+    fn assert_vec2(a: &[FVec2], b: &[FVec2], what: &str) {
+        assert_eq!(a.len(), b.len(), "{}", what);
+        for (a, b) in a.iter().zip(b.iter()) {
+            assert!((a.x - b.x).abs() < 1e-6 && (a.y - b.y).abs() < 1e-6, "{}: {:?} != {:?}", what, a, b);
+        }
+    }
+    fn assert_mat4(a: &FMat4, b: &FMat4, what: &str) {
+        for (a, b) in [(a.x, b.x), (a.y, b.y), (a.z, b.z), (a.w, b.w)] {
+            assert!((a.x - b.x).abs() < 1e-4 && (a.y - b.y).abs() < 1e-4 && (a.z - b.z).abs() < 1e-4 && (a.w - b.w).abs() < 1e-4, "{}: {:?} != {:?}", what, a, b);
+        }
+    }
+    /// Writes `models` into memory and loads them back with the default options.
+    fn round_trip(models: &[StandardModelData]) -> Vec<StandardModelData> {
+        let bytes = write_to(Cursor::new(vec![]), models, &ExportOptions::default()).unwrap().into_inner();
+        let document = match AnyDocument::from_seekable_reader(Cursor::new(bytes)).unwrap() {
+            AnyDocument::V7400(_, document) => document,
+            _ => panic!("exported something that isnt FBX 7.4"),
+        };
+        StandardModelData::try_parse(document).unwrap()
+    }
+    fn assert_round_trip(name: &str) {
+        let original = StandardModelData::try_new(&fixture(name)).unwrap();
+        assert!(original.iter().any(|model| !model.indices.is_empty()), "{} has no geometry", name);
+        let loaded = round_trip(&original);
+        assert_eq!(original.len(), loaded.len(), "{}", name);
+        for (original, loaded) in original.iter().zip(loaded.iter()) {
+            assert_eq!(original.tag, loaded.tag);
+            assert_vec3(&original.vertices, &loaded.vertices, 1e-6, "vertices");
+            assert_vec3(&original.normals, &loaded.normals, 1e-6, "normals");
+            // Merged back into control points on export and split again on import, so the
+            // welding has to come out the same for the indices to match.
+            assert_eq!(original.indices, loaded.indices, "{} indices", original.tag);
+            assert_eq!(original.control_points.len(), loaded.control_points.len());
+            assert_eq!(original.uvs.len(), loaded.uvs.len());
+            for (original, loaded) in original.uvs.iter().zip(loaded.uvs.iter()) {
+                assert_eq!(original.name, loaded.name);
+                assert_vec2(&original.uvs, &loaded.uvs, "uvs");
+            }
+            assert_eq!(original.submeshes, loaded.submeshes);
+            let tags = |model: &StandardModelData| model.materials.iter().map(|material| material.tag().to_string()).collect::<Vec<_>>();
+            assert_eq!(tags(original), tags(loaded));
+            assert_eq!(original.parent, loaded.parent);
+            assert_vec3(&[original.transform.translation], &[loaded.transform.translation], 1e-4, "translation");
+            assert_vec3(&[original.transform.scale], &[loaded.transform.scale], 1e-4, "scale");
+            // q and -q are the same rotation, compare the matrices instead.
+            assert_mat4(&quaternion_matrix(original.transform.rotation), &quaternion_matrix(loaded.transform.rotation), "rotation");
+            assert_mat4(&original.world, &loaded.world, "world");
+        }
+    }
+
+    #[test]
+    fn monke_reads_back_identically() {
+        assert_round_trip("monke.fbx");
+    }
+    #[test]
+    fn cube_reads_back_identically() {
+        assert_round_trip("cube.fbx");
+    }
+    #[test]
+    fn mixed_materials_are_written_by_polygon() {
+        let mut models = StandardModelData::try_new(&fixture("cube.fbx")).unwrap();
+        let model = models.iter_mut().find(|model| !model.indices.is_empty()).unwrap();
+        // Two copies of the same material are enough to get two ranges out of a single material file.
+        let material = model.materials.first().map(|material| Material::clone(material)).unwrap_or_default();
+        model.materials = vec![Rc::new(material.clone()), Rc::new(material)];
+        let half = model.indices.len() as u32 / 6 * 3;
+        model.submeshes = vec![
+            SubMesh { material: 0, first_index: 0, index_count: half },
+            SubMesh { material: 1, first_index: half, index_count: model.indices.len() as u32 - half },
+        ];
+        let loaded = round_trip(&models);
+        let (original, loaded) = models.iter().zip(loaded.iter()).find(|(model, _)| !model.indices.is_empty()).unwrap();
+        assert_eq!(original.submeshes, loaded.submeshes);
+        assert_eq!(original.indices, loaded.indices);
+    }
+}
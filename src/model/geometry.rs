@@ -0,0 +1,419 @@
+#![allow(unused)]
+//! Normals, tangents and bounds for indexed triangle meshes. The free functions work
+//! on plain slices so they can be used on anything, the `StandardModelData` methods at
+//! the bottom apply them to a loaded model before it goes into a `RenderBatch`.
+use std::collections::HashMap;
+
+use drowsed_math::{FMat4, FVec2, FVec3, FVec4, Vector, EuclideanGeometry};
+
+use super::model_loader::{StandardModelData, safe_normalize};
+use super::transform::{transform_point, transform_vector};
+
+/// How `corner_normals` decides which faces get averaged together.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NormalMode {
+    /// Every face around a position, no hard edges at all.
+    Smooth,
+    /// Every face keeps its own normal.
+    Flat,
+    /// Faces that meet at more than this many degrees get a hard edge between them,
+    /// like auto smooth in Blender. 30 to 60 works well for most things.
+    Angle(f32),
+}
+
+/// Cross product of two edges. Points out of the counter clockwise side and is twice as long as the area.
+pub fn triangle_normal(a: FVec3, b: FVec3, c: FVec3) -> FVec3 {
+    (b - a).cross(c - a)
+}
+/// Angle in radians at `a` in the triangle `a b c`.
+pub fn corner_angle(a: FVec3, b: FVec3, c: FVec3) -> f32 {
+    let (ab, ac) = (safe_normalize(b - a), safe_normalize(c - a));
+    ab.dot(&ac).clamp(-1.0, 1.0).acos()
+}
+fn length(v: FVec3) -> f32 {
+    v.dot(&v).sqrt()
+}
+/// Same as `MeshBuilder`, -0.0 and 0.0 are the same value.
+fn vector_key(p: FVec3) -> [u32; 3] {
+    [p.x, p.y, p.z].map(|v| if v == 0.0 { 0 } else { v.to_bits() })
+}
+
+/// # corner_normals
+///
+/// One normal for every entry of `indices`. Faces are weighted by the angle of the
+/// corner that touches the shared position, same as Blender and MikkTSpace expect.
+/// Corners are grouped by position rather than by index so uv seams dont turn into
+/// hard edges. Degenerate triangles get a zero normal and dont affect their neighbours.
+pub fn corner_normals(positions: &[FVec3], indices: &[u32], mode: NormalMode) -> Vec<FVec3> {
+    let triangles = indices.len() / 3;
+    let face_normals: Vec<FVec3> = indices.chunks_exact(3).map(|t| {
+        safe_normalize(triangle_normal(positions[t[0] as usize], positions[t[1] as usize], positions[t[2] as usize]))
+    }).collect();
+    if mode == NormalMode::Flat {
+        return (0..triangles * 3).map(|corner| face_normals[corner / 3]).collect();
+    }
+    let corner_weights: Vec<f32> = indices.chunks_exact(3).flat_map(|t| {
+        let [a, b, c] = [t[0], t[1], t[2]].map(|i| positions[i as usize]);
+        [corner_angle(a, b, c), corner_angle(b, c, a), corner_angle(c, a, b)]
+    }).collect();
+
+    let mut groups: HashMap<[u32; 3], Vec<usize>> = HashMap::new();
+    for (corner, index) in indices[..triangles * 3].iter().enumerate() {
+        groups.entry(vector_key(positions[*index as usize])).or_default().push(corner);
+    }
+    let min_cos = match mode {
+        NormalMode::Angle(degrees) => degrees.to_radians().cos(),
+        _ => -2.0,
+    };
+    let mut normals = vec![FVec3::from(0.0); triangles * 3];
+    for corners in groups.values() {
+        for &corner in corners.iter() {
+            let face = face_normals[corner / 3];
+            let mut sum = FVec3::from(0.0);
+            for &other in corners.iter() {
+                let other_face = face_normals[other / 3];
+                if other == corner || face.dot(&other_face) >= min_cos {
+                    sum += other_face * corner_weights[other];
+                }
+            }
+            normals[corner] = safe_normalize(sum);
+        }
+    }
+    normals
+}
+/// Smooth normal for every vertex, vertices at the same position end up with the same one.
+pub fn vertex_normals(positions: &[FVec3], indices: &[u32]) -> Vec<FVec3> {
+    let mut normals = vec![FVec3::from(0.0); positions.len()];
+    for (corner, normal) in corner_normals(positions, indices, NormalMode::Smooth).into_iter().enumerate() {
+        normals[indices[corner] as usize] = normal;
+    }
+    normals
+}
+
+/// # corner_tangents
+///
+/// Tangent of every corner in `indices` for normal mapping, `w` is the handedness and the
+/// bitangent is `bitangent(normal, tangent)`. `uvs` are the way the loaders store them with
+/// v = 0 at the top, v gets flipped back up here so the handedness comes out the same as in
+/// MikkTSpace and normal maps baked in Blender or Substance dont get an inverted green channel.
+///
+/// Like MikkTSpace every triangle contributes by the angle of its corner, the result is made
+/// orthogonal to the normal and a vertex is only averaged with the triangles around it that
+/// have the same handedness. Where mirrored uv islands meet the corners of one vertex end up
+/// with different tangents, `StandardModelData::generate_tangents` splits those vertices.
+pub fn corner_tangents(positions: &[FVec3], normals: &[FVec3], uvs: &[FVec2], indices: &[u32]) -> Vec<FVec4> {
+    // per vertex, one sum for each handedness
+    let mut tangents = vec![[FVec3::from(0.0); 2]; positions.len()];
+    let mut bitangents = vec![[FVec3::from(0.0); 2]; positions.len()];
+    let mut sides = Vec::with_capacity(indices.len() / 3);
+    for t in indices.chunks_exact(3) {
+        let [i0, i1, i2] = [t[0], t[1], t[2]].map(|i| i as usize);
+        let (p0, p1, p2) = (positions[i0], positions[i1], positions[i2]);
+        let (e1, e2) = (p1 - p0, p2 - p0);
+        let (d1, d2) = (uvs[i1] - uvs[i0], uvs[i2] - uvs[i0]);
+        // v points down in the stored uvs and up in the tangent space bakers use
+        let (d1, d2) = (FVec2::new(d1.x, -d1.y), FVec2::new(d2.x, -d2.y));
+        let det = d1.x * d2.y - d2.x * d1.y;
+        let side = (det < 0.0) as usize;
+        sides.push(side);
+        if det.abs() <= f32::EPSILON {
+            continue;
+        }
+        let r = 1.0 / det;
+        let tangent = (e1 * d2.y - e2 * d1.y) * r;
+        let bitangent = (e2 * d1.x - e1 * d2.x) * r;
+        let angles = [corner_angle(p0, p1, p2), corner_angle(p1, p2, p0), corner_angle(p2, p0, p1)];
+        for (i, angle) in [i0, i1, i2].into_iter().zip(angles) {
+            tangents[i][side] += tangent * angle;
+            bitangents[i][side] += bitangent * angle;
+        }
+    }
+    indices.iter().enumerate().map(|(corner, index)| {
+        let (i, side) = (*index as usize, sides[corner / 3]);
+        let n = normals[i];
+        // Gram-Schmidt, whatever is left after taking out the normal part.
+        let mut t = safe_normalize(tangents[i][side] - n * n.dot(&tangents[i][side]));
+        if t.dot(&t) == 0.0 {
+            t = any_perpendicular(n);
+        }
+        let w = if n.cross(t).dot(&bitangents[i][side]) < 0.0 { -1.0 } else { 1.0 };
+        FVec4::new(t.x, t.y, t.z, w)
+    }).collect()
+}
+/// Bitangent from a normal and a tangent with handedness in `w`.
+pub fn bitangent(normal: FVec3, tangent: FVec4) -> FVec3 {
+    normal.cross(FVec3::new(tangent.x, tangent.y, tangent.z)) * tangent.w
+}
+/// Some unit vector at a right angle to `n`, for vertices without usable uvs.
+fn any_perpendicular(n: FVec3) -> FVec3 {
+    let other = if n.x.abs() < 0.9 { FVec3::new(1.0, 0.0, 0.0) } else { FVec3::new(0.0, 1.0, 0.0) };
+    safe_normalize(n.cross(other))
+}
+
+/// # Aabb
+///
+/// Axis aligned bounding box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: FVec3,
+    pub max: FVec3,
+}
+impl Aabb {
+    /// `None` when there are no points.
+    pub fn from_points(points: &[FVec3]) -> Option<Self> {
+        let first = *points.first()?;
+        let mut aabb = Self { min: first, max: first };
+        for point in points[1..].iter() {
+            aabb.expand(*point);
+        }
+        Some(aabb)
+    }
+    pub fn expand(&mut self, point: FVec3) {
+        self.min = FVec3::new(self.min.x.min(point.x), self.min.y.min(point.y), self.min.z.min(point.z));
+        self.max = FVec3::new(self.max.x.max(point.x), self.max.y.max(point.y), self.max.z.max(point.z));
+    }
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        let mut aabb = *self;
+        aabb.expand(other.min);
+        aabb.expand(other.max);
+        aabb
+    }
+    pub fn center(&self) -> FVec3 {
+        (self.min + self.max) * 0.5
+    }
+    pub fn size(&self) -> FVec3 {
+        self.max - self.min
+    }
+    /// Half of `size`.
+    pub fn extents(&self) -> FVec3 {
+        self.size() * 0.5
+    }
+    pub fn contains(&self, point: FVec3) -> bool {
+        point.x >= self.min.x && point.y >= self.min.y && point.z >= self.min.z
+            && point.x <= self.max.x && point.y <= self.max.y && point.z <= self.max.z
+    }
+    pub fn corners(&self) -> [FVec3; 8] {
+        let (a, b) = (self.min, self.max);
+        [
+            FVec3::new(a.x, a.y, a.z), FVec3::new(b.x, a.y, a.z), FVec3::new(a.x, b.y, a.z), FVec3::new(b.x, b.y, a.z),
+            FVec3::new(a.x, a.y, b.z), FVec3::new(b.x, a.y, b.z), FVec3::new(a.x, b.y, b.z), FVec3::new(b.x, b.y, b.z),
+        ]
+    }
+    /// Box around the transformed corners, so it can only grow under rotation.
+    pub fn transformed(&self, matrix: &FMat4) -> Aabb {
+        let corners = self.corners().map(|corner| transform_point(matrix, corner));
+        Aabb::from_points(&corners).unwrap()
+    }
+}
+
+/// # BoundingSphere
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingSphere {
+    pub center: FVec3,
+    pub radius: f32,
+}
+impl BoundingSphere {
+    /// Ritter's sphere, at most a few percent bigger than the smallest one and a lot
+    /// tighter than the sphere around the `Aabb` for long thin meshes. `None` when there are no points.
+    pub fn from_points(points: &[FVec3]) -> Option<Self> {
+        let first = *points.first()?;
+        let farthest = |from: FVec3| *points.iter().max_by(|a, b| length(**a - from).total_cmp(&length(**b - from))).unwrap();
+        let a = farthest(first);
+        let b = farthest(a);
+        let mut sphere = Self { center: (a + b) * 0.5, radius: length(b - a) * 0.5 };
+        for point in points.iter() {
+            sphere.expand(*point);
+        }
+        Some(sphere)
+    }
+    pub fn from_aabb(aabb: &Aabb) -> Self {
+        Self { center: aabb.center(), radius: length(aabb.extents()) }
+    }
+    /// Grows the sphere just enough to hold `point`, moving the center towards it.
+    pub fn expand(&mut self, point: FVec3) {
+        let distance = length(point - self.center);
+        if distance <= self.radius {
+            return;
+        }
+        let radius = (self.radius + distance) * 0.5;
+        self.center = self.center + (point - self.center) * ((radius - self.radius) / distance);
+        self.radius = radius;
+    }
+    pub fn contains(&self, point: FVec3) -> bool {
+        length(point - self.center) <= self.radius
+    }
+    /// The radius gets scaled by the biggest axis scale of `matrix`.
+    pub fn transformed(&self, matrix: &FMat4) -> BoundingSphere {
+        let scale = [FVec3::new(1.0, 0.0, 0.0), FVec3::new(0.0, 1.0, 0.0), FVec3::new(0.0, 0.0, 1.0)]
+            .map(|axis| length(transform_vector(matrix, axis)))
+            .into_iter()
+            .fold(0.0f32, f32::max);
+        Self { center: transform_point(matrix, self.center), radius: self.radius * scale }
+    }
+}
+
+impl StandardModelData {
+    /// Box around the vertices in model space, `None` for models without any.
+    pub fn aabb(&self) -> Option<Aabb> {
+        Aabb::from_points(&self.vertices)
+    }
+    /// Same as `aabb` but in world space using `world`.
+    pub fn world_aabb(&self) -> Option<Aabb> {
+        self.aabb().map(|aabb| aabb.transformed(&self.world))
+    }
+    pub fn bounding_sphere(&self) -> Option<BoundingSphere> {
+        BoundingSphere::from_points(&self.vertices)
+    }
+    /// # recalculate_normals
+    ///
+    /// Throws away the normals of the model and makes new ones. Vertices get split where
+    /// `mode` puts a hard edge (and merged back where it doesnt), everything stored per
+    /// vertex, skin weights and blend shapes included, gets carried over to the new vertices.
+    pub fn recalculate_normals(&mut self, mode: NormalMode) {
+        let normals = corner_normals(&self.vertices, &self.indices, mode);
+        let mut lookup: HashMap<(u32, [u32; 3]), u32> = HashMap::new();
+        let mut remap: Vec<u32> = vec![];
+        let mut indices = Vec::with_capacity(self.indices.len());
+        for (index, normal) in self.indices.iter().zip(normals.iter()) {
+            let next = remap.len() as u32;
+            let vertex = *lookup.entry((*index, vector_key(*normal))).or_insert_with(|| {
+                remap.push(*index);
+                next
+            });
+            indices.push(vertex);
+        }
+        let mut new_normals = vec![FVec3::from(0.0); remap.len()];
+        for (vertex, normal) in indices.iter().zip(normals.iter()) {
+            new_normals[*vertex as usize] = *normal;
+        }
+        self.remap_vertices(&remap);
+        self.normals = new_normals;
+        self.indices = indices;
+    }
+    /// # generate_tangents
+    ///
+    /// Tangents of every vertex for the first uv set, see `corner_tangents`. Vertices whose
+    /// corners dont agree on a tangent, which is where mirrored uv islands meet, get split
+    /// first the same way `recalculate_normals` splits hard edges, so every side keeps its own
+    /// handedness like MikkTSpace. Models without uvs get an arbitrary tangent perpendicular to the normal.
+    pub fn generate_tangents(&mut self) -> Vec<FVec4> {
+        let normals = match self.normals.len() == self.vertices.len() {
+            true => self.normals.clone(),
+            false => vertex_normals(&self.vertices, &self.indices),
+        };
+        let uvs = match self.uvs.first() {
+            Some(set) => set.uvs.clone(),
+            None => vec![FVec2::default(); self.vertices.len()],
+        };
+        let corners = corner_tangents(&self.vertices, &normals, &uvs, &self.indices);
+        let mut lookup: HashMap<(u32, [u32; 3], u32), u32> = HashMap::new();
+        let mut remap: Vec<u32> = vec![];
+        let mut indices = Vec::with_capacity(self.indices.len());
+        for (index, tangent) in self.indices.iter().zip(corners.iter()) {
+            let next = remap.len() as u32;
+            let key = (*index, vector_key(FVec3::new(tangent.x, tangent.y, tangent.z)), tangent.w.to_bits());
+            let vertex = *lookup.entry(key).or_insert_with(|| {
+                remap.push(*index);
+                next
+            });
+            indices.push(vertex);
+        }
+        let mut tangents = vec![FVec4::default(); remap.len()];
+        for (vertex, tangent) in indices.iter().zip(corners.iter()) {
+            tangents[*vertex as usize] = *tangent;
+        }
+        self.remap_vertices(&remap);
+        self.indices = indices;
+        tangents
+    }
+    /// Rebuilds everything stored per vertex so new vertex `i` is a copy of old vertex `remap[i]`.
+    /// `indices` has to be fixed up by the caller.
+    pub(crate) fn remap_vertices(&mut self, remap: &[u32]) {
+        fn apply<T: Copy>(values: &mut Vec<T>, remap: &[u32]) {
+            if !values.is_empty() {
+                *values = remap.iter().map(|old| values[*old as usize]).collect();
+            }
+        }
+        apply(&mut self.vertices, remap);
+        apply(&mut self.normals, remap);
+        apply(&mut self.colors, remap);
+        apply(&mut self.control_points, remap);
+        for set in self.uvs.iter_mut() {
+            apply(&mut set.uvs, remap);
+        }
+        if let Some(skin) = &mut self.skin {
+            apply(&mut skin.joint_indices, remap);
+            apply(&mut skin.joint_weights, remap);
+        }
+        // Blend shapes only store the vertices they move, every copy of one of those moves too.
+        let mut copies: HashMap<u32, Vec<u32>> = HashMap::new();
+        for (new, old) in remap.iter().enumerate() {
+            copies.entry(*old).or_default().push(new as u32);
+        }
+        for shape in self.morph_channels.iter_mut().flat_map(|channel| channel.shapes.iter_mut()) {
+            let (mut indices, mut positions, mut normals) = (vec![], vec![], vec![]);
+            for (i, old) in shape.indices.iter().enumerate() {
+                for new in copies.get(old).into_iter().flatten() {
+                    indices.push(*new);
+                    positions.push(shape.positions[i]);
+                    if let Some(normal) = shape.normals.get(i) {
+                        normals.push(*normal);
+                    }
+                }
+            }
+            shape.indices = indices;
+            shape.positions = positions;
+            shape.normals = normals;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::model_loader::UVSet;
+
+    #[test]
+    fn tangents_match_mikktspace_handedness() {
+        // a quad facing +z with u going right and v going up in the texture, stored with v flipped
+        let positions = [FVec3::new(0.0, 0.0, 0.0), FVec3::new(1.0, 0.0, 0.0), FVec3::new(1.0, 1.0, 0.0), FVec3::new(0.0, 1.0, 0.0)];
+        let normals = [FVec3::new(0.0, 0.0, 1.0); 4];
+        let uvs = [FVec2::new(0.0, 1.0), FVec2::new(1.0, 1.0), FVec2::new(1.0, 0.0), FVec2::new(0.0, 0.0)];
+        let indices = [0, 1, 2, 0, 2, 3];
+        for tangent in corner_tangents(&positions, &normals, &uvs, &indices) {
+            assert!((tangent.x - 1.0).abs() < 1e-5 && tangent.y.abs() < 1e-5 && tangent.z.abs() < 1e-5, "{:?}", tangent);
+            assert_eq!(tangent.w, 1.0);
+            // the bitangent points where v goes up in the texture
+            let b = bitangent(FVec3::new(0.0, 0.0, 1.0), tangent);
+            assert!((b.y - 1.0).abs() < 1e-5);
+        }
+    }
+    #[test]
+    fn mirrored_uvs_split_vertices() {
+        // two quads next to each other, the uvs of the right one are mirrored across the middle edge
+        let mut model = StandardModelData::default();
+        for y in 0..2 {
+            for x in 0..3 {
+                model.vertices.push(FVec3::new(x as f32, y as f32, 0.0));
+            }
+        }
+        model.normals = vec![FVec3::new(0.0, 0.0, 1.0); 6];
+        model.uvs = vec![UVSet {
+            name: "UVMap".into(),
+            uvs: model.vertices.iter().map(|p| FVec2::new(1.0 - (p.x - 1.0).abs(), 1.0 - p.y)).collect(),
+        }];
+        model.indices = vec![0, 1, 4, 0, 4, 3, 1, 2, 5, 1, 5, 4];
+        let tangents = model.generate_tangents();
+        // the two vertices on the middle edge got a copy for each side
+        assert_eq!(model.vertices.len(), 8);
+        assert_eq!(tangents.len(), 8);
+        assert_eq!(tangents.iter().filter(|t| t.w < 0.0).count(), 4);
+        for (triangle, expected) in model.indices.chunks_exact(3).zip([1.0, 1.0, -1.0, -1.0]) {
+            for vertex in triangle {
+                let tangent = tangents[*vertex as usize];
+                assert_eq!(tangent.w, expected);
+                assert!((tangent.x - expected).abs() < 1e-5);
+            }
+        }
+    }
+}
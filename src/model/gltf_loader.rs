@@ -0,0 +1,533 @@
+#![allow(unused)]
+//! glTF 2.0 loader for .gltf and .glb files. The gltf crate takes care of buffers,
+//! accessors and interleaved buffer views, this maps what it reads onto the same
+//! `Scene`, `StandardModelData`, `Skin` and `AnimationClip` the FBX loader uses.
+use std::{collections::HashMap, path::{Path, PathBuf}, rc::Rc};
+
+use drowsed_math::{FMat4, FVec2, FVec3, FVec4, SquareMatrix, Vector, EuclideanGeometry, complex::quaternion::Quaternion};
+use gltf::{animation::{Interpolation as GltfInterpolation, util::ReadOutputs}, buffer, image::Source, mesh::Mode};
+
+use super::axis::{Axis, AxisSystem};
+use super::clip::{AnimationClip, Interpolation, Keyframe, Keyframes, Track};
+use super::error::ModelLoadError;
+use super::material::{Material, MaterialTexture};
+use super::model_loader::{StandardModelData, LoadOptions, GenerateNormals, UVSet, safe_normalize};
+use super::scene::{ModelTransform, Scene, SceneGraph};
+use super::skin::{Joint, Skin};
+use super::transform::{self, affine_inverse, quaternion_matrix, matrix_euler, transform_vector};
+use crate::vk_obj::rendering::mesh::SubMesh;
+
+/// glTF is always y up, +z towards the viewer and in meters.
+pub const GLTF_AXES: AxisSystem = AxisSystem {
+    right: Axis::PositiveX,
+    up: Axis::PositiveY,
+    front: Axis::PositiveZ,
+    unit_scale: 1.0,
+};
+
+/// Whether the extension of `filepath` is .gltf or .glb.
+pub fn is_gltf(filepath: &str) -> bool {
+    Path::new(filepath).extension().map_or(false, |extension| extension.eq_ignore_ascii_case("gltf") || extension.eq_ignore_ascii_case("glb"))
+}
+pub fn try_new(filepath: &str) -> Result<Vec<StandardModelData>, ModelLoadError> {
+    try_new_with(filepath, &LoadOptions::default())
+}
+pub fn try_new_with(filepath: &str, options: &LoadOptions) -> Result<Vec<StandardModelData>, ModelLoadError> {
+    Ok(try_parse_scene(filepath, options)?.models)
+}
+
+/// # try_parse_scene
+///
+/// Loads every node of the file as one model, the same way FBX models are laid out:
+/// `models[i]` and `graph.nodes[i]` are glTF node `i`. Nodes without a mesh come out as
+/// empty models so bones and empties keep their place in the hierarchy.
+/// Animations end up in `Scene::clips`.
+pub fn try_parse_scene(filepath: &str, options: &LoadOptions) -> Result<Scene, ModelLoadError> {
+    let options = options.for_file(filepath);
+    let gltf::Gltf { document, blob } = gltf::Gltf::open(filepath)?;
+    let buffers = gltf::import_buffers(&document, options.base_directory.as_deref(), blob)?;
+
+    let conversion = match &options.target_axes {
+        Some(target) => AxisSystem::conversion(options.source_axes.as_ref().unwrap_or(&GLTF_AXES), target),
+        None => FMat4::identity(),
+    };
+
+    let textures: Vec<Rc<MaterialTexture>> = document.textures().map(|texture| {
+        Rc::new(load_texture(&texture.source(), &buffers, options.base_directory.as_deref()))
+    }).collect();
+    let materials: Vec<Rc<Material>> = document.materials().map(|material| Rc::new(Material::parse_gltf(&material, &textures))).collect();
+    let default_material = Rc::new(Material::named("default"));
+
+    let mut links: Vec<(i64, i64)> = vec![];
+    let nodes = document.nodes().map(|node| {
+        for child in node.children() {
+            links.push((child.index() as i64, node.index() as i64));
+        }
+        let (translation, rotation, scale) = node.transform().decomposed();
+        let [x, y, z, w] = rotation;
+        let transform = ModelTransform {
+            translation: FVec3::new(translation[0], translation[1], translation[2]),
+            rotation: matrix_euler(&quaternion_matrix(Quaternion { x, y, z, w })),
+            scaling: FVec3::new(scale[0], scale[1], scale[2]),
+            ..Default::default()
+        };
+        (node.index() as i64, node_name(&node), transform)
+    }).collect();
+    let graph = SceneGraph::build(nodes, &links, conversion);
+
+    let mut models: Vec<StandardModelData> = graph.nodes.iter().enumerate().map(|(i, node)| StandardModelData {
+        tag: node.tag.clone(),
+        transform: transform::decompose(&graph.local_matrix(i)),
+        parent: node.parent,
+        world: node.world,
+        ..Default::default()
+    }).collect();
+    for node in document.nodes() {
+        let Some(mesh) = node.mesh() else {
+            continue;
+        };
+        let model = &mut models[node.index()];
+        load_mesh(model, &mesh, &buffers, &materials, &default_material, &options)?;
+        model.apply_matrix(&conversion);
+        model.skin = match node.skin() {
+            Some(skin) => Some(load_skin(&skin, &buffers, &graph, model)?),
+            None => None,
+        };
+    }
+    let clips = document.animations().map(|animation| load_animation(&animation, &buffers, &conversion)).collect();
+    Ok(Scene { models, graph, animations: vec![], clips })
+}
+
+fn node_name(node: &gltf::Node) -> String {
+    node.name().map(String::from).unwrap_or_else(|| format!("node{}", node.index()))
+}
+
+/// Embedded images keep their encoded bytes, external ones get resolved like FBX textures.
+fn load_texture(image: &gltf::Image, buffers: &[buffer::Data], directory: Option<&Path>) -> MaterialTexture {
+    let mut texture = MaterialTexture {
+        tag: image.name().map(String::from).unwrap_or_else(|| format!("image{}", image.index())),
+        ..Default::default()
+    };
+    match image.source() {
+        Source::View { view, .. } => {
+            let data = &buffers[view.buffer().index()].0;
+            texture.embedded = Some(Rc::new(data[view.offset()..view.offset() + view.length()].to_vec()));
+        }
+        Source::Uri { uri, .. } => {
+            if let Some(data) = uri.strip_prefix("data:") {
+                texture.embedded = data.split_once(";base64,").and_then(|(_, encoded)| decode_base64(encoded)).map(Rc::new);
+            } else {
+                let file = PathBuf::from(uri.replace("%20", " "));
+                texture.filename = file.clone();
+                texture.relative_filename = file;
+                texture.resolve(directory);
+            }
+        }
+    }
+    texture
+}
+
+/// Standard base64 as used by data uris, `None` when there is anything else in there.
+/// The url safe alphabet works too as long as it isnt mixed with the standard one, and
+/// padding is optional but has to be right when it is there.
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let value = |c: u8| -> Option<u32> {
+        match c {
+            b'A'..=b'Z' => Some((c - b'A') as u32),
+            b'a'..=b'z' => Some((c - b'a') as u32 + 26),
+            b'0'..=b'9' => Some((c - b'0') as u32 + 52),
+            b'+' | b'-' => Some(62),
+            b'/' | b'_' => Some(63),
+            _ => None,
+        }
+    };
+    let unpadded = text.trim_end_matches('=');
+    let padding = text.len() - unpadded.len();
+    let text = unpadded.as_bytes();
+    // A single character left over only has 6 bits, not enough for a byte.
+    if text.len() % 4 == 1 || padding > 2 || (padding > 0 && (text.len() + padding) % 4 != 0) {
+        return None;
+    }
+    let standard = text.iter().any(|c| matches!(c, b'+' | b'/'));
+    let url_safe = text.iter().any(|c| matches!(c, b'-' | b'_'));
+    if standard && url_safe {
+        return None;
+    }
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    for chunk in text.chunks(4) {
+        let mut bits = 0u32;
+        for (i, c) in chunk.iter().enumerate() {
+            bits |= value(*c)? << (18 - i * 6);
+        }
+        let decoded = [(bits >> 16) as u8, (bits >> 8) as u8, bits as u8];
+        bytes.extend_from_slice(&decoded[..chunk.len().saturating_sub(1)]);
+    }
+    Some(bytes)
+}
+
+/// Triangle list indices of a primitive, strips and fans get unrolled and
+/// anything that isnt triangles returns `None`.
+fn triangle_indices(mode: Mode, indices: Vec<u32>) -> Option<Vec<u32>> {
+    match mode {
+        Mode::Triangles => Some(indices),
+        Mode::TriangleStrip => Some((2..indices.len()).flat_map(|i| {
+            // Every other triangle of a strip is wound the other way.
+            if i % 2 == 0 {
+                [indices[i - 2], indices[i - 1], indices[i]]
+            } else {
+                [indices[i - 1], indices[i - 2], indices[i]]
+            }
+        }).collect()),
+        Mode::TriangleFan => Some((2..indices.len()).flat_map(|i| [indices[0], indices[i - 1], indices[i]]).collect()),
+        _ => None,
+    }
+}
+
+/// # load_mesh
+///
+/// Appends every triangle primitive of `mesh` to `model`, one submesh per primitive.
+/// Attributes only some primitives have get filled with defaults for the others.
+fn load_mesh(model: &mut StandardModelData, mesh: &gltf::Mesh, buffers: &[buffer::Data], materials: &[Rc<Material>], default_material: &Rc<Material>, options: &LoadOptions) -> Result<(), ModelLoadError> {
+    let uv_sets = mesh.primitives().map(|primitive| {
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()].0[..]));
+        (0..).take_while(|set| reader.read_tex_coords(*set).is_some()).count()
+    }).max().unwrap_or(0);
+    let has_normals = mesh.primitives().any(|primitive| primitive.get(&gltf::Semantic::Normals).is_some());
+    let has_colors = mesh.primitives().any(|primitive| primitive.get(&gltf::Semantic::Colors(0)).is_some());
+    let has_joints = mesh.primitives().any(|primitive| primitive.get(&gltf::Semantic::Joints(0)).is_some());
+    model.uvs = (0..uv_sets).map(|set| UVSet { name: format!("TEXCOORD_{}", set), uvs: vec![] }).collect();
+    let mut joint_indices: Vec<[u32; 4]> = vec![];
+    let mut joint_weights: Vec<FVec4> = vec![];
+
+    for primitive in mesh.primitives() {
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()].0[..]));
+        let Some(positions) = reader.read_positions() else {
+            continue;
+        };
+        let first_vertex = model.vertices.len() as u32;
+        model.vertices.extend(positions.map(|p| FVec3::new(p[0], p[1], p[2])));
+        let count = model.vertices.len() - first_vertex as usize;
+
+        let indices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..count as u32).collect(),
+        };
+        let Some(indices) = triangle_indices(primitive.mode(), indices) else {
+            model.vertices.truncate(first_vertex as usize);
+            continue;
+        };
+        if let Some(index) = indices.iter().find(|index| **index as usize >= count) {
+            return Err(ModelLoadError::IndexOutOfRange {
+                path: format!("meshes[{}]/primitives[{}]/indices", mesh.index(), primitive.index()),
+                index: *index as usize,
+                len: count,
+            });
+        }
+
+        if has_normals {
+            match reader.read_normals() {
+                Some(normals) => model.normals.extend(normals.map(|n| FVec3::new(n[0], n[1], n[2]))),
+                None => model.normals.resize(model.vertices.len(), FVec3::from(0.0)),
+            }
+        }
+        for (set, uvs) in model.uvs.iter_mut().enumerate() {
+            match reader.read_tex_coords(set as u32) {
+                // glTF already has the origin in the top left, no flip needed.
+                Some(coords) => uvs.uvs.extend(coords.into_f32().map(|uv| FVec2::new(uv[0], uv[1]))),
+                None => uvs.uvs.resize(model.vertices.len(), FVec2::default()),
+            }
+        }
+        if has_colors {
+            match reader.read_colors(0) {
+                Some(colors) => model.colors.extend(colors.into_rgba_f32().map(|c| FVec4::new(c[0], c[1], c[2], c[3]))),
+                None => model.colors.resize(model.vertices.len(), FVec4::new(1.0, 1.0, 1.0, 1.0)),
+            }
+        }
+        if has_joints {
+            match (reader.read_joints(0), reader.read_weights(0)) {
+                (Some(joints), Some(weights)) => {
+                    joint_indices.extend(joints.into_u16().map(|j| [j[0] as u32, j[1] as u32, j[2] as u32, j[3] as u32]));
+                    joint_weights.extend(weights.into_f32().map(|w| FVec4::new(w[0], w[1], w[2], w[3])));
+                }
+                _ => {
+                    joint_indices.resize(model.vertices.len(), [0; 4]);
+                    joint_weights.resize(model.vertices.len(), FVec4::new(0.0, 0.0, 0.0, 0.0));
+                }
+            }
+        }
+
+        let material = primitive.material().index().map(|index| &materials[index]).unwrap_or(default_material);
+        let slot = match model.materials.iter().position(|used| Rc::ptr_eq(used, material)) {
+            Some(slot) => slot,
+            None => {
+                model.materials.push(material.clone());
+                model.materials.len() - 1
+            }
+        };
+        model.submeshes.push(SubMesh {
+            material: slot,
+            first_index: model.indices.len() as u32,
+            index_count: indices.len() as u32,
+        });
+        model.indices.extend(indices.iter().map(|index| index + first_vertex));
+    }
+    // glTF vertices are not welded per control point, every vertex is its own.
+    model.control_points = (0..model.vertices.len() as u32).collect();
+    if has_joints {
+        model.skin = Some(Skin { joints: vec![], joint_indices, joint_weights });
+    }
+    if !has_normals {
+        generate_normals(model, options.missing_normals);
+    }
+    Ok(())
+}
+
+/// glTF says a mesh without normals should be flat shaded, `LoadOptions` still gets the final say.
+fn generate_normals(model: &mut StandardModelData, mode: GenerateNormals) {
+    match mode {
+        GenerateNormals::Skip => {}
+        GenerateNormals::Smooth => {
+            let mut normals = vec![FVec3::from(0.0); model.vertices.len()];
+            for triangle in model.indices.chunks_exact(3) {
+                let [a, b, c] = [0, 1, 2].map(|i| model.vertices[triangle[i] as usize]);
+                let normal = (b - a).cross(c - a);
+                for index in triangle {
+                    normals[*index as usize] += normal;
+                }
+            }
+            model.normals = normals.into_iter().map(safe_normalize).collect();
+        }
+        GenerateNormals::Flat => {
+            // Every triangle needs its own corners, so the mesh gets unwelded first.
+            let corners = std::mem::take(&mut model.indices);
+            let pick = |values: &[FVec3]| -> Vec<FVec3> { corners.iter().map(|i| values[*i as usize]).collect() };
+            let vertices = pick(&model.vertices);
+            model.normals = vertices.chunks_exact(3).flat_map(|t| {
+                let normal = safe_normalize((t[1] - t[0]).cross(t[2] - t[0]));
+                [normal; 3]
+            }).collect();
+            for set in model.uvs.iter_mut() {
+                set.uvs = corners.iter().map(|i| set.uvs[*i as usize]).collect();
+            }
+            if !model.colors.is_empty() {
+                model.colors = corners.iter().map(|i| model.colors[*i as usize]).collect();
+            }
+            if let Some(skin) = model.skin.as_mut() {
+                skin.joint_indices = corners.iter().map(|i| skin.joint_indices[*i as usize]).collect();
+                skin.joint_weights = corners.iter().map(|i| skin.joint_weights[*i as usize]).collect();
+            }
+            model.control_points = corners.clone();
+            model.vertices = vertices;
+            model.indices = (0..corners.len() as u32).collect();
+        }
+    }
+}
+
+/// Builds the joints of a skin. The per vertex joints and weights are already on
+/// `model.skin` from `load_mesh`, glTF joint indices point into `skin.joints()` in order.
+fn load_skin(skin: &gltf::Skin, buffers: &[buffer::Data], graph: &SceneGraph, model: &StandardModelData) -> Result<Skin, ModelLoadError> {
+    let conversion = graph.conversion;
+    let inverse_conversion = affine_inverse(&conversion);
+    let reader = skin.reader(|buffer| Some(&buffers[buffer.index()].0[..]));
+    let inverse_binds: Vec<FMat4> = match reader.read_inverse_bind_matrices() {
+        Some(matrices) => matrices.map(|m| conversion * to_fmat4(m) * inverse_conversion).collect(),
+        None => vec![],
+    };
+    let mut joints: Vec<Joint> = skin.joints().enumerate().map(|(i, node)| {
+        let inverse_bind = inverse_binds.get(i).copied().unwrap_or_else(FMat4::identity);
+        Joint {
+            tag: node_name(&node),
+            node: node.index(),
+            parent: None,
+            bind_pose: affine_inverse(&inverse_bind),
+            inverse_bind,
+        }
+    }).collect();
+    let joint_of_node: HashMap<usize, usize> = joints.iter().enumerate().map(|(i, joint)| (joint.node, i)).collect();
+    for joint in joints.iter_mut() {
+        let mut current = graph.nodes[joint.node].parent;
+        while let Some(node) = current {
+            if let Some(parent) = joint_of_node.get(&node) {
+                joint.parent = Some(*parent);
+                break;
+            }
+            current = graph.nodes[node].parent;
+        }
+    }
+    let mut result = model.skin.clone().unwrap_or_default();
+    if let Some(index) = result.joint_indices.iter().flatten().find(|index| **index as usize >= joints.len()) {
+        return Err(ModelLoadError::IndexOutOfRange {
+            path: format!("skins[{}]/joints", skin.index()),
+            index: *index as usize,
+            len: joints.len(),
+        });
+    }
+    result.joints = joints;
+    Ok(result)
+}
+
+/// glTF matrices are column major arrays of columns, same as `FMat4`.
+fn to_fmat4(m: [[f32; 4]; 4]) -> FMat4 {
+    let mut result = FMat4::identity();
+    result.x.x = m[0][0]; result.x.y = m[0][1]; result.x.z = m[0][2]; result.x.w = m[0][3];
+    result.y.x = m[1][0]; result.y.y = m[1][1]; result.y.z = m[1][2]; result.y.w = m[1][3];
+    result.z.x = m[2][0]; result.z.y = m[2][1]; result.z.z = m[2][2]; result.z.w = m[2][3];
+    result.w.x = m[3][0]; result.w.y = m[3][1]; result.w.z = m[3][2]; result.w.w = m[3][3];
+    result
+}
+
+/// # load_animation
+///
+/// Keys are taken over as they are, only moved into the target axis system. Every conversion
+/// `AxisSystem` makes is a rotation or mirror times a uniform scale, so it can be applied to
+/// values and cubic tangents alike without resampling.
+fn load_animation(animation: &gltf::Animation, buffers: &[buffer::Data], conversion: &FMat4) -> AnimationClip {
+    let scale = (conversion.x.x * conversion.x.x + conversion.x.y * conversion.x.y + conversion.x.z * conversion.x.z).sqrt();
+    let mut rotation_basis = *conversion;
+    for column in [&mut rotation_basis.x, &mut rotation_basis.y, &mut rotation_basis.z] {
+        column.x /= scale;
+        column.y /= scale;
+        column.z /= scale;
+    }
+    let mirrored = transform_vector(&rotation_basis, FVec3::new(1.0, 0.0, 0.0))
+        .cross(transform_vector(&rotation_basis, FVec3::new(0.0, 1.0, 0.0)))
+        .dot(&transform_vector(&rotation_basis, FVec3::new(0.0, 0.0, 1.0))) < 0.0;
+    let translation = |v: [f32; 3]| transform_vector(conversion, FVec3::new(v[0], v[1], v[2]));
+    let scaling = |v: [f32; 3]| {
+        let axes = transform_vector(&rotation_basis, FVec3::new(v[0], v[1], v[2]));
+        FVec3::new(axes.x.abs(), axes.y.abs(), axes.z.abs())
+    };
+    // Mirroring flips the direction of every rotation, which for a quaternion means flipping the axis part.
+    let rotation = |q: [f32; 4]| {
+        let axis = transform_vector(&rotation_basis, FVec3::new(q[0], q[1], q[2]));
+        let sign = if mirrored { -1.0 } else { 1.0 };
+        Quaternion { x: axis.x * sign, y: axis.y * sign, z: axis.z * sign, w: q[3] }
+    };
+
+    let mut tracks: Vec<Track> = vec![];
+    for channel in animation.channels() {
+        let reader = channel.reader(|buffer| Some(&buffers[buffer.index()].0[..]));
+        let (Some(times), Some(outputs)) = (reader.read_inputs(), reader.read_outputs()) else {
+            continue;
+        };
+        let times: Vec<f32> = times.collect();
+        let interpolation = match channel.sampler().interpolation() {
+            GltfInterpolation::Step => Interpolation::Step,
+            GltfInterpolation::Linear => Interpolation::Linear,
+            GltfInterpolation::CubicSpline => Interpolation::Cubic,
+        };
+        let node = channel.target().node().index();
+        let track = match tracks.iter().position(|track| track.node == node) {
+            Some(track) => &mut tracks[track],
+            None => {
+                tracks.push(Track { node, translation: None, rotation: None, scale: None });
+                tracks.last_mut().unwrap()
+            }
+        };
+        match outputs {
+            ReadOutputs::Translations(values) => track.translation = Some(keyframes(interpolation, times, values.map(translation).collect())),
+            ReadOutputs::Rotations(values) => track.rotation = Some(keyframes(interpolation, times, values.into_f32().map(rotation).collect())),
+            ReadOutputs::Scales(values) => track.scale = Some(keyframes(interpolation, times, values.map(scaling).collect())),
+            // Morph target weights arent imported from glTF.
+            ReadOutputs::MorphTargetWeights(_) => {}
+        }
+    }
+    let name = animation.name().map(String::from).unwrap_or_else(|| format!("animation{}", animation.index()));
+    AnimationClip::new(&name, tracks)
+}
+
+/// Cubic spline outputs come as `in tangent, value, out tangent` triplets per key.
+fn keyframes<T: Keyframe>(interpolation: Interpolation, times: Vec<f32>, values: Vec<T>) -> Keyframes<T> {
+    if interpolation != Interpolation::Cubic {
+        return Keyframes::new(interpolation, times, values);
+    }
+    let mut keys = Keyframes::new(interpolation, times, vec![]);
+    for triplet in values.chunks_exact(3) {
+        keys.in_tangents.push(triplet[0]);
+        keys.values.push(triplet[1]);
+        keys.out_tangents.push(triplet[2]);
+    }
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One triangle, three f32 positions followed by three u16 indices.
+    const TRIANGLE: &str = "AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAAABAAIA";
+
+    #[test]
+    fn base64_decodes_and_rejects_garbage() {
+        assert_eq!(decode_base64("aGVsbG8="), Some(b"hello".to_vec()));
+        assert_eq!(decode_base64("aGVsbG8"), Some(b"hello".to_vec()));
+        assert_eq!(decode_base64("aGk="), Some(b"hi".to_vec()));
+        assert_eq!(decode_base64(""), Some(vec![]));
+        assert_eq!(decode_base64("-_-_"), decode_base64("+/+/"));
+        // mixed alphabets, a dangling character, too much or misplaced padding
+        assert_eq!(decode_base64("+_+_"), None);
+        assert_eq!(decode_base64("aGVsbG8=a"), None);
+        assert_eq!(decode_base64("aGVsb"), None);
+        assert_eq!(decode_base64("aGk=="), None);
+        assert_eq!(decode_base64("aGVsbG8==="), None);
+        assert_eq!(decode_base64("aG=k"), None);
+    }
+
+    #[test]
+    fn embedded_gltf_loads() {
+        // The same triangle three times in one mesh, the middle one with another material.
+        let primitive = |material: usize| format!(r#"{{"attributes":{{"POSITION":0}},"indices":1,"material":{}}}"#, material);
+        let json = format!(
+            r#"{{
+                "asset": {{"version": "2.0"}},
+                "scene": 0,
+                "scenes": [{{"nodes": [0]}}],
+                "nodes": [
+                    {{"name": "parent", "translation": [1, 2, 3], "children": [1]}},
+                    {{"name": "child", "mesh": 0, "rotation": [0, 0, 0.70710677, 0.70710677], "scale": [2, 2, 2]}}
+                ],
+                "meshes": [{{"primitives": [{}, {}, {}]}}],
+                "materials": [{{"name": "red"}}, {{"name": "blue"}}],
+                "buffers": [{{"byteLength": 42, "uri": "data:application/octet-stream;base64,{}"}}],
+                "bufferViews": [
+                    {{"buffer": 0, "byteOffset": 0, "byteLength": 36, "target": 34962}},
+                    {{"buffer": 0, "byteOffset": 36, "byteLength": 6, "target": 34963}}
+                ],
+                "accessors": [
+                    {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0]}},
+                    {{"bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR"}}
+                ]
+            }}"#,
+            primitive(0), primitive(1), primitive(0), TRIANGLE,
+        );
+        let path = std::env::temp_dir().join(format!("yum_mocha_triangle_{}.gltf", std::process::id()));
+        std::fs::write(&path, json).unwrap();
+        let options = LoadOptions { target_axes: None, missing_normals: GenerateNormals::Skip, ..Default::default() };
+        let models = try_new_with(path.to_str().unwrap(), &options);
+        std::fs::remove_file(&path).ok();
+        let models = models.unwrap();
+
+        assert_eq!(models.len(), 2);
+        let (parent, child) = (&models[0], &models[1]);
+        assert_eq!((parent.tag.as_str(), child.tag.as_str()), ("parent", "child"));
+        assert_eq!(child.parent, Some(0));
+        assert!(parent.indices.is_empty());
+        let close = |a: FVec3, b: FVec3| (a - b).dot(&(a - b)) < 1e-8;
+        assert!(close(parent.transform.translation, FVec3::new(1.0, 2.0, 3.0)));
+        assert!(close(child.transform.scale, FVec3::new(2.0, 2.0, 2.0)));
+        assert!(close(transform_vector(&quaternion_matrix(child.transform.rotation), FVec3::new(1.0, 0.0, 0.0)), FVec3::new(0.0, 1.0, 0.0)));
+        // x gets scaled to 2, turned onto y and moved by the parent.
+        assert!(close(transform::transform_point(&child.world, FVec3::new(1.0, 0.0, 0.0)), FVec3::new(1.0, 4.0, 3.0)));
+
+        assert_eq!(child.vertices.len(), 9);
+        assert_eq!(child.vertices[4], FVec3::new(1.0, 0.0, 0.0));
+        assert_eq!(child.indices, (0..9).collect::<Vec<u32>>());
+        assert_eq!(child.submeshes, vec![
+            SubMesh { material: 0, first_index: 0, index_count: 3 },
+            SubMesh { material: 1, first_index: 3, index_count: 3 },
+            SubMesh { material: 0, first_index: 6, index_count: 3 },
+        ]);
+        let tags: Vec<&str> = child.materials.iter().map(|material| material.tag()).collect();
+        assert_eq!(tags, ["red", "blue"]);
+    }
+}
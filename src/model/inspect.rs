@@ -0,0 +1,459 @@
+#![allow(unused)]
+//! Looking at what is actually inside of an FBX file. Walks the node tree into plain
+//! structs that can be printed as indented text or written out as JSON, with long arrays
+//! cut short so a 50k vertex mesh doesnt bury everything else.
+use std::fmt::{self, Write};
+
+use fbxcel_dom::fbxcel::{tree::v7400::NodeHandle, low::v7400::AttributeValue};
+
+use super::error::ModelLoadError;
+use super::model_loader::{NodeAttributes, StandardModelData};
+
+/// # InspectValue
+///
+/// One attribute of a node. Arrays only keep the first `InspectOptions::max_array`
+/// values in `head`, `len` is the real length.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InspectValue {
+    Bool(bool),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    /// `Name\0\x01Class` object names are turned into `Class::Name` like ASCII files write them.
+    String(String),
+    Binary { len: usize, head: Vec<u8> },
+    Array { kind: &'static str, len: usize, head: Vec<InspectValue> },
+}
+impl InspectValue {
+    fn from_attribute(value: &AttributeValue, max_array: usize) -> Self {
+        fn array<T: Copy>(kind: &'static str, values: &[T], max_array: usize, convert: fn(T) -> InspectValue) -> InspectValue {
+            InspectValue::Array { kind, len: values.len(), head: values.iter().take(max_array).map(|v| convert(*v)).collect() }
+        }
+        match value {
+            AttributeValue::Bool(v) => Self::Bool(*v),
+            AttributeValue::I16(v) => Self::I16(*v),
+            AttributeValue::I32(v) => Self::I32(*v),
+            AttributeValue::I64(v) => Self::I64(*v),
+            AttributeValue::F32(v) => Self::F32(*v),
+            AttributeValue::F64(v) => Self::F64(*v),
+            AttributeValue::ArrBool(v) => array("bool", v, max_array, Self::Bool),
+            AttributeValue::ArrI32(v) => array("i32", v, max_array, Self::I32),
+            AttributeValue::ArrI64(v) => array("i64", v, max_array, Self::I64),
+            AttributeValue::ArrF32(v) => array("f32", v, max_array, Self::F32),
+            AttributeValue::ArrF64(v) => array("f64", v, max_array, Self::F64),
+            AttributeValue::Binary(v) => Self::Binary { len: v.len(), head: v.iter().take(max_array).copied().collect() },
+            AttributeValue::String(v) => match v.split_once("\u{0}\u{1}") {
+                Some((name, class)) => Self::String(format!("{}::{}", class, name)),
+                None => Self::String(v.clone()),
+            },
+        }
+    }
+    /// Type name, `i32`, `f64[]` and so on.
+    pub fn kind(&self) -> String {
+        match self {
+            Self::Bool(_) => "bool".into(),
+            Self::I16(_) => "i16".into(),
+            Self::I32(_) => "i32".into(),
+            Self::I64(_) => "i64".into(),
+            Self::F32(_) => "f32".into(),
+            Self::F64(_) => "f64".into(),
+            Self::String(_) => "string".into(),
+            Self::Binary { .. } => "binary".into(),
+            Self::Array { kind, .. } => format!("{}[]", kind),
+        }
+    }
+    fn write_json(&self, out: &mut String) {
+        let result = match self {
+            Self::Bool(v) => write!(out, "{}", v),
+            Self::I16(v) => write!(out, "{}", v),
+            Self::I32(v) => write!(out, "{}", v),
+            Self::I64(v) => write!(out, "{}", v),
+            Self::F32(v) => write_json_float(out, *v as f64),
+            Self::F64(v) => write_json_float(out, *v),
+            Self::String(v) => write_json_string(out, v),
+            Self::Binary { len, head } => {
+                write!(out, "{{\"type\":\"binary\",\"len\":{},\"head\":{:?}}}", len, head)
+            }
+            Self::Array { len, head, .. } => {
+                write!(out, "{{\"type\":\"{}\",\"len\":{},\"head\":[", self.kind(), len).unwrap();
+                for (i, value) in head.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    value.write_json(out);
+                }
+                write!(out, "]}}")
+            }
+        };
+        result.unwrap();
+    }
+}
+impl fmt::Display for InspectValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bool(v) => write!(f, "{}", if *v { "T" } else { "F" }),
+            Self::I16(v) => write!(f, "{}", v),
+            Self::I32(v) => write!(f, "{}", v),
+            Self::I64(v) => write!(f, "{}", v),
+            Self::F32(v) => write!(f, "{}", v),
+            Self::F64(v) => write!(f, "{}", v),
+            Self::String(v) => write!(f, "{:?}", v),
+            Self::Binary { len, head } => {
+                write!(f, "<{} bytes:", len)?;
+                for byte in head.iter() {
+                    write!(f, " {:02x}", byte)?;
+                }
+                if *len > head.len() {
+                    write!(f, " ...")?;
+                }
+                write!(f, ">")
+            }
+            Self::Array { kind, len, head } => {
+                write!(f, "{}[{}] {{", kind, len)?;
+                for (i, value) in head.iter().enumerate() {
+                    write!(f, "{}{}", if i > 0 { ", " } else { " " }, value)?;
+                }
+                if *len > head.len() {
+                    write!(f, ", ...")?;
+                }
+                write!(f, " }}")
+            }
+        }
+    }
+}
+
+fn write_json_float(out: &mut String, value: f64) -> fmt::Result {
+    // JSON has no NaN or infinity.
+    match value.is_finite() {
+        true => write!(out, "{}", value),
+        false => write!(out, "null"),
+    }
+}
+fn write_json_string(out: &mut String, value: &str) -> fmt::Result {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32)?,
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    Ok(())
+}
+
+/// Which nodes `inspect` hands back.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeFilter {
+    /// Every node with exactly this name, wherever it is.
+    Name(String),
+    /// Nodes whose path from the root matches, `/` separated. `*` matches any one name,
+    /// so `Objects/*/Properties70` finds the properties of every object.
+    Path(String),
+}
+impl NodeFilter {
+    pub fn matches(&self, name: &str, path: &str) -> bool {
+        match self {
+            NodeFilter::Name(wanted) => name == wanted,
+            NodeFilter::Path(pattern) => {
+                let pattern: Vec<&str> = pattern.trim_matches('/').split('/').collect();
+                let path: Vec<&str> = path.split('/').collect();
+                pattern.len() == path.len() && pattern.iter().zip(path.iter()).all(|(p, n)| *p == "*" || p == n)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InspectOptions {
+    /// How many values of an array are kept.
+    pub max_array: usize,
+    /// How many levels below a returned node get walked, `None` for all of them.
+    pub max_depth: Option<usize>,
+    pub filter: Option<NodeFilter>,
+}
+impl Default for InspectOptions {
+    fn default() -> Self {
+        Self { max_array: 8, max_depth: None, filter: None }
+    }
+}
+
+/// # InspectNode
+///
+/// A node with its attributes and children. `path` is the path from the root like
+/// `Objects/Geometry/Vertices`, the same thing `ModelLoadError` reports.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InspectNode {
+    pub name: String,
+    pub path: String,
+    pub attributes: Vec<InspectValue>,
+    pub children: Vec<InspectNode>,
+    /// Children that were left out because of `max_depth`.
+    pub skipped_children: usize,
+}
+impl InspectNode {
+    pub fn from_handle(node: &NodeHandle, options: &InspectOptions) -> Self {
+        Self::walk(node, options, 0)
+    }
+    fn walk(node: &NodeHandle, options: &InspectOptions, depth: usize) -> Self {
+        let descend = options.max_depth.map_or(true, |max| depth < max);
+        let children: Vec<InspectNode> = match descend {
+            true => node.children().map(|child| Self::walk(&child, options, depth + 1)).collect(),
+            false => vec![],
+        };
+        Self {
+            name: node.name().into(),
+            path: node.path(),
+            attributes: node.attributes().iter().map(|value| InspectValue::from_attribute(value, options.max_array)).collect(),
+            skipped_children: if descend { 0 } else { node.children().count() },
+            children,
+        }
+    }
+    /// Indented like ASCII FBX, two spaces per level.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        self.write_text(&mut out, 0);
+        out
+    }
+    fn write_text(&self, out: &mut String, depth: usize) {
+        let indent = "  ".repeat(depth);
+        let attributes: Vec<String> = self.attributes.iter().map(|value| value.to_string()).collect();
+        write!(out, "{}{}: {}", indent, self.name, attributes.join(", ")).unwrap();
+        match (self.children.is_empty(), self.skipped_children) {
+            (true, 0) => out.push('\n'),
+            (true, skipped) => writeln!(out, " {{ ... {} children }}", skipped).unwrap(),
+            (false, _) => {
+                out.push_str(" {\n");
+                for child in self.children.iter() {
+                    child.write_text(out, depth + 1);
+                }
+                writeln!(out, "{}}}", indent).unwrap();
+            }
+        }
+    }
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        self.write_json(&mut out);
+        out
+    }
+    fn write_json(&self, out: &mut String) {
+        out.push_str("{\"name\":");
+        write_json_string(out, &self.name).unwrap();
+        out.push_str(",\"path\":");
+        write_json_string(out, &self.path).unwrap();
+        out.push_str(",\"attributes\":[");
+        for (i, value) in self.attributes.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            value.write_json(out);
+        }
+        out.push_str("],\"children\":[");
+        for (i, child) in self.children.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            child.write_json(out);
+        }
+        write!(out, "],\"skipped_children\":{}}}", self.skipped_children).unwrap();
+    }
+}
+impl fmt::Display for InspectNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_text())
+    }
+}
+
+/// # inspect
+///
+/// Without a filter this is every child of `root`. With one it is every node that
+/// matches, each with its own subtree, so `NodeFilter::Name("Geometry".into())` gives
+/// back all the geometries no matter how deep they are.
+pub fn inspect(root: &NodeHandle, options: &InspectOptions) -> Vec<InspectNode> {
+    let mut found = vec![];
+    match &options.filter {
+        None => found.extend(root.children().map(|child| InspectNode::from_handle(&child, options))),
+        Some(filter) => collect_matches(root, filter, options, &mut found),
+    }
+    found
+}
+fn collect_matches(node: &NodeHandle, filter: &NodeFilter, options: &InspectOptions, found: &mut Vec<InspectNode>) {
+    for child in node.children() {
+        if filter.matches(child.name(), &child.path()) {
+            found.push(InspectNode::from_handle(&child, options));
+        } else {
+            collect_matches(&child, filter, options, found);
+        }
+    }
+}
+/// Loads a binary or ASCII FBX file and inspects its root.
+pub fn inspect_file(filepath: &str, options: &InspectOptions) -> Result<Vec<InspectNode>, ModelLoadError> {
+    let document = StandardModelData::load_document(filepath)?;
+    Ok(inspect(&document.tree().root(), options))
+}
+pub fn to_text(nodes: &[InspectNode]) -> String {
+    nodes.iter().map(InspectNode::to_text).collect()
+}
+/// A JSON array with one object per node.
+pub fn to_json(nodes: &[InspectNode]) -> String {
+    let mut out = String::from("[");
+    for (i, node) in nodes.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        node.write_json(&mut out);
+    }
+    out.push(']');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cube(options: &InspectOptions) -> Vec<InspectNode> {
+        inspect_file(&format!("{}/cube.fbx", env!("CARGO_MANIFEST_DIR")), options).unwrap()
+    }
+    /// Just enough of a JSON parser to say whether `text` is one valid value, gives back
+    /// where the value starts at `i` ended.
+    fn json_value(text: &[u8], mut i: usize) -> Option<usize> {
+        let skip = |mut i: usize| {
+            while i < text.len() && b" \t\n\r".contains(&text[i]) {
+                i += 1;
+            }
+            i
+        };
+        i = skip(i);
+        let end = match *text.get(i)? {
+            b'{' | b'[' => {
+                let close = if text[i] == b'{' { b'}' } else { b']' };
+                i = skip(i + 1);
+                if text.get(i) == Some(&close) {
+                    return Some(i + 1);
+                }
+                loop {
+                    i = skip(i);
+                    if close == b'}' {
+                        i = skip(json_value(text, i).filter(|end| text[i] == b'"' && *end > i)?);
+                        if text.get(i) != Some(&b':') {
+                            return None;
+                        }
+                        i += 1;
+                    }
+                    i = skip(json_value(text, i)?);
+                    match *text.get(i)? {
+                        b',' => i += 1,
+                        c if c == close => break i + 1,
+                        _ => return None,
+                    }
+                }
+            }
+            b'"' => {
+                i += 1;
+                loop {
+                    match *text.get(i)? {
+                        b'"' => break i + 1,
+                        b'\\' => {
+                            match *text.get(i + 1)? {
+                                b'u' if text.get(i + 2..i + 6)?.iter().all(u8::is_ascii_hexdigit) => i += 6,
+                                b'"' | b'\\' | b'/' | b'b' | b'f' | b'n' | b'r' | b't' => i += 2,
+                                _ => return None,
+                            }
+                        }
+                        c if c < 0x20 => return None,
+                        _ => i += 1,
+                    }
+                }
+            }
+            b't' if text[i..].starts_with(b"true") => i + 4,
+            b'f' if text[i..].starts_with(b"false") => i + 5,
+            b'n' if text[i..].starts_with(b"null") => i + 4,
+            _ => {
+                let start = i;
+                while i < text.len() && b"+-0123456789.eE".contains(&text[i]) {
+                    i += 1;
+                }
+                std::str::from_utf8(&text[start..i]).ok()?.parse::<f64>().ok()?;
+                i
+            }
+        };
+        Some(end)
+    }
+    fn is_json(text: &str) -> bool {
+        json_value(text.as_bytes(), 0).map_or(false, |end| end == text.len())
+    }
+
+    #[test]
+    fn filters_find_nodes() {
+        let geometries = cube(&InspectOptions { filter: Some(NodeFilter::Name("Geometry".into())), ..Default::default() });
+        assert_eq!(geometries.len(), 1);
+        assert_eq!(geometries[0].path, "Objects/Geometry");
+        // Binary `Name\0\x01Class` comes out the way ASCII files write it.
+        assert_eq!(geometries[0].attributes[1], InspectValue::String("Geometry::Cube.001".into()));
+
+        let properties = cube(&InspectOptions { filter: Some(NodeFilter::Path("Objects/*/Properties70".into())), ..Default::default() });
+        let paths: Vec<&str> = properties.iter().map(|node| node.path.as_str()).collect();
+        assert_eq!(paths, ["Objects/Geometry/Properties70", "Objects/Model/Properties70", "Objects/Material/Properties70"]);
+        assert!(!NodeFilter::Path("Objects/*".into()).matches("Properties70", "Objects/Model/Properties70"));
+    }
+
+    #[test]
+    fn arrays_and_depth_get_cut_short() {
+        let vertices = cube(&InspectOptions { max_array: 4, filter: Some(NodeFilter::Name("Vertices".into())), ..Default::default() });
+        match &vertices[0].attributes[0] {
+            InspectValue::Array { len, head, .. } => {
+                assert_eq!(*len, 24);
+                assert_eq!(head.len(), 4);
+            }
+            other => panic!("Vertices is {:?}", other),
+        }
+        assert!(vertices[0].to_text().contains(", ..."));
+
+        let geometry = cube(&InspectOptions { max_depth: Some(0), filter: Some(NodeFilter::Name("Geometry".into())), ..Default::default() });
+        assert!(geometry[0].children.is_empty());
+        assert_eq!(geometry[0].skipped_children, 9);
+        let objects = cube(&InspectOptions { max_depth: Some(1), filter: Some(NodeFilter::Name("Objects".into())), ..Default::default() });
+        assert_eq!(objects[0].children.len(), 3);
+        assert!(objects[0].children.iter().all(|child| child.children.is_empty() && child.skipped_children > 0));
+    }
+
+    #[test]
+    fn json_escapes_strings_and_floats() {
+        let node = InspectNode {
+            name: "Quote\"Back\\slash\nLine\u{1}".into(),
+            path: "a/b".into(),
+            attributes: vec![
+                InspectValue::String("Cube\u{0}\u{1}Model".into()),
+                InspectValue::F64(f64::NAN),
+                InspectValue::F32(f32::INFINITY),
+                InspectValue::F64(1.5),
+                InspectValue::I64(-3),
+                InspectValue::Bool(true),
+                InspectValue::Array { kind: "f64", len: 10, head: vec![InspectValue::F64(0.0), InspectValue::F64(f64::NEG_INFINITY)] },
+                InspectValue::Binary { len: 3, head: vec![1, 2] },
+            ],
+            children: vec![],
+            skipped_children: 2,
+        };
+        let json = node.to_json();
+        assert_eq!(json, concat!(
+            r#"{"name":"Quote\"Back\\slash\nLine\u0001","path":"a/b","attributes":["Cube\u0000\u0001Model",null,null,1.5,-3,true,"#,
+            r#"{"type":"f64[]","len":10,"head":[0,null]},{"type":"binary","len":3,"head":[1, 2]}],"children":[],"skipped_children":2}"#,
+        ));
+        assert!(is_json(&json));
+        assert!(!is_json("{\"a\":NaN}"));
+    }
+
+    #[test]
+    fn whole_file_is_valid_json() {
+        let json = to_json(&cube(&InspectOptions::default()));
+        assert!(json.starts_with('[') && json.len() > 1000);
+        assert!(is_json(&json));
+    }
+}
@@ -0,0 +1,357 @@
+#![allow(unused)]
+use std::{path::{Path, PathBuf}, rc::Rc};
+
+use drowsed_math::FVec3;
+use fbxcel_dom::fbxcel::tree::v7400::NodeHandle;
+
+use super::error::ModelLoadError;
+use super::mesh_cache::{CacheReader, CacheWriter};
+use super::model_loader::{NodeAttributes, property_fvec3};
+
+/// # MaterialTexture
+///
+/// A `Texture` object together with the file of the `Video` it points at.
+/// FBX stores the absolute path from the machine that exported it and a path relative
+/// to the FBX file, `path` is whichever of the two actually exists on this machine.
+#[derive(Default, Debug, Clone)]
+pub struct MaterialTexture {
+    pub tag: String,
+    pub filename: PathBuf,
+    pub relative_filename: PathBuf,
+    pub path: Option<PathBuf>,
+    /// Encoded image (png, jpeg, ...) for textures that live inside the model file,
+    /// hand it to `ImageTexture::from_memory` instead of using the path.
+    pub embedded: Option<Rc<Vec<u8>>>,
+}
+impl MaterialTexture {
+    /// Looks for the texture next to the FBX file first and falls back to the absolute path.
+    pub fn resolve(&mut self, directory: Option<&Path>) {
+        let relative = directory.map(|dir| dir.join(&self.relative_filename));
+        self.path = [relative, Some(self.filename.clone())].into_iter().flatten().find(|path| {
+            !path.as_os_str().is_empty() && path.is_file()
+        });
+    }
+    /// The resolved path, or the absolute one from the file if nothing was found.
+    /// Can be handed to `ImageTexture::new` after a `to_str`.
+    pub fn best_path(&self) -> &Path {
+        self.path.as_deref().unwrap_or(&self.filename)
+    }
+    pub(crate) fn parse_texture(node: &NodeHandle) -> Result<(i64, MaterialTexture), ModelLoadError> {
+        let mut texture = MaterialTexture {
+            tag: node.attr_str(1)?.into(),
+            ..Default::default()
+        };
+        let collection_id = node.attr_i64(0)?;
+        for child in node.children() {
+            match child.name() {
+                "FileName" => texture.filename = child.attr_str(0)?.into(),
+                "RelativeFilename" => texture.relative_filename = child.attr_str(0)?.into(),
+                _ => {}
+            }
+        }
+        Ok((collection_id, texture))
+    }
+    /// `Video` objects hold the same file names as the textures that use them. Some
+    /// exporters only write them on the video so they get copied over when the texture has none.
+    pub(crate) fn parse_video(node: &NodeHandle) -> Result<(i64, MaterialTexture), ModelLoadError> {
+        let mut video = MaterialTexture {
+            tag: node.attr_str(1)?.into(),
+            ..Default::default()
+        };
+        let collection_id = node.attr_i64(0)?;
+        for child in node.children() {
+            match child.name() {
+                "Filename" | "FileName" => video.filename = child.attr_str(0)?.into(),
+                "RelativeFilename" => video.relative_filename = child.attr_str(0)?.into(),
+                _ => {}
+            }
+        }
+        Ok((collection_id, video))
+    }
+    pub(crate) fn fill_from_video(&mut self, video: &MaterialTexture) {
+        if self.filename.as_os_str().is_empty() {
+            self.filename = video.filename.clone();
+        }
+        if self.relative_filename.as_os_str().is_empty() {
+            self.relative_filename = video.relative_filename.clone();
+        }
+    }
+}
+
+/// # Material
+///
+/// Surface properties of a `Material` object. Colors already have their `*Factor`
+/// multiplied in. Textures are stored under the name of the property they replace,
+/// for example `DiffuseColor` or `NormalMap`.
+#[derive(Debug, Clone)]
+pub struct Material {
+    tag: String,
+    diffuse: FVec3,
+    diffuse_factor: f32,
+    specular: FVec3,
+    specular_factor: f32,
+    shininess: f32,
+    emissive: FVec3,
+    emissive_factor: f32,
+    opacity: Option<f32>,
+    transparency_factor: f32,
+    metallic: f32,
+    roughness: f32,
+    textures: Vec<(String, Rc<MaterialTexture>)>,
+}
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            tag: String::new(),
+            diffuse: FVec3::from(0.0),
+            diffuse_factor: 1.0,
+            specular: FVec3::from(0.0),
+            specular_factor: 1.0,
+            shininess: 0.0,
+            emissive: FVec3::from(0.0),
+            emissive_factor: 1.0,
+            opacity: None,
+            transparency_factor: 0.0,
+            metallic: 0.0,
+            roughness: 1.0,
+            textures: vec![],
+        }
+    }
+}
+impl Material {
+    /// Default material with a name, for formats that reference materials that dont exist.
+    pub fn named(tag: &str) -> Self {
+        Self { tag: tag.into(), ..Default::default() }
+    }
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+    pub fn diffuse(&self) -> FVec3 {
+        self.diffuse * self.diffuse_factor
+    }
+    pub fn specular(&self) -> FVec3 {
+        self.specular * self.specular_factor
+    }
+    /// Phong exponent.
+    pub fn shininess(&self) -> f32 {
+        self.shininess
+    }
+    pub fn emissive(&self) -> FVec3 {
+        self.emissive * self.emissive_factor
+    }
+    /// 1.0 is fully opaque. Uses `Opacity` when the exporter wrote it and `TransparencyFactor` otherwise.
+    pub fn opacity(&self) -> f32 {
+        self.opacity.unwrap_or(1.0 - self.transparency_factor).clamp(0.0, 1.0)
+    }
+    /// PBR metalness, only glTF materials set it. Everything else is a dielectric.
+    pub fn metallic(&self) -> f32 {
+        self.metallic
+    }
+    /// PBR roughness, fully rough unless the file says otherwise.
+    pub fn roughness(&self) -> f32 {
+        self.roughness
+    }
+    /// Texture connected to the given material property.
+    pub fn texture(&self, property: &str) -> Option<&Rc<MaterialTexture>> {
+        self.textures.iter().find(|(name, _)| name == property).map(|(_, texture)| texture)
+    }
+    pub fn textures(&self) -> &[(String, Rc<MaterialTexture>)] {
+        &self.textures
+    }
+    pub fn diffuse_texture(&self) -> Option<&Rc<MaterialTexture>> {
+        self.texture("DiffuseColor").or_else(|| self.texture("Diffuse"))
+    }
+    pub fn specular_texture(&self) -> Option<&Rc<MaterialTexture>> {
+        self.texture("SpecularColor").or_else(|| self.texture("SpecularFactor"))
+    }
+    pub fn emissive_texture(&self) -> Option<&Rc<MaterialTexture>> {
+        self.texture("EmissiveColor").or_else(|| self.texture("EmissiveFactor"))
+    }
+    pub fn opacity_texture(&self) -> Option<&Rc<MaterialTexture>> {
+        self.texture("TransparentColor").or_else(|| self.texture("TransparencyFactor"))
+    }
+    /// Blender writes normal maps to `NormalMap`, some older exporters put them on `Bump`.
+    pub fn normal_map(&self) -> Option<&Rc<MaterialTexture>> {
+        self.texture("NormalMap").or_else(|| self.texture("Bump"))
+    }
+    /// glTF packs roughness into green and metalness into blue.
+    pub fn metallic_roughness_texture(&self) -> Option<&Rc<MaterialTexture>> {
+        self.texture("MetallicRoughness")
+    }
+    pub fn occlusion_texture(&self) -> Option<&Rc<MaterialTexture>> {
+        self.texture("AmbientOcclusion")
+    }
+    pub(crate) fn add_texture(&mut self, property: &str, texture: Rc<MaterialTexture>) {
+        self.textures.push((property.into(), texture));
+    }
+    pub(crate) fn parse(node: &NodeHandle) -> Result<(i64, Material), ModelLoadError> {
+        let mut material = Material::default();
+
+        let collection_id = node.attr_i64(0)?;
+        material.tag = node.attr_str(1)?.into();
+
+        for child in node.children() {
+            match child.name() {
+                "Properties70" => {
+                    for property in child.children() {
+                        // The short names are what FBX 6 used, some exporters still write both.
+                        match property.attr_str(0)? {
+                            "DiffuseColor" | "Diffuse" => material.diffuse = property_fvec3(&property)?,
+                            "DiffuseFactor" => material.diffuse_factor = property.attr_f64(4)? as f32,
+                            "SpecularColor" | "Specular" => material.specular = property_fvec3(&property)?,
+                            "SpecularFactor" => material.specular_factor = property.attr_f64(4)? as f32,
+                            "ShininessExponent" | "Shininess" => material.shininess = property.attr_f64(4)? as f32,
+                            "EmissiveColor" | "Emissive" => material.emissive = property_fvec3(&property)?,
+                            "EmissiveFactor" => material.emissive_factor = property.attr_f64(4)? as f32,
+                            "Opacity" => material.opacity = Some(property.attr_f64(4)? as f32),
+                            "TransparencyFactor" => material.transparency_factor = property.attr_f64(4)? as f32,
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok((collection_id, material))
+    }
+    /// # parse_mtl
+    ///
+    /// Reads every material of a Wavefront `.mtl` file. Textures get stored under the
+    /// same property names FBX uses so the accessors work the same for both formats.
+    /// Unknown statements are skipped.
+    pub(crate) fn parse_mtl(text: &str, directory: Option<&Path>) -> Vec<Material> {
+        let mut materials: Vec<Material> = vec![];
+        for line in text.lines() {
+            let line = line.trim();
+            let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let rest = rest.trim();
+            if keyword == "newmtl" {
+                materials.push(Material { tag: rest.into(), diffuse: FVec3::from(1.0), ..Default::default() });
+                continue;
+            }
+            let Some(material) = materials.last_mut() else {
+                continue;
+            };
+            let numbers: Vec<f32> = rest.split_whitespace().filter_map(|v| v.parse().ok()).collect();
+            let color = || match numbers.as_slice() {
+                [r, g, b, ..] => Some(FVec3::new(*r, *g, *b)),
+                [v] => Some(FVec3::from(*v)),
+                _ => None,
+            };
+            let texture_property = match keyword {
+                "Kd" => { material.diffuse = color().unwrap_or(material.diffuse); None }
+                "Ks" => { material.specular = color().unwrap_or(material.specular); None }
+                "Ke" => { material.emissive = color().unwrap_or(material.emissive); None }
+                "Ns" => { material.shininess = numbers.first().copied().unwrap_or(material.shininess); None }
+                "d" => { material.opacity = numbers.first().copied(); None }
+                "Tr" => { material.opacity = numbers.first().map(|tr| 1.0 - tr); None }
+                "map_Kd" => Some("DiffuseColor"),
+                "map_Ks" => Some("SpecularColor"),
+                "map_Ke" => Some("EmissiveColor"),
+                "map_d" => Some("TransparentColor"),
+                "map_Bump" | "map_bump" | "bump" | "norm" => Some("NormalMap"),
+                _ => None,
+            };
+            // Texture statements can have options like `-bm 1.0` in front, the file is always last.
+            if let (Some(property), Some(file)) = (texture_property, rest.split_whitespace().last()) {
+                let mut texture = MaterialTexture {
+                    tag: file.into(),
+                    filename: file.into(),
+                    relative_filename: file.into(),
+                    ..Default::default()
+                };
+                texture.resolve(directory);
+                material.add_texture(property, Rc::new(texture));
+            }
+        }
+        materials
+    }
+    /// # parse_gltf
+    ///
+    /// Turns a glTF metallic-roughness material into a `Material`. The base color goes into
+    /// `diffuse` and its alpha into `opacity`. `textures` holds one texture per glTF texture index.
+    pub(crate) fn parse_gltf(material: &gltf::Material, textures: &[Rc<MaterialTexture>]) -> Material {
+        let pbr = material.pbr_metallic_roughness();
+        let [r, g, b, a] = pbr.base_color_factor();
+        let [er, eg, eb] = material.emissive_factor();
+        let mut result = Material {
+            tag: material.name().unwrap_or("default").into(),
+            diffuse: FVec3::new(r, g, b),
+            emissive: FVec3::new(er, eg, eb),
+            opacity: Some(a),
+            metallic: pbr.metallic_factor(),
+            roughness: pbr.roughness_factor(),
+            ..Default::default()
+        };
+        let mut add = |property: &str, index: Option<usize>| {
+            if let Some(texture) = index.and_then(|index| textures.get(index)) {
+                result.add_texture(property, texture.clone());
+            }
+        };
+        add("DiffuseColor", pbr.base_color_texture().map(|info| info.texture().index()));
+        add("MetallicRoughness", pbr.metallic_roughness_texture().map(|info| info.texture().index()));
+        add("NormalMap", material.normal_texture().map(|info| info.texture().index()));
+        add("AmbientOcclusion", material.occlusion_texture().map(|info| info.texture().index()));
+        add("EmissiveColor", material.emissive_texture().map(|info| info.texture().index()));
+        result
+    }
+    /// Writes the raw properties for the mesh cache. Factors are kept separate so
+    /// reading them back gives the exact same material.
+    pub(crate) fn write_cached(&self, writer: &mut CacheWriter) {
+        writer.str(&self.tag);
+        writer.fvec3(self.diffuse);
+        writer.f32(self.diffuse_factor);
+        writer.fvec3(self.specular);
+        writer.f32(self.specular_factor);
+        writer.f32(self.shininess);
+        writer.fvec3(self.emissive);
+        writer.f32(self.emissive_factor);
+        writer.bool(self.opacity.is_some());
+        writer.f32(self.opacity.unwrap_or(1.0));
+        writer.f32(self.transparency_factor);
+        writer.f32(self.metallic);
+        writer.f32(self.roughness);
+        writer.u32(self.textures.len() as u32);
+        for (property, texture) in self.textures.iter() {
+            writer.str(property);
+            writer.str(&texture.tag);
+            writer.str(&texture.filename.to_string_lossy());
+            writer.str(&texture.relative_filename.to_string_lossy());
+            writer.str(&texture.path.as_deref().map(|path| path.to_string_lossy()).unwrap_or_default());
+            writer.bool(texture.embedded.is_some());
+            writer.bytes(texture.embedded.as_deref().map(|bytes| &bytes[..]).unwrap_or(&[]));
+        }
+    }
+    pub(crate) fn read_cached(reader: &mut CacheReader) -> Result<Material, ModelLoadError> {
+        let mut material = Material {
+            tag: reader.str()?,
+            diffuse: reader.fvec3()?,
+            diffuse_factor: reader.f32()?,
+            specular: reader.fvec3()?,
+            specular_factor: reader.f32()?,
+            shininess: reader.f32()?,
+            emissive: reader.fvec3()?,
+            emissive_factor: reader.f32()?,
+            ..Default::default()
+        };
+        let has_opacity = reader.bool()?;
+        let opacity = reader.f32()?;
+        material.opacity = has_opacity.then_some(opacity);
+        material.transparency_factor = reader.f32()?;
+        material.metallic = reader.f32()?;
+        material.roughness = reader.f32()?;
+        // Textures are shared between materials in memory, the cache just stores a copy for every use.
+        for _ in 0..reader.u32()? {
+            let property = reader.str()?;
+            let tag = reader.str()?;
+            let filename = PathBuf::from(reader.str()?);
+            let relative_filename = PathBuf::from(reader.str()?);
+            let path = Some(PathBuf::from(reader.str()?)).filter(|path| !path.as_os_str().is_empty());
+            let has_embedded = reader.bool()?;
+            let bytes = reader.bytes()?;
+            let embedded = has_embedded.then(|| Rc::new(bytes.to_vec()));
+            material.add_texture(&property, Rc::new(MaterialTexture { tag, filename, relative_filename, path, embedded }));
+        }
+        Ok(material)
+    }
+}
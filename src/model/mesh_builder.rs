@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+
+use drowsed_math::{FVec2, FVec3, FVec4};
+
+use super::vertex::GlobalDebugVertex;
+
+/// # BuilderCorner
+///
+/// Everything we know about one corner of a triangle before it becomes a vertex.
+/// `uvs` holds one value for every uv set of the mesh.
+#[derive(Debug, Clone, Copy)]
+pub struct BuilderCorner<'a> {
+    /// The control point this corner came from, used to find skin weights and such later on.
+    pub control_point: u32,
+    pub position: FVec3,
+    pub normal: FVec3,
+    pub uvs: &'a [FVec2],
+    pub color: FVec4,
+}
+
+/// # WeldedMesh
+///
+/// Output of `MeshBuilder`. Every vector besides `indices` has one entry per unique vertex.
+/// `vertices` uses the first uv set, the rest of them are in `uvs` together with the first one.
+#[derive(Default, Debug, Clone)]
+pub struct WeldedMesh {
+    pub vertices: Vec<GlobalDebugVertex>,
+    pub uvs: Vec<Vec<FVec2>>,
+    pub colors: Vec<FVec4>,
+    pub control_points: Vec<u32>,
+    pub indices: Vec<u32>,
+}
+
+/// # MeshBuilder
+///
+/// Turns a stream of triangle corners into an indexed vertex buffer. Two corners
+/// only share a vertex when every attribute is bit for bit the same and they come from the
+/// same control point, so hard edges and uv seams split exactly where the file says they do
+/// while smooth parts still get welded together.
+pub struct MeshBuilder {
+    lookup: HashMap<Vec<u32>, u32>,
+    key: Vec<u32>,
+    mesh: WeldedMesh,
+    uv_sets: usize,
+}
+
+impl MeshBuilder {
+    pub fn new(uv_sets: usize) -> Self {
+        Self::with_capacity(uv_sets, 0)
+    }
+    pub fn with_capacity(uv_sets: usize, corners: usize) -> Self {
+        Self {
+            lookup: HashMap::with_capacity(corners),
+            key: Vec::with_capacity(11 + uv_sets * 2),
+            mesh: WeldedMesh {
+                vertices: Vec::with_capacity(corners),
+                uvs: vec![Vec::with_capacity(corners); uv_sets],
+                colors: Vec::with_capacity(corners),
+                control_points: Vec::with_capacity(corners),
+                indices: Vec::with_capacity(corners),
+            },
+            uv_sets,
+        }
+    }
+    /// Adds one corner and returns the index of the vertex it ended up using.
+    pub fn push(&mut self, corner: BuilderCorner) -> u32 {
+        debug_assert_eq!(corner.uvs.len(), self.uv_sets, "every corner needs one uv per uv set");
+        self.key.clear();
+        self.key.push(corner.control_point);
+        Self::push_key(&mut self.key, &[corner.position.x, corner.position.y, corner.position.z]);
+        Self::push_key(&mut self.key, &[corner.normal.x, corner.normal.y, corner.normal.z]);
+        Self::push_key(&mut self.key, &[corner.color.x, corner.color.y, corner.color.z, corner.color.w]);
+        for uv in corner.uvs {
+            Self::push_key(&mut self.key, &[uv.x, uv.y]);
+        }
+
+        let index = match self.lookup.get(&self.key) {
+            Some(index) => *index,
+            None => {
+                let index = self.mesh.vertices.len() as u32;
+                self.mesh.vertices.push(GlobalDebugVertex {
+                    pos: corner.position,
+                    normal: corner.normal,
+                    uv: corner.uvs.first().copied().unwrap_or_default(),
+                });
+                for (set, uv) in corner.uvs.iter().enumerate() {
+                    self.mesh.uvs[set].push(*uv);
+                }
+                self.mesh.colors.push(corner.color);
+                self.mesh.control_points.push(corner.control_point);
+                self.lookup.insert(self.key.clone(), index);
+                index
+            }
+        };
+        self.mesh.indices.push(index);
+        index
+    }
+    /// How many indices were pushed so far.
+    pub fn index_count(&self) -> usize {
+        self.mesh.indices.len()
+    }
+    pub fn build(self) -> WeldedMesh {
+        self.mesh
+    }
+    fn push_key(key: &mut Vec<u32>, values: &[f32]) {
+        // -0.0 and 0.0 have different bits but are the same value, so they should weld.
+        key.extend(values.iter().map(|v| if *v == 0.0 { 0 } else { v.to_bits() }));
+    }
+}
@@ -0,0 +1,392 @@
+#![allow(unused)]
+//! Binary mesh cache. Parsing FBX files through fbxcel on every launch is slow, so a mesh
+//! can be written out once in the exact layout the GPU wants and read back with a couple of
+//! memcpys instead.
+//!
+//! Everything is little endian. The file looks like this:
+//!
+//! ```text
+//! magic            b"MOCHAMSH"
+//! version          u32
+//! vertex stride    u32
+//! attribute count  u32, then per attribute: location u32, format i32, offset u32
+//! index width      u32 (1, 2 or 4)
+//! vertex count     u32
+//! index count      u32
+//! submesh count    u32, then per submesh: material u32, first index u32, index count u32
+//! material count   u32, then the materials, see `Material::write_cached`
+//! vertex data      vertex count * stride bytes, only the attributes are written, padding is zero
+//! index data       index count * index width bytes
+//! ```
+use std::{io::Write, path::Path, rc::Rc, sync::Arc};
+
+use ash::vk;
+use drowsed_math::FVec3;
+use num_traits::AsPrimitive;
+
+use super::error::ModelLoadError;
+use super::material::Material;
+use crate::vk_obj::{device::ReplacingDevice, rendering::{batcher::RenderBatch, mesh::{Mesh, SubMesh, Vertex, VulkanIndexable}}};
+
+pub const MESH_CACHE_MAGIC: &[u8; 8] = b"MOCHAMSH";
+/// Bump this whenever the layout above changes, old files get rejected instead of misread.
+pub const MESH_CACHE_VERSION: u32 = 1;
+
+/// Appends little endian values to a byte buffer.
+pub(crate) struct CacheWriter {
+    pub bytes: Vec<u8>,
+}
+impl CacheWriter {
+    pub fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    pub fn i32(&mut self, value: i32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    pub fn f32(&mut self, value: f32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    pub fn bool(&mut self, value: bool) {
+        self.bytes.push(value as u8);
+    }
+    pub fn fvec3(&mut self, value: FVec3) {
+        self.f32(value.x);
+        self.f32(value.y);
+        self.f32(value.z);
+    }
+    /// Length prefixed bytes.
+    pub fn bytes(&mut self, value: &[u8]) {
+        self.u32(value.len() as u32);
+        self.bytes.extend_from_slice(value);
+    }
+    pub fn str(&mut self, value: &str) {
+        self.bytes(value.as_bytes());
+    }
+}
+
+/// Reads little endian values back out of a byte slice, every read checks that the data is actually there.
+pub(crate) struct CacheReader<'a> {
+    pub bytes: &'a [u8],
+    pub position: usize,
+}
+impl<'a> CacheReader<'a> {
+    pub fn take(&mut self, len: usize) -> Result<&'a [u8], ModelLoadError> {
+        let end = self.position.checked_add(len).filter(|end| *end <= self.bytes.len()).ok_or_else(|| {
+            ModelLoadError::InvalidCache(format!("file ends at byte {} but {} more were needed", self.bytes.len(), len))
+        })?;
+        let slice = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(slice)
+    }
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], ModelLoadError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+    pub fn u32(&mut self) -> Result<u32, ModelLoadError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+    pub fn i32(&mut self) -> Result<i32, ModelLoadError> {
+        Ok(i32::from_le_bytes(self.array()?))
+    }
+    pub fn f32(&mut self) -> Result<f32, ModelLoadError> {
+        Ok(f32::from_le_bytes(self.array()?))
+    }
+    pub fn bool(&mut self) -> Result<bool, ModelLoadError> {
+        Ok(self.take(1)?[0] != 0)
+    }
+    pub fn fvec3(&mut self) -> Result<FVec3, ModelLoadError> {
+        Ok(FVec3::new(self.f32()?, self.f32()?, self.f32()?))
+    }
+    pub fn bytes(&mut self) -> Result<&'a [u8], ModelLoadError> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+    pub fn str(&mut self) -> Result<String, ModelLoadError> {
+        String::from_utf8(self.bytes()?.to_vec()).map_err(|_| ModelLoadError::InvalidCache("string is not valid UTF-8".into()))
+    }
+}
+
+/// # CachedMesh
+///
+/// Vertices and indices exactly the way they get uploaded, plus the submeshes and
+/// materials that go with them. `V` and `I` are part of the file, a cache written with
+/// `Vertex3DNormalUV` and `u32` only loads back as `Vertex3DNormalUV` and `u32`.
+#[derive(Clone)]
+pub struct CachedMesh<V: Vertex, I: VulkanIndexable> {
+    pub vertices: Vec<V>,
+    pub indices: Vec<I>,
+    pub submeshes: Vec<SubMesh>,
+    pub materials: Vec<Rc<Material>>,
+}
+impl<V: Vertex, I: VulkanIndexable> CachedMesh<V, I> {
+    pub fn from_mesh(mesh: &dyn Mesh<V, I>, materials: &[Rc<Material>]) -> Self {
+        Self {
+            vertices: mesh.vertices(),
+            indices: mesh.indices(),
+            submeshes: mesh.submeshes(),
+            materials: materials.to_vec(),
+        }
+    }
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let attributes = V::attribute_description();
+        let stride = V::binding_description().stride;
+        let vertex_bytes = self.vertices.len() * stride as usize;
+        let index_bytes = self.indices.len() * std::mem::size_of::<I>();
+        let mut writer = CacheWriter { bytes: Vec::with_capacity(256 + vertex_bytes + index_bytes) };
+
+        writer.bytes.extend_from_slice(MESH_CACHE_MAGIC);
+        writer.u32(MESH_CACHE_VERSION);
+        writer.u32(stride);
+        writer.u32(attributes.len() as u32);
+        for attribute in attributes.iter() {
+            writer.u32(attribute.location);
+            writer.i32(attribute.format.as_raw());
+            writer.u32(attribute.offset);
+        }
+        writer.u32(std::mem::size_of::<I>() as u32);
+        writer.u32(self.vertices.len() as u32);
+        writer.u32(self.indices.len() as u32);
+        writer.u32(self.submeshes.len() as u32);
+        for submesh in self.submeshes.iter() {
+            writer.u32(submesh.material as u32);
+            writer.u32(submesh.first_index);
+            writer.u32(submesh.index_count);
+        }
+        writer.u32(self.materials.len() as u32);
+        for material in self.materials.iter() {
+            material.write_cached(&mut writer);
+        }
+        // Only the bytes of the attributes get copied, the padding of `V` is never initialised
+        // so reading it would be undefined. Little endian on everything this runs on.
+        let attribute_ranges: Vec<(usize, usize)> = attributes.iter().map(|attribute| {
+            let size = format_size(attribute.format).unwrap_or_else(|| panic!("vertex format {:?} isnt supported by the mesh cache", attribute.format));
+            (attribute.offset as usize, size)
+        }).collect();
+        for vertex in self.vertices.iter() {
+            let start = writer.bytes.len();
+            writer.bytes.resize(start + stride as usize, 0);
+            let source = vertex as *const V as *const u8;
+            for (offset, size) in attribute_ranges.iter() {
+                let target = &mut writer.bytes[start + offset..start + offset + size];
+                unsafe { std::ptr::copy_nonoverlapping(source.add(*offset), target.as_mut_ptr(), *size) };
+            }
+        }
+        writer.bytes.extend_from_slice(unsafe { std::slice::from_raw_parts(self.indices.as_ptr() as *const u8, index_bytes) });
+        writer.bytes
+    }
+    /// # from_bytes
+    ///
+    /// Checks the header against `V::attribute_description`, the stride of `V` and the
+    /// size of `I` before touching the data, so a cache from an older vertex layout gives
+    /// back `InvalidCache` instead of garbage. Vertices and indices are copied straight
+    /// into their final `Vec`s.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ModelLoadError> {
+        let invalid = |message: String| ModelLoadError::InvalidCache(message);
+        let mut reader = CacheReader { bytes, position: 0 };
+        if reader.take(MESH_CACHE_MAGIC.len())? != MESH_CACHE_MAGIC {
+            return Err(invalid("not a mesh cache file".into()));
+        }
+        let version = reader.u32()?;
+        if version != MESH_CACHE_VERSION {
+            return Err(invalid(format!("version {} but only version {} is supported", version, MESH_CACHE_VERSION)));
+        }
+        let stride = reader.u32()?;
+        if stride != V::binding_description().stride || stride as usize != std::mem::size_of::<V>() {
+            return Err(invalid(format!("vertex stride is {} but the vertex type has {}", stride, std::mem::size_of::<V>())));
+        }
+        let expected = V::attribute_description();
+        let attribute_count = reader.u32()? as usize;
+        if attribute_count != expected.len() {
+            return Err(invalid(format!("{} vertex attributes but the vertex type has {}", attribute_count, expected.len())));
+        }
+        for attribute in expected.iter() {
+            let (location, format, offset) = (reader.u32()?, vk::Format::from_raw(reader.i32()?), reader.u32()?);
+            if location != attribute.location || format != attribute.format || offset != attribute.offset {
+                return Err(invalid(format!("vertex attribute {} doesnt match the vertex type", attribute.location)));
+            }
+            if format_size(format).map_or(true, |size| offset as usize + size > stride as usize) {
+                return Err(invalid(format!("vertex attribute {} has a format the mesh cache cant read", attribute.location)));
+            }
+        }
+        let index_width = reader.u32()?;
+        if index_width as usize != std::mem::size_of::<I>() {
+            return Err(invalid(format!("indices are {} bytes wide but the index type has {}", index_width, std::mem::size_of::<I>())));
+        }
+        let vertex_count = reader.u32()? as usize;
+        let index_count = reader.u32()? as usize;
+
+        let submesh_count = reader.u32()?;
+        let mut submeshes = vec![];
+        for _ in 0..submesh_count {
+            let submesh = SubMesh { material: reader.u32()? as usize, first_index: reader.u32()?, index_count: reader.u32()? };
+            if submesh.first_index as usize + submesh.index_count as usize > index_count {
+                return Err(invalid(format!("submesh goes past the end of the {} indices", index_count)));
+            }
+            submeshes.push(submesh);
+        }
+        let material_count = reader.u32()?;
+        let mut materials = vec![];
+        for _ in 0..material_count {
+            materials.push(Rc::new(Material::read_cached(&mut reader)?));
+        }
+
+        let vertices = copy_slice::<V>(reader.take(vertex_count * std::mem::size_of::<V>())?, vertex_count);
+        let indices = copy_slice::<I>(reader.take(index_count * std::mem::size_of::<I>())?, index_count);
+        // A broken index would make the GPU read past the end of the vertex buffer.
+        if let Some(index) = indices.iter().map(|index| AsPrimitive::<usize>::as_(*index)).find(|index| *index >= vertex_count) {
+            return Err(invalid(format!("index {} points past the {} vertices", index, vertex_count)));
+        }
+        Ok(Self { vertices, indices, submeshes, materials })
+    }
+    pub fn save(&self, filepath: impl AsRef<Path>) -> Result<(), ModelLoadError> {
+        let mut file = std::fs::File::create(filepath)?;
+        file.write_all(&self.to_bytes())?;
+        Ok(())
+    }
+    pub fn load(filepath: impl AsRef<Path>) -> Result<Self, ModelLoadError> {
+        Self::from_bytes(&std::fs::read(filepath)?)
+    }
+    /// # load_or_else
+    ///
+    /// Loads `cache` if it is newer than `source`, otherwise calls `build` (which would
+    /// usually parse `source`) and writes the result to `cache` for next time.
+    /// Failing to write the cache isnt an error, it just gets built again on the next run.
+    pub fn load_or_else(
+        cache: impl AsRef<Path>,
+        source: impl AsRef<Path>,
+        build: impl FnOnce() -> Result<Self, ModelLoadError>,
+    ) -> Result<Self, ModelLoadError> {
+        let modified = |path: &Path| std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+        let fresh = match (modified(cache.as_ref()), modified(source.as_ref())) {
+            (Some(cache), Some(source)) => cache >= source,
+            // No source to compare with, the cache is all there is.
+            (Some(_), None) => true,
+            _ => false,
+        };
+        if fresh {
+            if let Ok(mesh) = Self::load(cache.as_ref()) {
+                return Ok(mesh);
+            }
+        }
+        let mesh = build()?;
+        let _ = mesh.save(cache.as_ref());
+        Ok(mesh)
+    }
+    /// Uploads the vertices and indices into a new `RenderBatch` with the submeshes of the cache.
+    pub fn to_batch(&self, device: Arc<ReplacingDevice>) -> RenderBatch<V, I> {
+        RenderBatch::new(device, self.vertices.clone(), self.indices.clone(), self.submeshes.clone())
+    }
+    pub fn into_batch(self, device: Arc<ReplacingDevice>) -> RenderBatch<V, I> {
+        RenderBatch::new(device, self.vertices, self.indices, self.submeshes)
+    }
+}
+impl<V: Vertex, I: VulkanIndexable> Mesh<V, I> for CachedMesh<V, I> {
+    fn vertices(&self) -> Vec<V> {
+        self.vertices.clone()
+    }
+    fn indices(&self) -> Vec<I> {
+        self.indices.clone()
+    }
+    fn submeshes(&self) -> Vec<SubMesh> {
+        self.submeshes.clone()
+    }
+}
+
+/// Size in bytes of the vertex formats the cache knows how to copy.
+fn format_size(format: vk::Format) -> Option<usize> {
+    match format {
+        vk::Format::R32_SFLOAT | vk::Format::R32_UINT | vk::Format::R32_SINT => Some(4),
+        vk::Format::R32G32_SFLOAT | vk::Format::R32G32_UINT | vk::Format::R32G32_SINT => Some(8),
+        vk::Format::R32G32B32_SFLOAT | vk::Format::R32G32B32_UINT | vk::Format::R32G32B32_SINT => Some(12),
+        vk::Format::R32G32B32A32_SFLOAT | vk::Format::R32G32B32A32_UINT | vk::Format::R32G32B32A32_SINT => Some(16),
+        vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_UINT => Some(4),
+        _ => None,
+    }
+}
+/// Copies `count` values out of unaligned bytes, the length has already been checked.
+/// The padding of the vertices in the file is zero so every byte that ends up in `T` is initialised.
+fn copy_slice<T>(bytes: &[u8], count: usize) -> Vec<T> {
+    let mut values: Vec<T> = Vec::with_capacity(count);
+    unsafe {
+        std::ptr::copy_nonoverlapping(bytes.as_ptr(), values.as_mut_ptr() as *mut u8, count * std::mem::size_of::<T>());
+        values.set_len(count);
+    }
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::model_loader::StandardModelData;
+    use crate::model::vertex::{Vertex3DNormalUV, Vertex3DRGB};
+
+    fn monke() -> CachedMesh<Vertex3DNormalUV, u32> {
+        let models = StandardModelData::try_new(&format!("{}/monke.fbx", env!("CARGO_MANIFEST_DIR"))).unwrap();
+        let model = models.iter().find(|model| !model.vertices.is_empty()).unwrap();
+        CachedMesh {
+            vertices: model.debug_vertices(),
+            indices: model.indices.clone(),
+            submeshes: model.submeshes.clone(),
+            materials: model.materials.clone(),
+        }
+    }
+    fn bits(vertex: &Vertex3DNormalUV) -> [u32; 8] {
+        let (p, n, uv) = (vertex.pos, vertex.normal, vertex.uv);
+        [p.x, p.y, p.z, n.x, n.y, n.z, uv.x, uv.y].map(f32::to_bits)
+    }
+
+    #[test]
+    fn round_trip_through_a_file() {
+        let mesh = monke();
+        let path = std::env::temp_dir().join(format!("yum_mocha_monke_{}.mesh", std::process::id()));
+        mesh.save(&path).unwrap();
+        let loaded = CachedMesh::<Vertex3DNormalUV, u32>::load(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(mesh.vertices.len(), loaded.vertices.len());
+        for (a, b) in mesh.vertices.iter().zip(loaded.vertices.iter()) {
+            assert_eq!(bits(a), bits(b));
+        }
+        assert_eq!(mesh.indices, loaded.indices);
+        assert_eq!(mesh.submeshes, loaded.submeshes);
+        assert_eq!(mesh.materials.len(), loaded.materials.len());
+        for (a, b) in mesh.materials.iter().zip(loaded.materials.iter()) {
+            assert_eq!(format!("{:?}", a), format!("{:?}", b));
+        }
+    }
+    #[test]
+    fn padding_is_written_as_zeroes() {
+        let mesh = CachedMesh::<Vertex3DRGB, u16> {
+            vertices: vec![Vertex3DRGB { coords: FVec3::new(1.0, 2.0, 3.0), rgb: FVec3::new(0.5, 0.5, 0.5) }],
+            indices: vec![0, 0, 0],
+            submeshes: vec![],
+            materials: vec![],
+        };
+        let bytes = mesh.to_bytes();
+        let stride = std::mem::size_of::<Vertex3DRGB>();
+        let vertex = &bytes[bytes.len() - 6 - stride..bytes.len() - 6];
+        assert!(vertex[24..].iter().all(|byte| *byte == 0));
+        let loaded = CachedMesh::<Vertex3DRGB, u16>::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded.vertices[0].coords.y, 2.0);
+        assert_eq!(loaded.vertices[0].rgb.z, 0.5);
+    }
+    #[test]
+    fn other_vertex_types_and_versions_are_rejected() {
+        let bytes = monke().to_bytes();
+        assert!(matches!(CachedMesh::<Vertex3DRGB, u32>::from_bytes(&bytes), Err(ModelLoadError::InvalidCache(_))));
+        assert!(matches!(CachedMesh::<Vertex3DNormalUV, u16>::from_bytes(&bytes), Err(ModelLoadError::InvalidCache(_))));
+
+        let mut old = bytes.clone();
+        old[MESH_CACHE_MAGIC.len()..MESH_CACHE_MAGIC.len() + 4].copy_from_slice(&(MESH_CACHE_VERSION + 1).to_le_bytes());
+        assert!(matches!(CachedMesh::<Vertex3DNormalUV, u32>::from_bytes(&old), Err(ModelLoadError::InvalidCache(_))));
+
+        assert!(matches!(CachedMesh::<Vertex3DNormalUV, u32>::from_bytes(&bytes[..bytes.len() - 1]), Err(ModelLoadError::InvalidCache(_))));
+    }
+    #[test]
+    fn indices_past_the_vertices_are_rejected() {
+        let mesh = monke();
+        let mut bytes = mesh.to_bytes();
+        let end = bytes.len();
+        bytes[end - 4..].copy_from_slice(&(mesh.vertices.len() as u32).to_le_bytes());
+        assert!(matches!(CachedMesh::<Vertex3DNormalUV, u32>::from_bytes(&bytes), Err(ModelLoadError::InvalidCache(_))));
+    }
+}
@@ -1,2 +1,24 @@
 pub mod vertex;
-pub mod model_loader;
\ No newline at end of file
+pub mod model_loader;
+pub mod error;
+pub mod triangulate;
+pub mod mesh_builder;
+pub mod transform;
+pub mod scene;
+pub mod axis;
+pub mod material;
+pub mod skin;
+pub mod animation;
+pub mod clip;
+pub mod morph;
+pub mod fbx_ascii;
+pub mod obj;
+pub mod gltf_loader;
+pub mod mesh_cache;
+pub mod fbx_export;
+pub mod inspect;
+pub mod geometry;
+pub mod optimize;
+pub mod simplify;
+pub mod primitives;
+pub mod convert;
\ No newline at end of file
@@ -1,42 +1,296 @@
 #![allow(unused)]
 /// This is probably one of the ugliest coded files on this entire project
 /// so I need to revise it a bunch.
-use std::{vec, io::Write, collections::HashMap, rc::Rc};
+use std::{vec, io::{Read, Seek}, collections::{HashMap, HashSet}, rc::Rc, path::{Path, PathBuf}};
 
-use drowsed_math::{FVec3, TransformQuaternion3D, complex::quaternion::Quaternion};
-use fbxcel_dom::{fbxcel::tree::v7400::NodeHandle, v7400::Document, any::AnyDocument};
+use drowsed_math::{FMat4, FVec2, FVec3, FVec4, TransformQuaternion3D, Vector, EuclideanGeometry, SquareMatrix, complex::quaternion::Quaternion};
+use fbxcel_dom::{fbxcel::{tree::v7400::NodeHandle, low::v7400::AttributeValue}, v7400::Document, any::AnyDocument};
 
-pub fn print_format(node: NodeHandle, depth: i32) {
-    let new_depth = depth + 1;
-    for child in node.children() {
-        for i in 0..depth {
-            print!("\t");
+use super::error::ModelLoadError;
+use super::scene::{Scene, SceneGraph, ModelTransform, RotationOrder};
+use super::axis::AxisSystem;
+use super::fbx_ascii;
+use super::obj;
+use super::gltf_loader;
+pub use super::material::{Material, MaterialTexture};
+use super::transform;
+use super::mesh_builder::{MeshBuilder, BuilderCorner};
+use super::vertex::{GlobalDebugVertex, Vertex3DRGB, Vertex3DSkinned};
+use super::skin::{self, Skin, Cluster};
+use super::animation::AnimationObjects;
+use super::morph::{self, MorphChannel, MorphShape, ShapeGeometry, ChannelData};
+use crate::vk_obj::rendering::mesh::SubMesh;
+use super::triangulate::{TriangleCorner, polygon_ranges, triangulate_polygon};
+
+/// # NodeAttributes
+///
+/// Checked access to the attributes of a node. Every getter returns a
+/// `ModelLoadError` with the path of the node instead of panicking, so
+/// a broken file only fails the load instead of the whole process.
+pub(crate) trait NodeAttributes<'a> {
+    /// Path of the node from the root, for example `Objects/Geometry/Vertices`.
+    fn path(&self) -> String;
+    fn attribute(&self, index: usize) -> Result<&'a AttributeValue, ModelLoadError>;
+    fn attr_i64(&self, index: usize) -> Result<i64, ModelLoadError>;
+    fn attr_f64(&self, index: usize) -> Result<f64, ModelLoadError>;
+    fn attr_str(&self, index: usize) -> Result<&'a str, ModelLoadError>;
+    fn attr_arr_f64(&self, index: usize) -> Result<&'a [f64], ModelLoadError>;
+    fn attr_arr_i32(&self, index: usize) -> Result<&'a [i32], ModelLoadError>;
+    fn attr_arr_i64(&self, index: usize) -> Result<&'a [i64], ModelLoadError>;
+    /// Accepts both float and double arrays since exporters dont agree on which to use.
+    fn attr_arr_f32(&self, index: usize) -> Result<Vec<f32>, ModelLoadError>;
+    fn child(&self, name: &str) -> Result<NodeHandle<'a>, ModelLoadError>;
+    fn wrong_type(&self, index: usize, expected: &'static str) -> ModelLoadError;
+}
+impl<'a> NodeAttributes<'a> for NodeHandle<'a> {
+    fn path(&self) -> String {
+        let mut names = vec![self.name().to_string()];
+        let mut current = self.parent();
+        while let Some(parent) = current {
+            // the root node has no name so we dont want a leading slash.
+            if !parent.name().is_empty() {
+                names.push(parent.name().to_string());
+            }
+            current = parent.parent();
         }
-        println!("{}: {:?}", child.name(), child.attributes());
-        print_format(child, new_depth);
+        names.reverse();
+        names.join("/")
     }
-}
-pub fn print_format_file(node: NodeHandle, depth: i32, file: &mut std::fs::File) {
-    let new_depth = depth + 1;
-    for child in node.children() {
-        for i in 0..depth {
-            file.write("\t".as_bytes()).unwrap();
+    fn attribute(&self, index: usize) -> Result<&'a AttributeValue, ModelLoadError> {
+        let attributes = self.attributes();
+        attributes.get(index).ok_or_else(|| ModelLoadError::IndexOutOfRange {
+            path: self.path(),
+            index,
+            len: attributes.len(),
+        })
+    }
+    fn attr_i64(&self, index: usize) -> Result<i64, ModelLoadError> {
+        match self.attribute(index)? {
+            AttributeValue::I64(v) => Ok(*v),
+            AttributeValue::I32(v) => Ok(*v as i64),
+            _ => Err(self.wrong_type(index, "i64")),
         }
-
-        file.write_fmt(format_args!("{}: {:?}\n", child.name(), child.attributes())).unwrap();
-        print_format_file(child, new_depth, file);
     }
+    fn attr_f64(&self, index: usize) -> Result<f64, ModelLoadError> {
+        match self.attribute(index)? {
+            AttributeValue::F64(v) => Ok(*v),
+            AttributeValue::F32(v) => Ok(*v as f64),
+            // ASCII files dont say what type a number is, `1` can be a double there.
+            AttributeValue::I32(v) => Ok(*v as f64),
+            AttributeValue::I64(v) => Ok(*v as f64),
+            _ => Err(self.wrong_type(index, "f64")),
+        }
+    }
+    fn attr_str(&self, index: usize) -> Result<&'a str, ModelLoadError> {
+        self.attribute(index)?.get_string().ok_or_else(|| self.wrong_type(index, "string"))
+    }
+    fn attr_arr_f64(&self, index: usize) -> Result<&'a [f64], ModelLoadError> {
+        self.attribute(index)?.get_arr_f64().ok_or_else(|| self.wrong_type(index, "f64 array"))
+    }
+    fn attr_arr_i32(&self, index: usize) -> Result<&'a [i32], ModelLoadError> {
+        self.attribute(index)?.get_arr_i32().ok_or_else(|| self.wrong_type(index, "i32 array"))
+    }
+    fn attr_arr_i64(&self, index: usize) -> Result<&'a [i64], ModelLoadError> {
+        self.attribute(index)?.get_arr_i64().ok_or_else(|| self.wrong_type(index, "i64 array"))
+    }
+    fn attr_arr_f32(&self, index: usize) -> Result<Vec<f32>, ModelLoadError> {
+        match self.attribute(index)? {
+            AttributeValue::ArrF32(v) => Ok(v.clone()),
+            AttributeValue::ArrF64(v) => Ok(v.iter().map(|v| *v as f32).collect()),
+            _ => Err(self.wrong_type(index, "f32 array")),
+        }
+    }
+    fn child(&self, name: &str) -> Result<NodeHandle<'a>, ModelLoadError> {
+        self.first_child_by_name(name).ok_or_else(|| ModelLoadError::MissingNode {
+            path: format!("{}/{}", self.path(), name),
+        })
+    }
+    fn wrong_type(&self, index: usize, expected: &'static str) -> ModelLoadError {
+        ModelLoadError::WrongAttributeType { path: self.path(), index, expected }
+    }
+}
+/// Turns a flat `[x, y, z, x, y, z, ...]` array into vectors.
+/// A trailing partial vector is an error since it means the file is cut off.
+pub(crate) fn to_fvec3(node: &NodeHandle, values: &[f64]) -> Result<Vec<FVec3>, ModelLoadError> {
+    if values.len() % 3 != 0 {
+        return Err(ModelLoadError::MalformedArray { path: node.path(), len: values.len(), stride: 3 });
+    }
+    Ok(values.chunks_exact(3).map(|v| FVec3::new(v[0] as f32, v[1] as f32, v[2] as f32)).collect())
+}
+fn to_fvec2(node: &NodeHandle, values: &[f64]) -> Result<Vec<FVec2>, ModelLoadError> {
+    if values.len() % 2 != 0 {
+        return Err(ModelLoadError::MalformedArray { path: node.path(), len: values.len(), stride: 2 });
+    }
+    Ok(values.chunks_exact(2).map(|v| FVec2::new(v[0] as f32, v[1] as f32)).collect())
+}
+fn to_fvec4(node: &NodeHandle, values: &[f64]) -> Result<Vec<FVec4>, ModelLoadError> {
+    if values.len() % 4 != 0 {
+        return Err(ModelLoadError::MalformedArray { path: node.path(), len: values.len(), stride: 4 });
+    }
+    Ok(values.chunks_exact(4).map(|v| FVec4::new(v[0] as f32, v[1] as f32, v[2] as f32, v[3] as f32)).collect())
+}
+/// Reads the three numbers at attributes 4, 5 and 6 of a `P` property node.
+pub(crate) fn property_fvec3(property: &NodeHandle) -> Result<FVec3, ModelLoadError> {
+    Ok(FVec3::new(
+        property.attr_f64(4)? as f32,
+        property.attr_f64(5)? as f32,
+        property.attr_f64(6)? as f32,
+    ))
 }
 #[derive(Default, Debug)]
 pub enum GeometryNormal {
     #[default]
     None,
-    ByPolygonVertex(Vec<FVec3>),
+    ByPolygonVertex(LayerElement<FVec3>),
+    /// Blender and Maya call this `ByVertice`, 3ds Max `ByControlPoint`.
+    ByControlPoint(LayerElement<FVec3>),
+    ByPolygon(LayerElement<FVec3>),
+    AllSame(LayerElement<FVec3>),
 }
-#[derive(Default, Debug)]
-pub struct Material {
-    tag: String,
-    diffuse: FVec3 // Diffuse indices
+impl GeometryNormal {
+    fn from_layer(layer: LayerElement<FVec3>) -> Self {
+        match layer.mapping {
+            LayerMapping::ByPolygonVertex => Self::ByPolygonVertex(layer),
+            LayerMapping::ByControlPoint => Self::ByControlPoint(layer),
+            LayerMapping::ByPolygon => Self::ByPolygon(layer),
+            LayerMapping::AllSame => Self::AllSame(layer),
+            // Normals per edge dont mean anything for shading, we rather generate our own.
+            LayerMapping::ByEdge | LayerMapping::None => Self::None,
+        }
+    }
+    fn layer(&self) -> Option<&LayerElement<FVec3>> {
+        match self {
+            Self::None => None,
+            Self::ByPolygonVertex(layer) | Self::ByControlPoint(layer) | Self::ByPolygon(layer) | Self::AllSame(layer) => Some(layer),
+        }
+    }
+}
+/// # GenerateNormals
+///
+/// What to do when a geometry comes without any normals.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GenerateNormals {
+    /// Averages the normals of every polygon touching a control point, weighted by area.
+    #[default]
+    Smooth,
+    /// Every polygon uses its face normal. Neighbouring polygons that are flat against each
+    /// other can still share vertices when their uvs match too.
+    Flat,
+    /// Leave `StandardModelData::normals` empty.
+    Skip,
+}
+/// # LoadOptions
+///
+/// Knobs for `StandardModelData::try_new_with`. `Default` gives you what `try_new` does.
+#[derive(Debug, Clone)]
+pub struct LoadOptions {
+    pub missing_normals: GenerateNormals,
+    /// Coordinate system everything gets converted into. `None` keeps whatever the file uses.
+    pub target_axes: Option<AxisSystem>,
+    /// Use this instead of the `GlobalSettings` of the file, for files that lie about it.
+    pub source_axes: Option<AxisSystem>,
+    /// Where relative texture paths start from. `try_new_with` fills this in with the
+    /// folder of the file when it's `None`.
+    pub base_directory: Option<PathBuf>,
+}
+impl LoadOptions {
+    /// Copy of the options with `base_directory` pointing at the folder of `filepath` if it wasnt set.
+    pub fn for_file(&self, filepath: &str) -> Self {
+        let mut options = self.clone();
+        if options.base_directory.is_none() {
+            options.base_directory = Path::new(filepath).parent().map(Path::to_path_buf);
+        }
+        options
+    }
+}
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self {
+            missing_normals: GenerateNormals::default(),
+            target_axes: Some(AxisSystem::ENGINE),
+            source_axes: None,
+            base_directory: None,
+        }
+    }
+}
+/// # LayerMapping
+///
+/// `MappingInformationType` of a `LayerElement*` node. It says what a value
+/// in the layer belongs to. Exporters dont agree on the names so `ByVertex`,
+/// `ByVertice` and `ByControlPoint` all mean the same thing.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerMapping {
+    #[default]
+    None,
+    ByPolygonVertex,
+    ByControlPoint,
+    ByPolygon,
+    ByEdge,
+    AllSame,
+}
+impl LayerMapping {
+    fn from_fbx(name: &str) -> Self {
+        match name {
+            "ByPolygonVertex" => Self::ByPolygonVertex,
+            "ByVertex" | "ByVertice" | "ByControlPoint" => Self::ByControlPoint,
+            "ByPolygon" => Self::ByPolygon,
+            "ByEdge" => Self::ByEdge,
+            "AllSame" => Self::AllSame,
+            _ => Self::None,
+        }
+    }
+}
+/// # LayerReference
+///
+/// `ReferenceInformationType` of a `LayerElement*` node. With `Direct` the
+/// mapping indexes straight into the values, with `IndexToDirect` it indexes
+/// into the index array first. Old files write `Index` instead of `IndexToDirect`.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerReference {
+    #[default]
+    Direct,
+    IndexToDirect,
+}
+/// # LayerElement
+///
+/// One `LayerElementUV`, `LayerElementNormal`, ... node with its values
+/// still laid out the way the file stores them.
+#[derive(Default, Debug, Clone)]
+pub struct LayerElement<T> {
+    pub name: String,
+    pub mapping: LayerMapping,
+    pub reference: LayerReference,
+    pub values: Vec<T>,
+    pub indices: Vec<i32>,
+}
+impl<T: Copy> LayerElement<T> {
+    /// Looks up the value for one corner of a polygon. `polygon_vertex` is the position
+    /// in `PolygonVertexIndex`, `control_point` the vertex it points at and `polygon`
+    /// the polygon it belongs to. Returns `None` if the layer doesnt cover that corner.
+    pub fn get(&self, polygon_vertex: usize, control_point: usize, polygon: usize) -> Option<T> {
+        let index = match self.mapping {
+            LayerMapping::ByPolygonVertex => polygon_vertex,
+            LayerMapping::ByControlPoint => control_point,
+            LayerMapping::ByPolygon => polygon,
+            LayerMapping::AllSame => 0,
+            LayerMapping::ByEdge | LayerMapping::None => return None,
+        };
+        let index = match self.reference {
+            LayerReference::Direct => index,
+            LayerReference::IndexToDirect => usize::try_from(*self.indices.get(index)?).ok()?,
+        };
+        self.values.get(index).copied()
+    }
+}
+/// # UVSet
+///
+/// One named set of texture coordinates, the same length as `StandardModelData::vertices`.
+/// FBX puts the uv origin in the bottom left, these are flipped on import so the origin
+/// is in the top left the same way `ImageTexture` gets sampled.
+#[derive(Default, Debug, Clone)]
+pub struct UVSet {
+    pub name: String,
+    pub uvs: Vec<FVec2>,
 }
 #[derive(Default)]
 pub struct Geometry {
@@ -45,272 +299,747 @@ pub struct Geometry {
     polygon_indices: Vec<i32>,
     edges: Vec<i32>,
     normal: GeometryNormal,
+    uvs: Vec<LayerElement<FVec2>>,
+    colors: Option<LayerElement<FVec4>>,
+    materials: Option<LayerElement<i32>>,
+}
+impl Geometry {
+    /// # triangles
+    ///
+    /// Splits every polygon into triangles. The corners come out three at a time and
+    /// each one still knows its polygon vertex and polygon, so layers that are stored
+    /// per polygon vertex or per polygon stay lined up with the triangles.
+    fn triangles(&self) -> Result<Vec<TriangleCorner>, ModelLoadError> {
+        let mut corners = Vec::with_capacity(self.polygon_indices.len());
+        let mut points = vec![];
+        let mut polygon_corners = vec![];
+        for (polygon, range) in polygon_ranges(&self.polygon_indices).into_iter().enumerate() {
+            points.clear();
+            polygon_corners.clear();
+            for polygon_vertex in range {
+                let index = self.polygon_indices[polygon_vertex];
+                let control_point = (if index.is_negative() { !index } else { index }) as usize;
+                let point = self.vertices.get(control_point).ok_or_else(|| ModelLoadError::IndexOutOfRange {
+                    path: format!("Objects/Geometry({})/PolygonVertexIndex", self.tag),
+                    index: control_point,
+                    len: self.vertices.len(),
+                })?;
+                points.push(*point);
+                polygon_corners.push(TriangleCorner { polygon_vertex, control_point, polygon });
+            }
+            for triangle in triangulate_polygon(&points) {
+                corners.extend(triangle.iter().map(|i| polygon_corners[*i]));
+            }
+        }
+        Ok(corners)
+    }
+    /// Area weighted normal of every polygon, taken from the triangles it got split into.
+    fn polygon_normals(&self, corners: &[TriangleCorner]) -> Vec<FVec3> {
+        let polygons = corners.last().map(|corner| corner.polygon + 1).unwrap_or(0);
+        let mut normals = vec![FVec3::from(0.0); polygons];
+        for triangle in corners.chunks_exact(3) {
+            let a = self.vertices[triangle[0].control_point];
+            let b = self.vertices[triangle[1].control_point];
+            let c = self.vertices[triangle[2].control_point];
+            normals[triangle[0].polygon] += (b - a).cross(c - a);
+        }
+        normals
+    }
+    /// One normal per corner, every corner of a polygon gets the same one.
+    fn flat_normals(&self, corners: &[TriangleCorner]) -> Vec<FVec3> {
+        let normals = self.polygon_normals(corners);
+        corners.iter().map(|corner| safe_normalize(normals[corner.polygon])).collect()
+    }
+    /// One normal per corner, every corner of a control point gets the same one.
+    fn smooth_normals(&self, corners: &[TriangleCorner]) -> Vec<FVec3> {
+        let normals = self.polygon_normals(corners);
+        let mut summed = vec![FVec3::from(0.0); self.vertices.len()];
+        // A polygon can touch the same control point more than once after triangulation,
+        // so only count it once per polygon vertex.
+        let mut seen = vec![false; self.polygon_indices.len()];
+        for corner in corners.iter() {
+            if !seen[corner.polygon_vertex] {
+                seen[corner.polygon_vertex] = true;
+                summed[corner.control_point] += normals[corner.polygon];
+            }
+        }
+        corners.iter().map(|corner| safe_normalize(summed[corner.control_point])).collect()
+    }
+}
+/// Normalizes without turning degenerate vectors into NaN.
+pub(crate) fn safe_normalize(v: FVec3) -> FVec3 {
+    if v.dot(&v) <= f32::EPSILON * f32::EPSILON {
+        FVec3::from(0.0)
+    } else {
+        v.normalize()
+    }
 }
 pub struct ModelData {
     tag: String,
-    transform: TransformQuaternion3D,
+    transform: ModelTransform,
 }
+/// Everything `parse_objects` pulls out of the `Objects` node.
+/// `model_order` keeps the models in the order the file lists them.
 #[derive(Default)]
+struct FbxObjects {
+    geometries: HashMap<i64, Geometry>,
+    models: HashMap<i64, ModelData>,
+    materials: HashMap<i64, Material>,
+    textures: HashMap<i64, MaterialTexture>,
+    videos: HashMap<i64, MaterialTexture>,
+    skins: HashSet<i64>,
+    clusters: HashMap<i64, Cluster>,
+    bind_poses: HashMap<i64, FMat4>,
+    model_order: Vec<i64>,
+    shapes: HashMap<i64, ShapeGeometry>,
+    blend_shapes: HashSet<i64>,
+    blend_shape_channels: HashMap<i64, ChannelData>,
+    animations: AnimationObjects,
+}
+/// # Connection
+///
+/// One line of `Connections`. `child` gets attached to `parent`, for OP links
+/// `property` says which property of the parent it plugs into (`DiffuseColor`, ...).
+#[derive(Debug, Clone)]
+pub struct Connection {
+    pub child: i64,
+    pub parent: i64,
+    pub property: Option<String>,
+}
 pub struct StandardModelData {
     pub tag: String,
     pub vertices: Vec<FVec3>,
     pub normals: Vec<FVec3>,
+    pub uvs: Vec<UVSet>,
+    /// RGBA vertex colors, empty when the file has none.
+    pub colors: Vec<FVec4>,
+    /// The FBX control point every vertex was made from. Vertices get split on seams
+    /// so more than one of them can point at the same control point.
+    pub control_points: Vec<u32>,
     pub indices: Vec<u32>,
     pub materials: Vec<Rc<Material>>,
+    /// Ranges of `indices` that use the same material, `SubMesh::material` indexes `materials`.
+    /// Meshes without a material layer get one submesh using material 0.
+    pub submeshes: Vec<SubMesh>,
+    /// Joints and per vertex weights, only there when the model is bound to a skeleton.
+    pub skin: Option<Skin>,
+    /// Blend shape channels of the model, see `morphed` to apply them.
+    pub morph_channels: Vec<MorphChannel>,
+    /// Transform relative to the parent, with the pivots and pre/post rotation folded in.
     pub transform: TransformQuaternion3D,
+    /// Index of the parent model in the `Vec` this model was loaded into.
+    pub parent: Option<usize>,
+    /// Parent transforms times `transform`, this is where the model actually is.
+    pub world: FMat4,
+}
+impl Default for StandardModelData {
+    fn default() -> Self {
+        Self {
+            tag: String::new(),
+            vertices: vec![],
+            normals: vec![],
+            uvs: vec![],
+            colors: vec![],
+            control_points: vec![],
+            indices: vec![],
+            materials: vec![],
+            submeshes: vec![],
+            skin: None,
+            morph_channels: vec![],
+            transform: TransformQuaternion3D::default(),
+            parent: None,
+            world: FMat4::identity(),
+        }
+    }
 }
 
 impl StandardModelData {
+    /// Panicking version of `try_new`, kept around for the places that
+    /// only ever load files that ship with the repo.
     pub fn new(filepath: &str) -> Vec<Self> {
-        let file = std::fs::File::open(filepath).expect("Failed to open file");
-        let reader = std::io::BufReader::new(file);
-        match AnyDocument::from_seekable_reader(reader).expect("Failed to load document") {
-        AnyDocument::V7400(_fbx_ver, doc) => {
-            return Self::parse(doc);
+        Self::try_new(filepath).unwrap_or_else(|e| panic!("{}", e))
+    }
+    pub fn try_new(filepath: &str) -> Result<Vec<Self>, ModelLoadError> {
+        Self::try_new_with(filepath, &LoadOptions::default())
+    }
+    /// Loads an FBX file, or an OBJ or glTF file when the extension says so.
+    pub fn try_new_with(filepath: &str, options: &LoadOptions) -> Result<Vec<Self>, ModelLoadError> {
+        if Path::new(filepath).extension().map_or(false, |extension| extension.eq_ignore_ascii_case("obj")) {
+            return obj::try_new_with(filepath, options);
+        }
+        if gltf_loader::is_gltf(filepath) {
+            return gltf_loader::try_new_with(filepath, options);
         }
-        _ => panic!("Got FBX document of unsupported version"),
+        Self::try_parse_with(Self::load_document(filepath)?, &options.for_file(filepath))
     }
+    /// Loads a binary or ASCII FBX file, binary ones start with `Kaydara FBX Binary`.
+    pub(crate) fn load_document(filepath: &str) -> Result<Box<Document>, ModelLoadError> {
+        let mut file = std::fs::File::open(filepath)?;
+        let mut magic = [0u8; 18];
+        let binary = file.read_exact(&mut magic).is_ok() && &magic == b"Kaydara FBX Binary";
+        if !binary {
+            let bytes = std::fs::read(filepath)?;
+            return fbx_ascii::load_document(&String::from_utf8_lossy(&bytes));
+        }
+        file.seek(std::io::SeekFrom::Start(0))?;
+        let reader = std::io::BufReader::new(file);
+        match AnyDocument::from_seekable_reader(reader)? {
+            AnyDocument::V7400(_fbx_ver, doc) => Ok(doc),
+            _ => Err(ModelLoadError::UnsupportedVersion),
+        }
     }
     pub fn parse(document: Box<Document>) -> Vec<Self> {
+        Self::try_parse(document).unwrap_or_else(|e| panic!("{}", e))
+    }
+    pub fn try_parse(document: Box<Document>) -> Result<Vec<Self>, ModelLoadError> {
+        Self::try_parse_with(document, &LoadOptions::default())
+    }
+    pub fn try_parse_with(document: Box<Document>, options: &LoadOptions) -> Result<Vec<Self>, ModelLoadError> {
+        Ok(Self::try_parse_scene(document, options)?.models)
+    }
+    /// # try_parse_scene
+    ///
+    /// Same as `try_parse_with` but also hands back the hierarchy the models are in.
+    pub fn try_parse_scene(document: Box<Document>, options: &LoadOptions) -> Result<Scene, ModelLoadError> {
         let tree = document.tree();
         let root = tree.root();
 
         // Parse Data into seperate Geometries, Models and Materials.
 
-        let mut _objects: (HashMap::<i64, Geometry>, HashMap::<i64,ModelData>, HashMap::<i64,Rc<Material>>) = (HashMap::new(), HashMap::new(), HashMap::new());
-        let mut _connections: Vec<(i64, i64)> = vec![];
+        let mut objects = FbxObjects::default();
+        let mut connections: Vec<Connection> = vec![];
+        let mut file_axes = AxisSystem::FBX_DEFAULT;
         for child in root.children() {
             match child.name() {
+                "GlobalSettings" => {
+                    file_axes = AxisSystem::from_global_settings(&child)?;
+                }
                 "Objects" => {
-                    _objects = Self::parse_objects(&child);
+                    objects = Self::parse_objects(&child)?;
                 }
                 "Connections" => {
-                    _connections = Self::parse_connections(&child);
+                    connections = Self::parse_connections(&child)?;
                 }
                 _ => {}
             }
         }
-        let mut model_sheis:Vec<StandardModelData> = Vec::with_capacity(_objects.1.len());
-        let mut model_sheis_idx:Vec<i64> = Vec::with_capacity(_objects.1.len());
 
-        for (idx, model) in _objects.1.iter() {
-            model_sheis.push(StandardModelData {
-                tag: model.tag.clone(),
-                transform: model.transform,
-                ..Default::default()
-            });
-            model_sheis_idx.push(*idx);
-        }
-        
-        for connection in _connections {
+        let conversion = match &options.target_axes {
+            Some(target) => AxisSystem::conversion(options.source_axes.as_ref().unwrap_or(&file_axes), target),
+            None => FMat4::identity(),
+        };
+        let graph = SceneGraph::build(
+            objects.model_order.iter().map(|id| {
+                let model = &objects.models[id];
+                (*id, model.tag.clone(), model.transform)
+            }).collect(),
+            &connections.iter().map(|c| (c.child, c.parent)).collect::<Vec<_>>(),
+            conversion,
+        );
+        let mut models: Vec<StandardModelData> = graph.nodes.iter().enumerate().map(|(i, node)| StandardModelData {
+            tag: node.tag.clone(),
+            transform: transform::decompose(&graph.local_matrix(i)),
+            parent: node.parent,
+            world: node.world,
+            ..Default::default()
+        }).collect();
+        let lookup: HashMap<i64, usize> = objects.model_order.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+
+        let materials = Self::link_materials(&mut objects, &connections, options);
+
+        for connection in connections.iter() {
             // if rhs == 0 it means its just initializing a model and we can just skip.
-            // this is most likely a bad approach but ive been writing this for like 9 hours
-            // now and im kinda sick of all this.
-            if connection.1 == 0 {
+            if connection.parent == 0 {
                 continue;
             }
-            for i in 0..model_sheis.len() {
-                if model_sheis_idx[i] == connection.1 {
-                    // parse geometry data
-                    if _objects.0.contains_key(&connection.0) {
-                        let geometry = _objects.0.get(&connection.0).unwrap();
-                        model_sheis[i].indices = Self::get_indices(geometry);
-
-                        model_sheis[i].vertices = geometry.vertices.clone();
-                        
-                        match &geometry.normal {
-                            GeometryNormal::ByPolygonVertex(v) => {
-                                model_sheis[i].normals = vec![FVec3::from(0.0); model_sheis[i].vertices.len()];
-                                let mut j = 0;
-                                for idx in 0..(model_sheis[i].indices.len()-1) {
-                                    let index = model_sheis[i].indices[idx] as usize;
-                                    if model_sheis[i].normals[index] == 0.0 {
-                                        let val = v[j];
-                                        model_sheis[i].normals[index] = val;
-                                    }
-                                    j += 1;
-                                }
-                            } 
-                            GeometryNormal::None => {}
-                        }
-                    } 
-                    // parse material data. Keep in mind there can be multiple material data for 1 model.
-                    else if _objects.2.contains_key(&connection.0) {
-                        let material = _objects.2.get(&connection.0).unwrap();
-                        model_sheis[i].materials.push(material.clone());
+            let Some(&i) = lookup.get(&connection.parent) else {
+                continue;
+            };
+            // parse geometry data
+            if let Some(geometry) = objects.geometries.get(&connection.child) {
+                models[i].apply_geometry(geometry, options)?;
+                let node = &graph.nodes[i];
+                if node.transform.has_geometric_transform() {
+                    models[i].apply_matrix(&node.transform.geometric_matrix());
+                }
+                models[i].apply_matrix(&conversion);
+            } 
+            // parse material data. Keep in mind there can be multiple material data for 1 model.
+            else if let Some(material) = materials.get(&connection.child) {
+                models[i].materials.push(material.clone());
+            }
+        }
+        let geometry_models = Self::geometry_models(&objects, &connections, &lookup);
+        Self::link_skins(&objects, &connections, &geometry_models, &lookup, &graph, &mut models)?;
+        Self::link_morphs(&objects, &connections, &geometry_models, &graph, &mut models)?;
+        let animations = std::mem::take(&mut objects.animations).link(&connections, &lookup);
+        Ok(Scene { models, graph, animations, clips: vec![] })
+    }
+    /// # link_skins
+    ///
+    /// Skinning goes bone model -> cluster -> skin -> geometry -> model in `Connections`.
+    /// Every model that uses a skinned geometry gets its own `Skin` since the vertices of two
+    /// models using the same geometry can be welded differently.
+    fn link_skins(objects: &FbxObjects, connections: &[Connection], geometry_models: &HashMap<i64, Vec<usize>>, lookup: &HashMap<i64, usize>, graph: &SceneGraph, models: &mut [StandardModelData]) -> Result<(), ModelLoadError> {
+        let mut skin_geometry: Vec<(i64, i64)> = vec![];
+        let mut cluster_skin: Vec<(i64, i64)> = vec![];
+        let mut cluster_bone: HashMap<i64, usize> = HashMap::new();
+        for connection in connections.iter() {
+            if objects.skins.contains(&connection.child) && objects.geometries.contains_key(&connection.parent) {
+                skin_geometry.push((connection.child, connection.parent));
+            } else if objects.clusters.contains_key(&connection.child) && objects.skins.contains(&connection.parent) {
+                cluster_skin.push((connection.child, connection.parent));
+            } else if objects.clusters.contains_key(&connection.parent) {
+                if let Some(bone) = lookup.get(&connection.child) {
+                    cluster_bone.insert(connection.parent, *bone);
+                }
+            }
+        }
+        for (skin, geometry) in skin_geometry {
+            // Clusters without a bone dont move anything so they just get skipped.
+            let clusters: Vec<(&Cluster, usize)> = cluster_skin.iter()
+                .filter(|(_, cluster_skin)| *cluster_skin == skin)
+                .filter_map(|(cluster, _)| Some((&objects.clusters[cluster], *cluster_bone.get(cluster)?)))
+                .collect();
+            let control_point_count = objects.geometries[&geometry].vertices.len();
+            for model in geometry_models.get(&geometry).into_iter().flatten() {
+                let built = Skin::build(&clusters, graph, &objects.bind_poses, control_point_count, &models[*model].control_points)?;
+                models[*model].skin = Some(built);
+            }
+        }
+        Ok(())
+    }
+    /// Every model index each geometry is attached to.
+    fn geometry_models(objects: &FbxObjects, connections: &[Connection], lookup: &HashMap<i64, usize>) -> HashMap<i64, Vec<usize>> {
+        let mut geometry_models: HashMap<i64, Vec<usize>> = HashMap::new();
+        for connection in connections.iter() {
+            if !objects.geometries.contains_key(&connection.child) {
+                continue;
+            }
+            if let Some(model) = lookup.get(&connection.parent) {
+                geometry_models.entry(connection.child).or_default().push(*model);
+            }
+        }
+        geometry_models
+    }
+    /// # link_morphs
+    ///
+    /// Blend shapes go shape geometry -> channel -> blend shape -> geometry -> model in `Connections`.
+    /// The shapes get mapped onto the welded vertices of every model separately, same as skins.
+    fn link_morphs(objects: &FbxObjects, connections: &[Connection], geometry_models: &HashMap<i64, Vec<usize>>, graph: &SceneGraph, models: &mut [StandardModelData]) -> Result<(), ModelLoadError> {
+        let mut blend_shape_geometry: Vec<(i64, i64)> = vec![];
+        let mut channel_blend_shape: Vec<(i64, i64)> = vec![];
+        let mut shape_channel: Vec<(i64, i64)> = vec![];
+        for connection in connections.iter() {
+            if objects.blend_shapes.contains(&connection.child) && objects.geometries.contains_key(&connection.parent) {
+                blend_shape_geometry.push((connection.child, connection.parent));
+            } else if objects.blend_shape_channels.contains_key(&connection.child) && objects.blend_shapes.contains(&connection.parent) {
+                channel_blend_shape.push((connection.child, connection.parent));
+            } else if objects.shapes.contains_key(&connection.child) && objects.blend_shape_channels.contains_key(&connection.parent) {
+                shape_channel.push((connection.child, connection.parent));
+            }
+        }
+        for (blend_shape, geometry) in blend_shape_geometry {
+            let control_point_count = objects.geometries[&geometry].vertices.len();
+            for model in geometry_models.get(&geometry).into_iter().flatten() {
+                let node = &graph.nodes[*model];
+                let matrix = graph.conversion * node.transform.geometric_matrix();
+                for (channel_id, _) in channel_blend_shape.iter().filter(|(_, parent)| *parent == blend_shape) {
+                    let data = &objects.blend_shape_channels[channel_id];
+                    let mut channel = MorphChannel {
+                        tag: data.tag.clone(),
+                        id: *channel_id,
+                        weight: data.deform_percent / 100.0,
+                        shapes: vec![],
+                    };
+                    let shapes = shape_channel.iter().filter(|(_, parent)| parent == channel_id);
+                    for (i, (shape, _)) in shapes.enumerate() {
+                        // Without FullWeights a single shape is fully on at 100%.
+                        let full_weight = data.full_weights.get(i).map(|w| *w as f32 / 100.0).unwrap_or(1.0);
+                        let built = MorphShape::build(&objects.shapes[shape], full_weight, &models[*model].control_points, control_point_count, &matrix)?;
+                        channel.shapes.push(built);
                     }
+                    channel.shapes.sort_by(|a, b| a.full_weight.total_cmp(&b.full_weight));
+                    models[*model].morph_channels.push(channel);
                 }
             }
         }
-        model_sheis
+        Ok(())
     }
-    fn get_indices(geometry: &Geometry) -> Vec<u32>{
-        let indices: Vec<u32> = geometry.polygon_indices.iter().map(|index| {
-            if index.is_negative() {
-                !*index as u32
-            } else {
-                *index as u32
+    /// # link_materials
+    ///
+    /// Hooks videos up to textures and textures up to the material properties they
+    /// are connected to, then hands out the finished materials.
+    fn link_materials(objects: &mut FbxObjects, connections: &[Connection], options: &LoadOptions) -> HashMap<i64, Rc<Material>> {
+        for connection in connections.iter() {
+            if let (Some(video), Some(texture)) = (objects.videos.get(&connection.child), objects.textures.get_mut(&connection.parent)) {
+                texture.fill_from_video(video);
             }
+        }
+        let textures: HashMap<i64, Rc<MaterialTexture>> = objects.textures.drain().map(|(id, mut texture)| {
+            texture.resolve(options.base_directory.as_deref());
+            (id, Rc::new(texture))
         }).collect();
-        indices
+        for connection in connections.iter() {
+            if let (Some(texture), Some(material), Some(property)) = (textures.get(&connection.child), objects.materials.get_mut(&connection.parent), &connection.property) {
+                material.add_texture(property, texture.clone());
+            }
+        }
+        objects.materials.drain().map(|(id, material)| (id, Rc::new(material))).collect()
+    }
+    /// Moves the vertices and normals of the model by `matrix`. Used to bake the
+    /// geometric transform in since it isnt passed on to child models.
+    /// A mirroring matrix also flips the winding of the triangles back so they keep facing outwards.
+    pub fn apply_matrix(&mut self, matrix: &FMat4) {
+        let normal_matrix = transform::normal_matrix(matrix);
+        if transform::is_mirroring(matrix) {
+            for triangle in self.indices.chunks_exact_mut(3) {
+                triangle.swap(1, 2);
+            }
+        }
+        for vertex in self.vertices.iter_mut() {
+            *vertex = transform::transform_point(matrix, *vertex);
+        }
+        for normal in self.normals.iter_mut() {
+            *normal = safe_normalize(transform::transform_vector(&normal_matrix, *normal));
+        }
+    }
+    /// # apply_geometry
+    ///
+    /// Triangulates the geometry and welds its corners into the vertices of this model.
+    /// Vertices only get shared when position, normal, uvs and color all match, so a cube
+    /// ends up with 24 vertices while a smooth mesh keeps roughly one per control point.
+    fn apply_geometry(&mut self, geometry: &Geometry, options: &LoadOptions) -> Result<(), ModelLoadError> {
+        let corners = geometry.triangles()?;
+        let generated = match (geometry.normal.layer(), options.missing_normals) {
+            (None, GenerateNormals::Smooth) => Some(geometry.smooth_normals(&corners)),
+            (None, GenerateNormals::Flat) => Some(geometry.flat_normals(&corners)),
+            _ => None,
+        };
+        // Triangles get grouped by material so every material ends up as one range of indices.
+        let triangle_materials: Vec<usize> = corners.chunks_exact(3).map(|triangle| {
+            let corner = triangle[0];
+            geometry.materials.as_ref()
+                .and_then(|layer| layer.get(corner.polygon_vertex, corner.control_point, corner.polygon))
+                .map(|material| material.max(0) as usize)
+                .unwrap_or(0)
+        }).collect();
+        let mut triangle_order: Vec<usize> = (0..triangle_materials.len()).collect();
+        triangle_order.sort_by_key(|triangle| triangle_materials[*triangle]);
+
+        let mut builder = MeshBuilder::with_capacity(geometry.uvs.len(), corners.len());
+        let mut uvs = vec![FVec2::default(); geometry.uvs.len()];
+        let white = FVec4::new(1.0, 1.0, 1.0, 1.0);
+        self.submeshes.clear();
+        for (i, corner) in triangle_order.iter().flat_map(|triangle| (triangle * 3)..(triangle * 3 + 3)).map(|i| (i, &corners[i])) {
+            if i % 3 == 0 {
+                let material = triangle_materials[i / 3];
+                match self.submeshes.last_mut() {
+                    Some(submesh) if submesh.material == material => submesh.index_count += 3,
+                    _ => self.submeshes.push(SubMesh {
+                        material,
+                        first_index: builder.index_count() as u32,
+                        index_count: 3,
+                    }),
+                }
+            }
+            let normal = match (geometry.normal.layer(), &generated) {
+                (Some(layer), _) => layer.get(corner.polygon_vertex, corner.control_point, corner.polygon).ok_or_else(|| ModelLoadError::IndexOutOfRange {
+                    path: format!("Objects/Geometry({})/LayerElementNormal/Normals", geometry.tag),
+                    index: corner.polygon_vertex,
+                    len: layer.values.len(),
+                })?,
+                (None, Some(generated)) => generated[i],
+                (None, None) => FVec3::from(0.0),
+            };
+            for (set, layer) in geometry.uvs.iter().enumerate() {
+                let uv = layer.get(corner.polygon_vertex, corner.control_point, corner.polygon).ok_or_else(|| ModelLoadError::IndexOutOfRange {
+                    path: format!("Objects/Geometry({})/LayerElementUV({})", geometry.tag, layer.name),
+                    index: corner.polygon_vertex,
+                    len: layer.values.len(),
+                })?;
+                uvs[set] = FVec2::new(uv.x, 1.0 - uv.y);
+            }
+            let color = match &geometry.colors {
+                Some(layer) => layer.get(corner.polygon_vertex, corner.control_point, corner.polygon).ok_or_else(|| ModelLoadError::IndexOutOfRange {
+                    path: format!("Objects/Geometry({})/LayerElementColor/Colors", geometry.tag),
+                    index: corner.polygon_vertex,
+                    len: layer.values.len(),
+                })?,
+                None => white,
+            };
+            builder.push(BuilderCorner {
+                control_point: corner.control_point as u32,
+                position: geometry.vertices[corner.control_point],
+                normal,
+                uvs: &uvs,
+                color,
+            });
+        }
+        let mesh = builder.build();
+
+        self.vertices = mesh.vertices.iter().map(|v| v.pos).collect();
+        self.normals = match (geometry.normal.layer(), &generated) {
+            (None, None) => vec![],
+            _ => mesh.vertices.iter().map(|v| v.normal).collect(),
+        };
+        self.uvs = geometry.uvs.iter().zip(mesh.uvs.into_iter()).map(|(layer, uvs)| UVSet {
+            name: layer.name.clone(),
+            uvs,
+        }).collect();
+        self.colors = match geometry.colors {
+            Some(_) => mesh.colors,
+            None => vec![],
+        };
+        self.control_points = mesh.control_points;
+        self.indices = mesh.indices;
+        Ok(())
+    }
+    /// Saved weight of every channel in `morph_channels`.
+    pub fn morph_weights(&self) -> Vec<f32> {
+        self.morph_channels.iter().map(|channel| channel.weight).collect()
+    }
+    /// # morphed
+    ///
+    /// Positions and normals with the blend shapes applied, `weights[i]` (0 to 1) goes with
+    /// `morph_channels[i]`. The model itself stays untouched so this can run every frame.
+    pub fn morphed(&self, weights: &[f32]) -> (Vec<FVec3>, Vec<FVec3>) {
+        let mut positions = self.vertices.clone();
+        let mut normals = self.normals.clone();
+        morph::blend_morphs(&self.morph_channels, weights, &mut positions, &mut normals);
+        (positions, normals)
+    }
+    /// Interleaves the model into the vertex type the debug pipeline uses.
+    /// Missing normals or uvs come out as zero.
+    pub fn debug_vertices(&self) -> Vec<GlobalDebugVertex> {
+        let uvs = self.uvs.first();
+        self.vertices.iter().enumerate().map(|(i, pos)| GlobalDebugVertex {
+            pos: *pos,
+            normal: self.normals.get(i).copied().unwrap_or_default(),
+            uv: uvs.and_then(|set| set.uvs.get(i)).copied().unwrap_or_default(),
+        }).collect()
+    }
+    /// Interleaves the model into `Vertex3DRGB` for the `vertex3rgb` pipeline.
+    /// Alpha gets dropped and models without vertex colors come out white.
+    pub fn rgb_vertices(&self) -> Vec<Vertex3DRGB> {
+        self.vertices.iter().enumerate().map(|(i, coords)| Vertex3DRGB {
+            coords: *coords,
+            rgb: self.colors.get(i).map(|c| FVec3::new(c.x, c.y, c.z)).unwrap_or(FVec3::from(1.0)),
+        }).collect()
     }
-    fn parse_objects(node: &NodeHandle) -> (HashMap::<i64, Geometry>, HashMap::<i64,ModelData>, HashMap::<i64,Rc<Material>>) {
-        let mut geometries = HashMap::<i64, Geometry>::new();
-        let mut models = HashMap::<i64,ModelData>::new();
-        let mut materials = HashMap::<i64,Rc<Material>>::new();
+    /// Interleaves the model into `Vertex3DSkinned`. Models without a skin come out
+    /// with every weight at zero, which the skinning shader should treat as unskinned.
+    pub fn skinned_vertices(&self) -> Vec<Vertex3DSkinned> {
+        let uvs = self.uvs.first();
+        self.vertices.iter().enumerate().map(|(i, pos)| Vertex3DSkinned {
+            pos: *pos,
+            normal: self.normals.get(i).copied().unwrap_or_default(),
+            uv: uvs.and_then(|set| set.uvs.get(i)).copied().unwrap_or_default(),
+            joints: self.skin.as_ref().map(|skin| skin.joint_indices[i]).unwrap_or_default(),
+            weights: self.skin.as_ref().map(|skin| skin.joint_weights[i]).unwrap_or_default(),
+        }).collect()
+    }
+    fn parse_objects(node: &NodeHandle) -> Result<FbxObjects, ModelLoadError> {
+        let mut objects = FbxObjects::default();
 
         for child in node.children() {
             match child.name() {
+                "Geometry" if child.attr_str(2)? == "Shape" => {
+                    let (id, shape) = ShapeGeometry::parse(&child)?;
+                    objects.shapes.insert(id, shape);
+                }
                 "Geometry" => {
-                    let (id, geo) = Self::parse_geometry(&child);
-                    geometries.insert(id, geo);
+                    let (id, geo) = Self::parse_geometry(&child)?;
+                    objects.geometries.insert(id, geo);
                 }
                 "Model" => {
-                    let (id, model) = Self::parse_model(&child);
-                    models.insert(id, model);
+                    let (id, model) = Self::parse_model(&child)?;
+                    if objects.models.insert(id, model).is_none() {
+                        objects.model_order.push(id);
+                    }
                 }
                 "Material" => {
-                    let (id, material) = Self::parse_material(&child);
-                    materials.insert(id, material);
+                    let (id, material) = Material::parse(&child)?;
+                    objects.materials.insert(id, material);
+                }
+                "Texture" => {
+                    let (id, texture) = MaterialTexture::parse_texture(&child)?;
+                    objects.textures.insert(id, texture);
+                }
+                "Video" => {
+                    let (id, video) = MaterialTexture::parse_video(&child)?;
+                    objects.videos.insert(id, video);
+                }
+                "Deformer" => {
+                    match child.attr_str(2)? {
+                        "Skin" => {
+                            objects.skins.insert(child.attr_i64(0)?);
+                        }
+                        "Cluster" => {
+                            let (id, cluster) = Cluster::parse(&child)?;
+                            objects.clusters.insert(id, cluster);
+                        }
+                        "BlendShape" => {
+                            objects.blend_shapes.insert(child.attr_i64(0)?);
+                        }
+                        "BlendShapeChannel" => {
+                            let (id, channel) = ChannelData::parse(&child)?;
+                            objects.blend_shape_channels.insert(id, channel);
+                        }
+                        _ => {}
+                    }
+                }
+                "Pose" => {
+                    if child.attr_str(2)? == "BindPose" {
+                        objects.bind_poses.extend(skin::parse_bind_pose(&child)?);
+                    }
+                }
+                _ => {
+                    objects.animations.parse(&child)?;
                 }
-                _ => {}
             }
         }
-        (geometries, models, materials)
+        Ok(objects)
     }
-    fn parse_geometry(node: &NodeHandle) -> (i64, Geometry) {
+    fn parse_geometry(node: &NodeHandle) -> Result<(i64, Geometry), ModelLoadError> {
         let mut geo = Geometry {
             tag: String::new(),
             vertices: vec![],
             polygon_indices: vec![],
             edges: vec![],
             normal: GeometryNormal::None,
+            uvs: vec![],
+            colors: None,
+            materials: None,
         };
-        let collection_id = node.attributes()[0].get_i64().unwrap();
-        geo.tag = node.attributes()[1].get_string().unwrap().into();
+        let collection_id = node.attr_i64(0)?;
+        geo.tag = node.attr_str(1)?.into();
         for child in node.children() {
             match child.name() {
                 "Vertices" => {
-                    let attribute = child.attributes();
-                    let verticesf64 = attribute[0].get_arr_f64().unwrap();
-                    let mut verticesf32 = Vec::<FVec3>::with_capacity(verticesf64.len() / 3);
-                    let mut i = 0;
-                    while (i < verticesf64.len()) {
-                        verticesf32.push(FVec3::new(
-                            verticesf64[i] as f32, 
-                            verticesf64[i + 1] as f32, 
-                            verticesf64[i+ 2] as f32)
-                        );
-                        i += 3;
-                    }
-                    geo.vertices = verticesf32;
+                    geo.vertices = to_fvec3(&child, child.attr_arr_f64(0)?)?;
                 }
                 "PolygonVertexIndex" => {
-                    let attribute = child.attributes();
-                    let indicesi32 = attribute[0].get_arr_i32().unwrap().to_vec();
-                    geo.polygon_indices = indicesi32;
+                    geo.polygon_indices = child.attr_arr_i32(0)?.to_vec();
                 }
                 "Edges" => {
-                    let attribute = child.attributes();
-                    let edges = attribute[0].get_arr_i32().unwrap().to_vec();
-                    geo.edges = edges;
+                    geo.edges = child.attr_arr_i32(0)?.to_vec();
                 }
                 "LayerElementNormal" => {
-                    let mut normals = Vec::<f64>::new();
-                    let mut mapping = String::new();
-                    for element in child.children() {
-                        match element.name() {
-                            "MappingInformationType" => {
-                                mapping = element.attributes()[0].get_string().unwrap().into();
-                            }
-                            "Normals" => {
-                                normals = element.attributes()[0].get_arr_f64().unwrap().to_vec();
-                            }
-                            _ => {}
-                        }
+                    // Only the first normal layer gets used, nobody exports more than one anyway.
+                    if geo.normal.layer().is_none() {
+                        let layer = Self::parse_layer_element(&child, "Normals", "NormalsIndex", to_fvec3)?;
+                        geo.normal = GeometryNormal::from_layer(layer);
                     }
-                    if mapping == "ByPolygonVertex" {
-                        let mut normalsf32 = Vec::<FVec3>::with_capacity(normals.len() / 3);
-                        let mut i = 0;
-                        while (i < normals.len()) {
-                            normalsf32.push(FVec3::new(
-                                normals[i] as f32, 
-                                normals[i + 1] as f32, 
-                                normals[i+ 2] as f32)
-                            );
-                            i += 3;
-                        }
-                        geo.normal = GeometryNormal::ByPolygonVertex(normalsf32);
+                }
+                "LayerElementUV" => {
+                    let layer = Self::parse_layer_element(&child, "UV", "UVIndex", to_fvec2)?;
+                    geo.uvs.push(layer);
+                }
+                "LayerElementColor" => {
+                    if geo.colors.is_none() {
+                        geo.colors = Some(Self::parse_layer_element(&child, "Colors", "ColorIndex", to_fvec4)?);
+                    }
+                }
+                "LayerElementMaterial" => {
+                    if geo.materials.is_none() {
+                        geo.materials = Some(Self::parse_material_layer(&child)?);
                     }
                 }
                 _ => {}
             }
         }
-        (collection_id, geo)
+        Ok((collection_id, geo))
     }
-    fn parse_model(node: &NodeHandle) -> (i64, ModelData) {
-        let mut model = ModelData {
-            tag: String::new(),
-            transform: TransformQuaternion3D::default(),
+    /// # parse_layer_element
+    ///
+    /// Reads the common parts of a `LayerElement*` node. `values` and `index` are
+    /// the names of the child nodes holding the data, for example `UV` and `UVIndex`.
+    fn parse_layer_element<T>(
+        node: &NodeHandle,
+        values: &str,
+        index: &str,
+        convert: fn(&NodeHandle, &[f64]) -> Result<Vec<T>, ModelLoadError>,
+    ) -> Result<LayerElement<T>, ModelLoadError> {
+        let mut layer = LayerElement::<T> {
+            name: String::new(),
+            mapping: LayerMapping::None,
+            reference: LayerReference::Direct,
+            values: vec![],
+            indices: vec![],
         };
-        let collection_id = node.attributes()[0].get_i64().unwrap();
-        model.tag = node.attributes()[1].get_string().unwrap().into();
-        for child in node.children() {
-            match child.name() {
-                "Properties70" => {
-                    for property in child.children() {
-                        let attribute_name = property.attributes()[0].get_string().unwrap();
-                        match attribute_name {
-                            "Translation" => {
-                                model.transform.translation.x = property.attributes()[4].get_f64().unwrap() as f32;
-                                model.transform.translation.y = property.attributes()[5].get_f64().unwrap() as f32;
-                                model.transform.translation.z = property.attributes()[6].get_f64().unwrap() as f32;
-                            }
-                            "Rotation" => {
-                                let euler = FVec3::new(
-                                    property.attributes()[4].get_f64().unwrap() as f32, 
-                                    property.attributes()[5].get_f64().unwrap() as f32, 
-                                    property.attributes()[6].get_f64().unwrap() as f32,
-                                );
-                                model.transform.rotation = Quaternion::from_euler(euler);
-                                // model.transform.rotation.x = property.attributes()[4].get_f64().unwrap() as f32;
-                                // model.transform.rotation.y = property.attributes()[5].get_f64().unwrap() as f32;
-                                // model.transform.rotation.z = property.attributes()[6].get_f64().unwrap() as f32;
-                            }
-                            "Scaling" => {
-                                model.transform.scale.x = property.attributes()[4].get_f64().unwrap() as f32;
-                                model.transform.scale.y = property.attributes()[5].get_f64().unwrap() as f32;
-                                model.transform.scale.z = property.attributes()[6].get_f64().unwrap() as f32;
-                            }
-                            _ => {}
-                        }
-                    }
+        for element in node.children() {
+            match element.name() {
+                "Name" => {
+                    layer.name = element.attr_str(0)?.into();
+                }
+                "MappingInformationType" => {
+                    layer.mapping = LayerMapping::from_fbx(element.attr_str(0)?);
+                }
+                "ReferenceInformationType" => {
+                    layer.reference = match element.attr_str(0)? {
+                        "IndexToDirect" | "Index" => LayerReference::IndexToDirect,
+                        _ => LayerReference::Direct,
+                    };
+                }
+                name if name == values => {
+                    layer.values = convert(&element, element.attr_arr_f64(0)?)?;
+                }
+                name if name == index => {
+                    layer.indices = element.attr_arr_i32(0)?.to_vec();
                 }
                 _ => {}
             }
         }
-        (collection_id, model)
+        if layer.reference == LayerReference::IndexToDirect && layer.indices.is_empty() {
+            return Err(ModelLoadError::MissingNode { path: format!("{}/{}", node.path(), index) });
+        }
+        Ok(layer)
     }
-    fn parse_material(node: &NodeHandle) -> (i64, Rc<Material>) {
-        let mut material = Material {
+    /// # parse_material_layer
+    ///
+    /// `LayerElementMaterial` says `IndexToDirect` but the `Materials` array already
+    /// holds the material index of every polygon (or one index for `AllSame`), it indexes
+    /// into the materials connected to the model in the order they are connected.
+    fn parse_material_layer(node: &NodeHandle) -> Result<LayerElement<i32>, ModelLoadError> {
+        let mut layer = LayerElement::<i32>::default();
+        for element in node.children() {
+            match element.name() {
+                "Name" => layer.name = element.attr_str(0)?.into(),
+                "MappingInformationType" => layer.mapping = LayerMapping::from_fbx(element.attr_str(0)?),
+                "Materials" => layer.values = element.attr_arr_i32(0)?.to_vec(),
+                _ => {}
+            }
+        }
+        Ok(layer)
+    }
+    fn parse_model(node: &NodeHandle) -> Result<(i64, ModelData), ModelLoadError> {
+        let mut model = ModelData {
             tag: String::new(),
-            diffuse: FVec3::from(0.0),
+            transform: ModelTransform::default(),
         };
-
-        let collection_id = node.attributes()[0].get_i64().unwrap();
-        material.tag = node.attributes()[1].get_string().unwrap().into();
-
+        let collection_id = node.attr_i64(0)?;
+        model.tag = node.attr_str(1)?.into();
         for child in node.children() {
             match child.name() {
                 "Properties70" => {
                     for property in child.children() {
-                        let attribute_name = property.attributes()[0].get_string().unwrap();
-                        match attribute_name {
-                            "DiffuseColor" => {
-                                material.diffuse.x = property.attributes()[4].get_f64().unwrap() as f32;
-                                material.diffuse.y = property.attributes()[5].get_f64().unwrap() as f32;
-                                material.diffuse.z = property.attributes()[6].get_f64().unwrap() as f32;
-                            }
+                        let transform = &mut model.transform;
+                        // Older exporters leave out the `Lcl ` prefix.
+                        match property.attr_str(0)? {
+                            "Lcl Translation" | "Translation" => transform.translation = property_fvec3(&property)?,
+                            "Lcl Rotation" | "Rotation" => transform.rotation = property_fvec3(&property)?,
+                            "Lcl Scaling" | "Scaling" => transform.scaling = property_fvec3(&property)?,
+                            "PreRotation" => transform.pre_rotation = property_fvec3(&property)?,
+                            "PostRotation" => transform.post_rotation = property_fvec3(&property)?,
+                            "RotationOffset" => transform.rotation_offset = property_fvec3(&property)?,
+                            "RotationPivot" => transform.rotation_pivot = property_fvec3(&property)?,
+                            "ScalingOffset" => transform.scaling_offset = property_fvec3(&property)?,
+                            "ScalingPivot" => transform.scaling_pivot = property_fvec3(&property)?,
+                            "GeometricTranslation" => transform.geometric_translation = property_fvec3(&property)?,
+                            "GeometricRotation" => transform.geometric_rotation = property_fvec3(&property)?,
+                            "GeometricScaling" => transform.geometric_scaling = property_fvec3(&property)?,
+                            "RotationOrder" => transform.rotation_order = RotationOrder::from_fbx(property.attr_i64(4)?),
                             _ => {}
                         }
                     }
@@ -318,7 +1047,7 @@ impl StandardModelData {
                 _ => {}
             }
         }
-        (collection_id, Rc::new(material))
+        Ok((collection_id, model))
     }
     /// # parse_connections
     ///
@@ -326,18 +1055,22 @@ impl StandardModelData {
     /// At the very start of connections the models get assigned to
     /// id: 0 to initialize them. After this different nodes such as
     /// geometry and material nodes get added to the model via connections.
-    /// the way they get assigned is lhs -> rhs. 
+    /// the way they get assigned is lhs -> rhs. A model connected to another
+    /// model is a child of it, `SceneGraph::build` takes care of those.
     /// 
     /// ### Note: This could very well be wrong since I just gathered this information
     /// ### from parsing various fbx files and looking at the similarities and making assumptions.
-    fn parse_connections(node: &NodeHandle) -> Vec<(i64, i64)> {
-        let mut connections: Vec<(i64, i64)> = vec![];
+    fn parse_connections(node: &NodeHandle) -> Result<Vec<Connection>, ModelLoadError> {
+        let mut connections: Vec<Connection> = vec![];
         for child in node.children() {
-            let attributelist = child.attributes();
-            let lhs = attributelist[1].get_i64().unwrap();
-            let rhs = attributelist[2].get_i64().unwrap();
-            connections.push((lhs, rhs));
+            let property = match child.attr_str(0)? {
+                "OP" => Some(child.attr_str(3)?.to_string()),
+                _ => None,
+            };
+            let lhs = child.attr_i64(1)?;
+            let rhs = child.attr_i64(2)?;
+            connections.push(Connection { child: lhs, parent: rhs, property });
         }
-        connections
+        Ok(connections)
     }
 }
\ No newline at end of file
@@ -0,0 +1,206 @@
+#![allow(unused)]
+use drowsed_math::{FMat4, FVec3};
+use fbxcel_dom::fbxcel::tree::v7400::NodeHandle;
+
+use super::animation::AnimationStack;
+use super::error::ModelLoadError;
+use super::model_loader::{NodeAttributes, to_fvec3};
+use super::transform::{normal_matrix, transform_vector};
+
+/// # ShapeGeometry
+///
+/// A `Geometry` of type `Shape` as it is in the file. Only the control points it
+/// moves are stored, `indices[i]` gets moved by `positions[i]`.
+#[derive(Debug, Clone, Default)]
+pub struct ShapeGeometry {
+    pub tag: String,
+    pub indices: Vec<i32>,
+    pub positions: Vec<FVec3>,
+    pub normals: Vec<FVec3>,
+}
+impl ShapeGeometry {
+    pub(crate) fn parse(node: &NodeHandle) -> Result<(i64, ShapeGeometry), ModelLoadError> {
+        let collection_id = node.attr_i64(0)?;
+        let mut shape = ShapeGeometry {
+            tag: node.attr_str(1)?.into(),
+            ..Default::default()
+        };
+        for child in node.children() {
+            match child.name() {
+                "Indexes" => shape.indices = child.attr_arr_i32(0)?.to_vec(),
+                "Vertices" => shape.positions = to_fvec3(&child, child.attr_arr_f64(0)?)?,
+                "Normals" => shape.normals = to_fvec3(&child, child.attr_arr_f64(0)?)?,
+                _ => {}
+            }
+        }
+        if shape.indices.len() != shape.positions.len() {
+            return Err(ModelLoadError::IndexOutOfRange {
+                path: format!("{}/Vertices", node.path()),
+                index: shape.indices.len(),
+                len: shape.positions.len(),
+            });
+        }
+        // Normals are optional, but if they are there they have to line up with the indices.
+        if !shape.normals.is_empty() && shape.normals.len() != shape.indices.len() {
+            shape.normals.clear();
+        }
+        Ok((collection_id, shape))
+    }
+}
+
+/// A `Deformer` of type `BlendShapeChannel` before it gets hooked up to its shapes.
+/// `full_weights` are in percent, one per shape for in-between shapes.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ChannelData {
+    pub tag: String,
+    pub deform_percent: f32,
+    pub full_weights: Vec<f64>,
+}
+impl ChannelData {
+    pub(crate) fn parse(node: &NodeHandle) -> Result<(i64, ChannelData), ModelLoadError> {
+        let collection_id = node.attr_i64(0)?;
+        let mut channel = ChannelData {
+            tag: node.attr_str(1)?.into(),
+            ..Default::default()
+        };
+        for child in node.children() {
+            match child.name() {
+                "DeformPercent" => channel.deform_percent = child.attr_f64(0)? as f32,
+                "FullWeights" => channel.full_weights = child.attr_arr_f64(0)?.to_vec(),
+                "Properties70" => {
+                    for property in child.children() {
+                        if property.attr_str(0)? == "DeformPercent" {
+                            channel.deform_percent = property.attr_f64(4)? as f32;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok((collection_id, channel))
+    }
+}
+
+/// # MorphShape
+///
+/// One target of a channel, already mapped onto the vertices of a model.
+/// `indices` are vertex indices of the model, `normals` is empty when the file had no normal deltas.
+#[derive(Debug, Clone, Default)]
+pub struct MorphShape {
+    pub tag: String,
+    /// Channel weight (0 to 1) at which this shape is fully applied. Anything but 1
+    /// means it is an in-between shape.
+    pub full_weight: f32,
+    pub indices: Vec<u32>,
+    pub positions: Vec<FVec3>,
+    pub normals: Vec<FVec3>,
+}
+impl MorphShape {
+    /// Turns the per control point deltas of a shape into per vertex ones and moves them into the
+    /// space the model got loaded in. `matrix` is the same one the vertices of the model got.
+    pub(crate) fn build(shape: &ShapeGeometry, full_weight: f32, control_points: &[u32], control_point_count: usize, matrix: &FMat4) -> Result<Self, ModelLoadError> {
+        let mut by_control_point: Vec<Option<usize>> = vec![None; control_point_count];
+        for (i, index) in shape.indices.iter().enumerate() {
+            let slot = usize::try_from(*index).ok().and_then(|index| by_control_point.get_mut(index)).ok_or_else(|| ModelLoadError::IndexOutOfRange {
+                path: format!("Objects/Geometry({})/Indexes", shape.tag),
+                index: (*index).max(0) as usize,
+                len: control_point_count,
+            })?;
+            *slot = Some(i);
+        }
+        let normal_matrix = normal_matrix(matrix);
+        let mut morph = MorphShape {
+            tag: shape.tag.clone(),
+            full_weight,
+            ..Default::default()
+        };
+        for (vertex, control_point) in control_points.iter().enumerate() {
+            let Some(i) = by_control_point[*control_point as usize] else {
+                continue;
+            };
+            morph.indices.push(vertex as u32);
+            morph.positions.push(transform_vector(matrix, shape.positions[i]));
+            if !shape.normals.is_empty() {
+                morph.normals.push(transform_vector(&normal_matrix, shape.normals[i]));
+            }
+        }
+        Ok(morph)
+    }
+    fn add_into(&self, amount: f32, positions: &mut [FVec3], normals: &mut [FVec3]) {
+        if amount == 0.0 {
+            return;
+        }
+        for (i, index) in self.indices.iter().enumerate() {
+            let index = *index as usize;
+            positions[index] += self.positions[i] * amount;
+            if let (Some(delta), Some(normal)) = (self.normals.get(i), normals.get_mut(index)) {
+                *normal += *delta * amount;
+            }
+        }
+    }
+}
+
+/// # MorphChannel
+///
+/// A `BlendShapeChannel`. Usually one shape, more than one means in-between shapes
+/// which get blended depending on where the weight falls between their `full_weight`s.
+#[derive(Debug, Clone, Default)]
+pub struct MorphChannel {
+    pub tag: String,
+    /// FBX id of the channel, `AnimationCurveNode::target_id` of its `DeformPercent` curves.
+    pub id: i64,
+    /// Weight from 0 to 1 the file was saved with.
+    pub weight: f32,
+    /// Sorted by `full_weight`.
+    pub shapes: Vec<MorphShape>,
+}
+impl MorphChannel {
+    /// Weight of the channel at `time` seconds into `stack`, the saved weight if it isnt animated.
+    pub fn animated_weight(&self, stack: &AnimationStack, time: f64) -> f32 {
+        stack.sample_deform_percent(self.id, time).map(|percent| percent / 100.0).unwrap_or(self.weight)
+    }
+    /// Adds the deltas of the channel at `weight` to the given vertices.
+    pub fn add_into(&self, weight: f32, positions: &mut [FVec3], normals: &mut [FVec3]) {
+        let Some(first) = self.shapes.first() else {
+            return;
+        };
+        if weight <= first.full_weight || self.shapes.len() == 1 {
+            let full = if first.full_weight > 0.0 { first.full_weight } else { 1.0 };
+            first.add_into(weight / full, positions, normals);
+            return;
+        }
+        let next = self.shapes.partition_point(|shape| shape.full_weight <= weight);
+        if next >= self.shapes.len() {
+            let last = self.shapes.last().unwrap();
+            last.add_into(weight / last.full_weight, positions, normals);
+            return;
+        }
+        let (a, b) = (&self.shapes[next - 1], &self.shapes[next]);
+        let t = (weight - a.full_weight) / (b.full_weight - a.full_weight);
+        a.add_into(1.0 - t, positions, normals);
+        b.add_into(t, positions, normals);
+    }
+}
+
+/// # blend_morphs
+///
+/// Applies `channels` on top of the base vertices, `weights[i]` (0 to 1) goes with
+/// `channels[i]` and missing weights count as 0. Normals get renormalized afterwards,
+/// pass an empty `normals` for models that have none.
+pub fn blend_morphs(channels: &[MorphChannel], weights: &[f32], positions: &mut [FVec3], normals: &mut [FVec3]) {
+    let mut moved_normals = false;
+    for (channel, weight) in channels.iter().zip(weights.iter()) {
+        if *weight != 0.0 {
+            channel.add_into(*weight, positions, normals);
+            moved_normals |= channel.shapes.iter().any(|shape| !shape.normals.is_empty());
+        }
+    }
+    if moved_normals {
+        for normal in normals.iter_mut() {
+            let length = (normal.x * normal.x + normal.y * normal.y + normal.z * normal.z).sqrt();
+            if length > f32::EPSILON {
+                *normal = *normal * (1.0 / length);
+            }
+        }
+    }
+}
@@ -0,0 +1,364 @@
+#![allow(unused)]
+//! Wavefront OBJ loader, gives back the same `StandardModelData` the FBX loader does.
+use std::{collections::HashMap, path::Path, rc::Rc};
+
+use drowsed_math::{FMat4, FVec2, FVec3, FVec4, SquareMatrix, Vector, EuclideanGeometry};
+
+use super::axis::{Axis, AxisSystem};
+use super::error::ModelLoadError;
+use super::material::Material;
+use super::mesh_builder::{MeshBuilder, BuilderCorner};
+use super::model_loader::{StandardModelData, LoadOptions, GenerateNormals, UVSet, safe_normalize};
+use super::triangulate::triangulate_polygon;
+use crate::vk_obj::rendering::mesh::SubMesh;
+
+/// OBJ has no idea what axes or units are, but pretty much every exporter writes
+/// y up with +z towards the viewer, and meters is as good a guess as any.
+pub const OBJ_AXES: AxisSystem = AxisSystem {
+    right: Axis::PositiveX,
+    up: Axis::PositiveY,
+    front: Axis::PositiveZ,
+    unit_scale: 1.0,
+};
+
+#[derive(Debug, Clone, Copy)]
+struct ObjCorner {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+struct ObjFace {
+    corners: Vec<ObjCorner>,
+    /// Index into `ObjObject::materials`.
+    material: usize,
+}
+#[derive(Default)]
+struct ObjObject {
+    name: String,
+    faces: Vec<ObjFace>,
+    /// Names from `usemtl` in the order the object first uses them, `None` for faces before any `usemtl`.
+    materials: Vec<Option<String>>,
+}
+impl ObjObject {
+    fn material_slot(&mut self, name: &Option<String>) -> usize {
+        match self.materials.iter().position(|material| material == name) {
+            Some(slot) => slot,
+            None => {
+                self.materials.push(name.clone());
+                self.materials.len() - 1
+            }
+        }
+    }
+}
+
+/// Everything the `v`, `vt` and `vn` statements of a file define. Shared between objects.
+#[derive(Default)]
+struct ObjAttributes {
+    positions: Vec<FVec3>,
+    /// Only filled when the file has `v x y z r g b` colors.
+    colors: Vec<FVec4>,
+    uvs: Vec<FVec2>,
+    normals: Vec<FVec3>,
+}
+
+pub fn try_new(filepath: &str) -> Result<Vec<StandardModelData>, ModelLoadError> {
+    try_new_with(filepath, &LoadOptions::default())
+}
+pub fn try_new_with(filepath: &str, options: &LoadOptions) -> Result<Vec<StandardModelData>, ModelLoadError> {
+    let bytes = std::fs::read(filepath)?;
+    parse(&String::from_utf8_lossy(&bytes), &options.for_file(filepath))
+}
+
+/// # parse
+///
+/// Turns the text of an OBJ file into one model per `o` statement. `mtllib` files
+/// get looked up in `options.base_directory`, missing ones just leave the models
+/// with default materials. Polygons get triangulated and every `usemtl` range
+/// becomes a submesh.
+pub fn parse(text: &str, options: &LoadOptions) -> Result<Vec<StandardModelData>, ModelLoadError> {
+    let mut attributes = ObjAttributes::default();
+    let mut library: HashMap<String, Rc<Material>> = HashMap::new();
+    let mut objects: Vec<ObjObject> = vec![ObjObject { name: "default".into(), ..Default::default() }];
+    let mut current_material: Option<String> = None;
+
+    for (number, line) in text.lines().enumerate() {
+        let line_number = number + 1;
+        let error = |message: String| ModelLoadError::ObjSyntax { line: line_number, message };
+        let line = line.split('#').next().unwrap_or("").trim();
+        let mut words = line.split_whitespace();
+        let Some(keyword) = words.next() else {
+            continue;
+        };
+        let numbers = |words: std::str::SplitWhitespace| -> Result<Vec<f32>, ModelLoadError> {
+            words.map(|w| w.parse::<f32>().map_err(|_| error(format!("`{}` is not a number", w)))).collect()
+        };
+        match keyword {
+            "v" => {
+                let values = numbers(words)?;
+                if values.len() < 3 {
+                    return Err(error("a vertex needs at least 3 coordinates".into()));
+                }
+                attributes.positions.push(FVec3::new(values[0], values[1], values[2]));
+                if values.len() >= 6 {
+                    // Vertices before the first colored one didnt have any, they stay white.
+                    attributes.colors.resize(attributes.positions.len() - 1, FVec4::new(1.0, 1.0, 1.0, 1.0));
+                    attributes.colors.push(FVec4::new(values[3], values[4], values[5], 1.0));
+                }
+            }
+            "vt" => {
+                let values = numbers(words)?;
+                let u = values.first().copied().ok_or_else(|| error("a texture coordinate needs at least 1 value".into()))?;
+                // Bottom left origin like FBX, flipped the same way.
+                attributes.uvs.push(FVec2::new(u, 1.0 - values.get(1).copied().unwrap_or(0.0)));
+            }
+            "vn" => {
+                let values = numbers(words)?;
+                if values.len() < 3 {
+                    return Err(error("a normal needs 3 coordinates".into()));
+                }
+                attributes.normals.push(FVec3::new(values[0], values[1], values[2]));
+            }
+            "f" => {
+                let mut corners = vec![];
+                for word in words {
+                    corners.push(parse_corner(word, &attributes).map_err(error)?);
+                }
+                if corners.len() < 3 {
+                    return Err(error("a face needs at least 3 corners".into()));
+                }
+                let object = objects.last_mut().unwrap();
+                let material = object.material_slot(&current_material);
+                object.faces.push(ObjFace { corners, material });
+            }
+            "o" => {
+                let name = line[1..].trim();
+                objects.push(ObjObject { name: name.into(), ..Default::default() });
+            }
+            "usemtl" => current_material = Some(line["usemtl".len()..].trim().into()),
+            "mtllib" => {
+                for file in words {
+                    let path = match &options.base_directory {
+                        Some(directory) => directory.join(file),
+                        None => file.into(),
+                    };
+                    if let Ok(bytes) = std::fs::read(&path) {
+                        let materials = Material::parse_mtl(&String::from_utf8_lossy(&bytes), options.base_directory.as_deref());
+                        library.extend(materials.into_iter().map(|material| (material.tag().to_string(), Rc::new(material))));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let conversion = match &options.target_axes {
+        Some(target) => AxisSystem::conversion(options.source_axes.as_ref().unwrap_or(&OBJ_AXES), target),
+        None => FMat4::identity(),
+    };
+    let mut models = vec![];
+    for object in objects.iter().filter(|object| !object.faces.is_empty()) {
+        let mut model = build_model(object, &attributes, options);
+        // A file that never says `usemtl` has no materials at all, same as an FBX mesh without a material layer.
+        if object.materials.iter().any(Option::is_some) {
+            model.materials = object.materials.iter().map(|name| {
+                let name = name.as_deref().unwrap_or("default");
+                library.get(name).cloned().unwrap_or_else(|| Rc::new(Material::named(name)))
+            }).collect();
+        }
+        model.apply_matrix(&conversion);
+        models.push(model);
+    }
+    Ok(models)
+}
+
+/// One `v/vt/vn` of a face. Indices start at 1, negative ones count back from the last vertex.
+fn parse_corner(word: &str, attributes: &ObjAttributes) -> Result<ObjCorner, String> {
+    let mut parts = word.split('/');
+    let index = |part: Option<&str>, len: usize| -> Result<Option<usize>, String> {
+        let Some(part) = part.filter(|part| !part.is_empty()) else {
+            return Ok(None);
+        };
+        let value: i64 = part.parse().map_err(|_| format!("`{}` is not an index", part))?;
+        let resolved = match value {
+            0 => return Err("indices start at 1".into()),
+            v if v > 0 => v - 1,
+            v => len as i64 + v,
+        };
+        if resolved < 0 || resolved >= len as i64 {
+            return Err(format!("index {} is out of range, only {} are defined", value, len));
+        }
+        Ok(Some(resolved as usize))
+    };
+    let position = index(parts.next(), attributes.positions.len())?.ok_or_else(|| format!("`{}` has no vertex index", word))?;
+    let uv = index(parts.next(), attributes.uvs.len())?;
+    let normal = index(parts.next(), attributes.normals.len())?;
+    Ok(ObjCorner { position, uv, normal })
+}
+
+fn build_model(object: &ObjObject, attributes: &ObjAttributes, options: &LoadOptions) -> StandardModelData {
+    let has_uvs = object.faces.iter().flat_map(|face| face.corners.iter()).any(|corner| corner.uv.is_some());
+    let has_normals = object.faces.iter().flat_map(|face| face.corners.iter()).any(|corner| corner.normal.is_some());
+    let has_colors = !attributes.colors.is_empty();
+
+    // Triangulate first, the generated normals need the area of every face.
+    let mut triangles: Vec<(usize, [ObjCorner; 3])> = vec![];
+    let mut face_normals: Vec<FVec3> = Vec::with_capacity(object.faces.len());
+    for (face_index, face) in object.faces.iter().enumerate() {
+        let points: Vec<FVec3> = face.corners.iter().map(|corner| attributes.positions[corner.position]).collect();
+        let mut normal = FVec3::from(0.0);
+        for [a, b, c] in triangulate_polygon(&points) {
+            normal += (points[b] - points[a]).cross(points[c] - points[a]);
+            triangles.push((face_index, [face.corners[a], face.corners[b], face.corners[c]]));
+        }
+        face_normals.push(normal);
+    }
+    let generate = if has_normals { GenerateNormals::Skip } else { options.missing_normals };
+    let mut smooth_normals: Vec<FVec3> = vec![];
+    if generate == GenerateNormals::Smooth {
+        smooth_normals = vec![FVec3::from(0.0); attributes.positions.len()];
+        for (face, normal) in object.faces.iter().zip(face_normals.iter()) {
+            for corner in face.corners.iter() {
+                smooth_normals[corner.position] += *normal;
+            }
+        }
+    }
+    triangles.sort_by_key(|(face, _)| object.faces[*face].material);
+
+    let mut model = StandardModelData { tag: object.name.clone(), ..Default::default() };
+    let mut builder = MeshBuilder::with_capacity(has_uvs as usize, triangles.len() * 3);
+    let mut uv = [FVec2::default()];
+    let white = FVec4::new(1.0, 1.0, 1.0, 1.0);
+    for (face, triangle) in triangles.iter() {
+        let material = object.faces[*face].material;
+        match model.submeshes.last_mut() {
+            Some(submesh) if submesh.material == material => submesh.index_count += 3,
+            _ => model.submeshes.push(SubMesh { material, first_index: builder.index_count() as u32, index_count: 3 }),
+        }
+        for corner in triangle.iter() {
+            let normal = match (corner.normal, generate) {
+                (Some(normal), _) => attributes.normals[normal],
+                (None, GenerateNormals::Smooth) => safe_normalize(smooth_normals[corner.position]),
+                (None, GenerateNormals::Flat) => safe_normalize(face_normals[*face]),
+                (None, GenerateNormals::Skip) => FVec3::from(0.0),
+            };
+            uv[0] = corner.uv.map(|uv| attributes.uvs[uv]).unwrap_or_default();
+            builder.push(BuilderCorner {
+                control_point: corner.position as u32,
+                position: attributes.positions[corner.position],
+                normal,
+                uvs: &uv[..has_uvs as usize],
+                color: attributes.colors.get(corner.position).copied().unwrap_or(white),
+            });
+        }
+    }
+    let mesh = builder.build();
+    model.vertices = mesh.vertices.iter().map(|v| v.pos).collect();
+    if has_normals || generate != GenerateNormals::Skip {
+        model.normals = mesh.vertices.iter().map(|v| v.normal).collect();
+    }
+    model.uvs = mesh.uvs.into_iter().map(|uvs| UVSet { name: "UVMap".into(), uvs }).collect();
+    if has_colors {
+        model.colors = mesh.colors;
+    }
+    model.control_points = mesh.control_points;
+    model.indices = mesh.indices;
+    model
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Keeps the file's own axes so positions come back exactly as written.
+    fn load(text: &str) -> Result<Vec<StandardModelData>, ModelLoadError> {
+        parse(text, &LoadOptions { target_axes: None, missing_normals: GenerateNormals::Skip, ..Default::default() })
+    }
+    fn positions(model: &StandardModelData) -> Vec<[f32; 3]> {
+        model.indices.iter().map(|index| model.vertices[*index as usize]).map(|p| [p.x, p.y, p.z]).collect()
+    }
+    const QUAD: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 1 1\nvn 0 0 1\n";
+
+    #[test]
+    fn corner_forms() {
+        let full = load(&format!("{}f 1/1/1 2/2/1 3/3/1\n", QUAD)).unwrap();
+        assert_eq!(full.len(), 1);
+        assert_eq!(full[0].indices, vec![0, 1, 2]);
+        assert_eq!(full[0].uvs.len(), 1);
+        // v gets flipped to a top left origin
+        assert_eq!(full[0].uvs[0].uvs[2], FVec2::new(1.0, 0.0));
+        assert_eq!(full[0].uvs[0].uvs[0], FVec2::new(0.0, 1.0));
+        assert!(full[0].normals.iter().all(|n| *n == FVec3::new(0.0, 0.0, 1.0)));
+
+        let normals_only = load(&format!("{}f 1//1 2//1 3//1\n", QUAD)).unwrap();
+        assert!(normals_only[0].uvs.is_empty());
+        assert_eq!(normals_only[0].normals.len(), 3);
+
+        let plain = load(&format!("{}f 1 2 3\n", QUAD)).unwrap();
+        assert!(plain[0].uvs.is_empty() && plain[0].normals.is_empty());
+        assert_eq!(positions(&plain[0]), vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0]]);
+        let flat = parse(&format!("{}f 1 2 3\n", QUAD), &LoadOptions { target_axes: None, missing_normals: GenerateNormals::Flat, ..Default::default() }).unwrap();
+        assert!(flat[0].normals.iter().all(|n| *n == FVec3::new(0.0, 0.0, 1.0)));
+    }
+
+    #[test]
+    fn negative_indices_count_back() {
+        let models = load("v 0 0 0\nv 1 0 0\nv 1 1 0\nf -3 -2 -1\nv 2 0 0\nf -3 -1 -2\n").unwrap();
+        assert_eq!(positions(&models[0]), vec![
+            [0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0],
+            [1.0, 0.0, 0.0], [2.0, 0.0, 0.0], [1.0, 1.0, 0.0],
+        ]);
+        // The second face reuses the vertices of the first one.
+        assert_eq!(models[0].vertices.len(), 4);
+    }
+
+    #[test]
+    fn polygons_get_triangulated() {
+        let quad = load(&format!("{}f 1 2 3 4\n", QUAD)).unwrap();
+        assert_eq!(quad[0].indices.len(), 6);
+        assert_eq!(quad[0].vertices.len(), 4);
+        let pentagon = load("v 0 0 0\nv 2 0 0\nv 3 1 0\nv 1 2 0\nv -1 1 0\nf 1 2 3 4 5\n").unwrap();
+        assert_eq!(pentagon[0].indices.len(), 9);
+        assert_eq!(pentagon[0].vertices.len(), 5);
+        assert_eq!(pentagon[0].submeshes, vec![SubMesh { material: 0, first_index: 0, index_count: 9 }]);
+    }
+
+    #[test]
+    fn usemtl_ranges_become_submeshes() {
+        let text = format!("{}f 1 2 3\nusemtl red\nf 1 3 4\nusemtl blue\nf 1 2 4\nusemtl red\nf 2 3 4\n", QUAD);
+        let models = load(&text).unwrap();
+        let model = &models[0];
+        // Faces before the first usemtl get a material of their own, red gathers both of its faces.
+        assert_eq!(model.submeshes, vec![
+            SubMesh { material: 0, first_index: 0, index_count: 3 },
+            SubMesh { material: 1, first_index: 3, index_count: 6 },
+            SubMesh { material: 2, first_index: 9, index_count: 3 },
+        ]);
+        let tags: Vec<&str> = model.materials.iter().map(|material| material.tag()).collect();
+        assert_eq!(tags, ["default", "red", "blue"]);
+        assert_eq!(positions(model)[3..9], [[0.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]]);
+        // Without any usemtl there are no materials at all.
+        assert!(load(&format!("{}f 1 2 3\n", QUAD)).unwrap()[0].materials.is_empty());
+    }
+
+    #[test]
+    fn o_starts_a_new_model() {
+        let models = load("o first\nv 0 0 0\nv 1 0 0\nv 1 1 0\nf 1 2 3\no second one\nv 5 0 0\nv 6 0 0\nv 6 1 0\nf 4 5 6\no empty\n").unwrap();
+        let tags: Vec<&str> = models.iter().map(|model| model.tag.as_str()).collect();
+        assert_eq!(tags, ["first", "second one"]);
+        assert_eq!(models[1].vertices.len(), 3);
+        assert_eq!(positions(&models[1])[0], [5.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn bad_indices_report_their_line() {
+        let line = |text: &str| match load(text) {
+            Err(ModelLoadError::ObjSyntax { line, .. }) => line,
+            other => panic!("expected a syntax error, got {:?}", other.map(|models| models.len())),
+        };
+        assert_eq!(line("v 0 0 0\nv 1 0 0\nv 1 1 0\n\nf 0 1 2\n"), 5);
+        assert_eq!(line("v 0 0 0\nv 1 0 0\nv 1 1 0\nf 1 2 4\n"), 4);
+        assert_eq!(line("v 0 0 0\nv 1 0 0\n# comment\nv 1 1 0\nf -4 1 2\n"), 5);
+        assert_eq!(line(&format!("{}f 1/4 2/1 3/1\n", QUAD)), 9);
+        assert_eq!(line(&format!("{}f 1//2 2//1 3//1\n", QUAD)), 9);
+    }
+}
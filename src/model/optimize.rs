@@ -0,0 +1,498 @@
+#![allow(unused)]
+//! Reordering triangles and vertices so the GPU does less work drawing them. Nothing
+//! here changes what a mesh looks like, only the order things are stored in.
+//!
+//! The usual order is `optimize_vertex_cache`, then `optimize_overdraw` on the result,
+//! then `optimize_vertex_fetch` last since it renumbers the vertices.
+//! `StandardModelData::optimize` does all three per submesh.
+use drowsed_math::{FVec3, Vector, EuclideanGeometry};
+
+use super::geometry::triangle_normal;
+use super::model_loader::{StandardModelData, safe_normalize};
+
+/// Size of the LRU cache Forsyth's scoring assumes. Real hardware caches are a bit
+/// different but it doesnt matter much, the ordering is good for any size.
+const FORSYTH_CACHE_SIZE: usize = 32;
+const FORSYTH_CACHE_DECAY_POWER: f32 = 1.5;
+const FORSYTH_LAST_TRIANGLE_SCORE: f32 = 0.75;
+const FORSYTH_VALENCE_BOOST_SCALE: f32 = 2.0;
+const FORSYTH_VALENCE_BOOST_POWER: f32 = 0.5;
+
+/// Cache size `acmr` uses by default, what most desktop GPUs behave like.
+pub const DEFAULT_CACHE_SIZE: usize = 16;
+
+/// # CacheStatistics
+///
+/// How a triangle order does on a simulated FIFO vertex cache.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CacheStatistics {
+    pub misses: usize,
+    /// Average cache misses per triangle. 3 is the worst, 0.5 is about the best a regular grid can get.
+    pub acmr: f32,
+    /// Average transforms per vertex, misses divided by the vertices actually used. 1 is perfect.
+    pub atvr: f32,
+}
+
+/// # cache_statistics
+///
+/// Runs `indices` through a FIFO cache of `cache_size` vertices and counts the misses.
+pub fn cache_statistics(indices: &[u32], vertex_count: usize, cache_size: usize) -> CacheStatistics {
+    let mut timestamps = vec![0usize; vertex_count];
+    let mut used = vec![false; vertex_count];
+    // Starts above the cache size so nothing counts as cached before it was seen.
+    let mut time = cache_size + 1;
+    let mut misses = 0;
+    for index in indices.iter() {
+        let vertex = *index as usize;
+        used[vertex] = true;
+        if time - timestamps[vertex] > cache_size {
+            timestamps[vertex] = time;
+            time += 1;
+            misses += 1;
+        }
+    }
+    let triangles = indices.len() / 3;
+    let used = used.iter().filter(|used| **used).count();
+    CacheStatistics {
+        misses,
+        acmr: if triangles == 0 { 0.0 } else { misses as f32 / triangles as f32 },
+        atvr: if used == 0 { 0.0 } else { misses as f32 / used as f32 },
+    }
+}
+/// ACMR with the default cache size.
+pub fn acmr(indices: &[u32], vertex_count: usize) -> f32 {
+    cache_statistics(indices, vertex_count, DEFAULT_CACHE_SIZE).acmr
+}
+
+fn forsyth_score(cache_position: Option<usize>, remaining: u32) -> f32 {
+    if remaining == 0 {
+        return -1.0;
+    }
+    let mut score = match cache_position {
+        // The last triangle's vertices get a fixed score so the very same triangle
+        // isnt favoured over its neighbours.
+        Some(position) if position < 3 => FORSYTH_LAST_TRIANGLE_SCORE,
+        Some(position) => {
+            let scale = 1.0 / (FORSYTH_CACHE_SIZE - 3) as f32;
+            (1.0 - (position - 3) as f32 * scale).powf(FORSYTH_CACHE_DECAY_POWER)
+        }
+        None => 0.0,
+    };
+    // Vertices with few triangles left get finished off first so they dont end up stranded.
+    score += FORSYTH_VALENCE_BOOST_SCALE * (remaining as f32).powf(-FORSYTH_VALENCE_BOOST_POWER);
+    score
+}
+
+/// # optimize_vertex_cache
+///
+/// Tom Forsyth's linear speed vertex cache optimization. Greedily picks the next triangle
+/// by how many of its vertices are still in a simulated LRU cache and how few triangles
+/// they have left. Hands back the same triangles in a new order, the winding of every
+/// triangle stays the same.
+pub fn optimize_vertex_cache(indices: &[u32], vertex_count: usize) -> Vec<u32> {
+    let triangle_count = indices.len() / 3;
+    if triangle_count == 0 {
+        return vec![];
+    }
+    // Triangles of every vertex, packed into one list with offsets.
+    let mut valence = vec![0u32; vertex_count];
+    for index in indices[..triangle_count * 3].iter() {
+        valence[*index as usize] += 1;
+    }
+    let mut offsets = vec![0usize; vertex_count + 1];
+    for vertex in 0..vertex_count {
+        offsets[vertex + 1] = offsets[vertex] + valence[vertex] as usize;
+    }
+    let mut adjacency = vec![0u32; offsets[vertex_count]];
+    let mut filled = offsets.clone();
+    for (triangle, corners) in indices.chunks_exact(3).enumerate() {
+        for vertex in corners.iter() {
+            adjacency[filled[*vertex as usize]] = triangle as u32;
+            filled[*vertex as usize] += 1;
+        }
+    }
+
+    let mut remaining = valence.clone();
+    let mut cache_position: Vec<Option<usize>> = vec![None; vertex_count];
+    let mut vertex_scores: Vec<f32> = (0..vertex_count).map(|v| forsyth_score(None, remaining[v])).collect();
+    let mut triangle_scores: Vec<f32> = indices.chunks_exact(3).map(|t| t.iter().map(|v| vertex_scores[*v as usize]).sum()).collect();
+    let mut emitted = vec![false; triangle_count];
+
+    let mut cache: Vec<u32> = Vec::with_capacity(FORSYTH_CACHE_SIZE + 3);
+    let mut output = Vec::with_capacity(triangle_count * 3);
+    let mut best = triangle_scores.iter().enumerate().max_by(|a, b| a.1.total_cmp(b.1)).map(|(t, _)| t);
+    // Where to look for a leftover triangle when nothing in the cache is usable.
+    let mut cursor = 0;
+    while let Some(triangle) = best {
+        emitted[triangle] = true;
+        let corners = &indices[triangle * 3..triangle * 3 + 3];
+        output.extend_from_slice(corners);
+
+        for vertex in corners.iter() {
+            let v = *vertex as usize;
+            // Take the triangle out of the active ones of the vertex.
+            let start = offsets[v];
+            let active = &mut adjacency[start..start + remaining[v] as usize];
+            if let Some(position) = active.iter().position(|t| *t as usize == triangle) {
+                let last = active.len() - 1;
+                active.swap(position, last);
+                remaining[v] -= 1;
+            }
+        }
+        // Most recently used first.
+        let mut new_cache: Vec<u32> = corners.to_vec();
+        new_cache.extend(cache.iter().filter(|v| !corners.contains(v)));
+        let evicted: Vec<u32> = new_cache.iter().skip(FORSYTH_CACHE_SIZE).copied().collect();
+        new_cache.truncate(FORSYTH_CACHE_SIZE);
+        cache = new_cache;
+
+        for vertex in evicted.iter() {
+            cache_position[*vertex as usize] = None;
+        }
+        for (position, vertex) in cache.iter().enumerate() {
+            cache_position[*vertex as usize] = Some(position);
+        }
+        // Only the vertices that were touched changed score, and only their triangles need updating.
+        best = None;
+        let mut best_score = -1.0;
+        for vertex in cache.iter().chain(evicted.iter()) {
+            let v = *vertex as usize;
+            vertex_scores[v] = forsyth_score(cache_position[v], remaining[v]);
+        }
+        for vertex in cache.iter() {
+            let v = *vertex as usize;
+            for t in adjacency[offsets[v]..offsets[v] + remaining[v] as usize].iter() {
+                let t = *t as usize;
+                let score = indices[t * 3..t * 3 + 3].iter().map(|v| vertex_scores[*v as usize]).sum();
+                triangle_scores[t] = score;
+                if score > best_score {
+                    best_score = score;
+                    best = Some(t);
+                }
+            }
+        }
+        if best.is_none() {
+            while cursor < triangle_count && emitted[cursor] {
+                cursor += 1;
+            }
+            best = (cursor < triangle_count).then_some(cursor);
+        }
+    }
+    output
+}
+
+/// # optimize_overdraw
+///
+/// Reorders clusters of triangles so the ones facing out from the middle of the mesh get
+/// drawn first, which lets the depth test throw away more of what is behind them. The
+/// clusters are cut from the cache optimized order so the cache efficiency stays within
+/// `threshold` of the input (1.05 means ACMR can get 5% worse). Every cluster is counted
+/// from a cold cache since it can end up after any other one, and if the reordered
+/// triangles still come out above the bound the input is handed back unchanged.
+pub fn optimize_overdraw(indices: &[u32], positions: &[FVec3], threshold: f32) -> Vec<u32> {
+    let triangle_count = indices.len() / 3;
+    if triangle_count == 0 {
+        return vec![];
+    }
+    let target = cache_statistics(indices, positions.len(), DEFAULT_CACHE_SIZE).acmr * threshold;
+
+    // A triangle that misses on all three vertices starts over with a cold cache anyway,
+    // so cutting there doesnt cost anything.
+    let mut clusters: Vec<usize> = vec![];
+    let mut timestamps = vec![0usize; positions.len()];
+    let mut time = DEFAULT_CACHE_SIZE + 1;
+    let (mut cluster_start, mut cluster_misses) = (0, 0);
+    for (triangle, corners) in indices.chunks_exact(3).enumerate() {
+        let cold = corners.iter().all(|vertex| time - timestamps[*vertex as usize] > DEFAULT_CACHE_SIZE);
+        let cluster_triangles = triangle - cluster_start;
+        // Otherwise cut as soon as the cluster so far is at least as good as the target.
+        let soft = cluster_triangles > 0 && cluster_misses as f32 / cluster_triangles as f32 <= target;
+        if triangle == 0 || cold || soft {
+            clusters.push(triangle);
+            cluster_start = triangle;
+            cluster_misses = 0;
+            // Pushes everything out of the cache so the new cluster starts cold.
+            time += DEFAULT_CACHE_SIZE + 1;
+        }
+        for vertex in corners.iter() {
+            let v = *vertex as usize;
+            if time - timestamps[v] > DEFAULT_CACHE_SIZE {
+                timestamps[v] = time;
+                time += 1;
+                cluster_misses += 1;
+            }
+        }
+    }
+
+    let mut mesh_centroid = FVec3::from(0.0);
+    let mut mesh_area = 0.0;
+    let mut cluster_data: Vec<(FVec3, FVec3, f32)> = vec![];
+    for (i, start) in clusters.iter().enumerate() {
+        let end = clusters.get(i + 1).copied().unwrap_or(triangle_count);
+        let (mut centroid, mut normal, mut area) = (FVec3::from(0.0), FVec3::from(0.0), 0.0);
+        for corners in indices[start * 3..end * 3].chunks_exact(3) {
+            let [a, b, c] = [corners[0], corners[1], corners[2]].map(|v| positions[v as usize]);
+            let n = triangle_normal(a, b, c);
+            let triangle_area = n.dot(&n).sqrt() * 0.5;
+            centroid += (a + b + c) * (triangle_area / 3.0);
+            normal += n;
+            area += triangle_area;
+        }
+        mesh_centroid += centroid;
+        mesh_area += area;
+        cluster_data.push((centroid, normal, area));
+    }
+    if mesh_area > 0.0 {
+        mesh_centroid = mesh_centroid * (1.0 / mesh_area);
+    }
+    let sort_keys: Vec<f32> = cluster_data.iter().map(|(centroid, normal, area)| {
+        let centroid = if *area > 0.0 { *centroid * (1.0 / *area) } else { *centroid };
+        (centroid - mesh_centroid).dot(&safe_normalize(*normal))
+    }).collect();
+    let mut order: Vec<usize> = (0..clusters.len()).collect();
+    order.sort_by(|a, b| sort_keys[*b].total_cmp(&sort_keys[*a]));
+
+    let mut output = Vec::with_capacity(triangle_count * 3);
+    for cluster in order {
+        let end = clusters.get(cluster + 1).copied().unwrap_or(triangle_count);
+        output.extend_from_slice(&indices[clusters[cluster] * 3..end * 3]);
+    }
+    // Indices past the last full triangle dont get reordered, keep them at the end.
+    output.extend_from_slice(&indices[triangle_count * 3..]);
+    if cache_statistics(&output, positions.len(), DEFAULT_CACHE_SIZE).acmr > target {
+        return indices.to_vec();
+    }
+    output
+}
+
+/// # optimize_vertex_fetch
+///
+/// Renumbers the vertices in the order the indices first use them so vertex fetches
+/// walk through memory front to back. Rewrites `indices` and hands back the remap, new
+/// vertex `i` is old vertex `remap[i]`. Vertices no index uses are dropped.
+pub fn optimize_vertex_fetch(indices: &mut [u32], vertex_count: usize) -> Vec<u32> {
+    let mut new_index = vec![u32::MAX; vertex_count];
+    let mut remap = Vec::with_capacity(vertex_count);
+    for index in indices.iter_mut() {
+        let old = *index as usize;
+        if new_index[old] == u32::MAX {
+            new_index[old] = remap.len() as u32;
+            remap.push(old as u32);
+        }
+        *index = new_index[old];
+    }
+    remap
+}
+
+/// # CompactIndices
+///
+/// Indices in the smallest type that fits, half the memory and bandwidth for any
+/// mesh with fewer than 65536 vertices. 0xFFFF is left out since it is the primitive
+/// restart value, so the biggest index has to be 0xFFFE or less to get `U16`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompactIndices {
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+impl CompactIndices {
+    /// Goes by the indices themselves, so a wrong vertex count or stale indices can never
+    /// get truncated into `u16`.
+    pub fn new(indices: &[u32]) -> Self {
+        let narrow: Option<Vec<u16>> = indices.iter().map(|index| u16::try_from(*index).ok().filter(|index| *index != u16::MAX)).collect();
+        match narrow {
+            Some(indices) => Self::U16(indices),
+            None => Self::U32(indices.to_vec()),
+        }
+    }
+    pub fn len(&self) -> usize {
+        match self {
+            Self::U16(indices) => indices.len(),
+            Self::U32(indices) => indices.len(),
+        }
+    }
+    pub fn size_in_bytes(&self) -> usize {
+        match self {
+            Self::U16(indices) => indices.len() * 2,
+            Self::U32(indices) => indices.len() * 4,
+        }
+    }
+}
+
+/// What `StandardModelData::optimize` does.
+#[derive(Debug, Clone, Copy)]
+pub struct OptimizeOptions {
+    pub vertex_cache: bool,
+    /// How much worse the ACMR is allowed to get for less overdraw, `None` skips the overdraw pass.
+    pub overdraw_threshold: Option<f32>,
+    pub vertex_fetch: bool,
+}
+impl Default for OptimizeOptions {
+    fn default() -> Self {
+        Self { vertex_cache: true, overdraw_threshold: Some(1.05), vertex_fetch: true }
+    }
+}
+/// Cache statistics before and after `StandardModelData::optimize`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct OptimizeReport {
+    pub before: CacheStatistics,
+    pub after: CacheStatistics,
+}
+
+impl StandardModelData {
+    /// # optimize
+    ///
+    /// Runs the passes in `options` over every submesh on its own, so triangles never move
+    /// to another material and the submesh ranges stay valid.
+    pub fn optimize(&mut self, options: &OptimizeOptions) -> OptimizeReport {
+        let vertex_count = self.vertices.len();
+        let before = cache_statistics(&self.indices, vertex_count, DEFAULT_CACHE_SIZE);
+        let ranges: Vec<(usize, usize)> = match self.submeshes.is_empty() {
+            true => vec![(0, self.indices.len())],
+            false => self.submeshes.iter().map(|submesh| {
+                (submesh.first_index as usize, (submesh.first_index + submesh.index_count) as usize)
+            }).collect(),
+        };
+        for (start, end) in ranges {
+            let mut range = self.indices[start..end].to_vec();
+            if options.vertex_cache {
+                range = optimize_vertex_cache(&range, vertex_count);
+            }
+            if let Some(threshold) = options.overdraw_threshold {
+                range = optimize_overdraw(&range, &self.vertices, threshold);
+            }
+            // Indices past the last full triangle dont get handed back, keep them where they were.
+            self.indices[start..start + range.len()].copy_from_slice(&range);
+        }
+        if options.vertex_fetch {
+            let remap = optimize_vertex_fetch(&mut self.indices, vertex_count);
+            self.remap_vertices(&remap);
+        }
+        let after = cache_statistics(&self.indices, self.vertices.len(), DEFAULT_CACHE_SIZE);
+        OptimizeReport { before, after }
+    }
+    /// # narrowest_indices
+    ///
+    /// The indices as `u16` when the model is small enough for them. This is opt in, `to_mesh`
+    /// and `RenderBatchBuilder` never call it. Match on what comes back and build a `u16` or
+    /// `u32` batch to go with it.
+    pub fn narrowest_indices(&self) -> CompactIndices {
+        CompactIndices::new(&self.indices)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use drowsed_math::FVec4;
+    use crate::vk_obj::rendering::mesh::SubMesh;
+
+    /// The triangles in a fixed random order, a small LCG so the tests dont need `rand`.
+    fn shuffled(indices: &[u32], seed: u64) -> Vec<u32> {
+        let mut triangles: Vec<[u32; 3]> = indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect();
+        let mut state = seed;
+        for i in (1..triangles.len()).rev() {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            triangles.swap(i, (state >> 33) as usize % (i + 1));
+        }
+        triangles.concat()
+    }
+    fn sorted_triangles(indices: &[u32]) -> Vec<[u32; 3]> {
+        let mut triangles: Vec<[u32; 3]> = indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect();
+        triangles.sort();
+        triangles
+    }
+    fn grid(size: u32) -> (Vec<u32>, Vec<FVec3>) {
+        let row = size + 1;
+        let positions = (0..row * row).map(|i| FVec3::new((i % row) as f32, (i / row) as f32, 0.0)).collect();
+        let mut indices = vec![];
+        for y in 0..size {
+            for x in 0..size {
+                let a = y * row + x;
+                indices.extend_from_slice(&[a, a + 1, a + row + 1, a, a + row + 1, a + row]);
+            }
+        }
+        (indices, positions)
+    }
+    fn monke() -> StandardModelData {
+        let models = StandardModelData::try_new(&format!("{}/monke.fbx", env!("CARGO_MANIFEST_DIR"))).unwrap();
+        models.into_iter().find(|model| !model.vertices.is_empty()).unwrap()
+    }
+    /// monke.fbx is flat shaded, so hardly any vertex is shared between two triangles and its
+    /// ACMR is close to 3 in any order. Indexing the control points instead gives the welded
+    /// mesh underneath, which is what the cache actually has something to do on.
+    fn welded(model: &StandardModelData) -> (Vec<u32>, Vec<FVec3>) {
+        let count = model.control_points.iter().max().map_or(0, |max| *max as usize + 1);
+        let mut positions = vec![FVec3::from(0.0); count];
+        for (vertex, control_point) in model.control_points.iter().enumerate() {
+            positions[*control_point as usize] = model.vertices[vertex];
+        }
+        let indices = model.indices.iter().map(|index| model.control_points[*index as usize]).collect();
+        (indices, positions)
+    }
+
+    #[test]
+    fn vertex_cache_improves_a_shuffled_grid() {
+        let (indices, positions) = grid(32);
+        let indices = shuffled(&indices, 1);
+        let optimized = optimize_vertex_cache(&indices, positions.len());
+        let (before, after) = (acmr(&indices, positions.len()), acmr(&optimized, positions.len()));
+        // Shuffled it misses on nearly every vertex, a good order gets a grid to about 0.7.
+        assert!(before > 2.5, "{}", before);
+        assert!(after < before && after < 0.85, "{} -> {}", before, after);
+        assert_eq!(sorted_triangles(&optimized), sorted_triangles(&indices));
+    }
+
+    #[test]
+    fn vertex_cache_improves_monke() {
+        let (indices, positions) = welded(&monke());
+        // Once in the order the file has them (about 1.8) and once shuffled (about 2.9).
+        for indices in [indices.clone(), shuffled(&indices, 1)] {
+            let optimized = optimize_vertex_cache(&indices, positions.len());
+            let (before, after) = (acmr(&indices, positions.len()), acmr(&optimized, positions.len()));
+            assert!(after < before && after < 0.85, "{} -> {}", before, after);
+            assert_eq!(sorted_triangles(&optimized), sorted_triangles(&indices));
+        }
+    }
+
+    #[test]
+    fn overdraw_stays_within_threshold() {
+        let (indices, positions) = welded(&monke());
+        let indices = optimize_vertex_cache(&indices, positions.len());
+        let before = acmr(&indices, positions.len());
+        for threshold in [1.0, 1.05, 1.5] {
+            let reordered = optimize_overdraw(&indices, &positions, threshold);
+            let after = acmr(&reordered, positions.len());
+            assert!(after <= before * threshold, "{} -> {} with {}", before, after, threshold);
+            assert_eq!(sorted_triangles(&reordered), sorted_triangles(&indices));
+        }
+    }
+
+    #[test]
+    fn optimize_keeps_triangles_and_submeshes() {
+        let mut model = monke();
+        let half = model.indices.len() / 6 * 3;
+        model.submeshes = vec![
+            SubMesh { material: 0, first_index: 0, index_count: half as u32 },
+            SubMesh { material: 1, first_index: half as u32, index_count: (model.indices.len() - half) as u32 },
+        ];
+        // Every vertex gets its old index as a color so it can be followed through the remap.
+        model.colors = (0..model.vertices.len()).map(|i| FVec4::new(i as f32, 0.0, 0.0, 0.0)).collect();
+        let (original, submeshes) = (model.indices.clone(), model.submeshes.clone());
+        model.optimize(&OptimizeOptions::default());
+
+        assert_eq!(model.submeshes, submeshes);
+        let old: Vec<u32> = model.indices.iter().map(|index| model.colors[*index as usize].x as u32).collect();
+        for submesh in submeshes.iter() {
+            let range = submesh.first_index as usize..(submesh.first_index + submesh.index_count) as usize;
+            assert_eq!(sorted_triangles(&old[range.clone()]), sorted_triangles(&original[range]));
+        }
+    }
+
+    #[test]
+    fn compact_indices_leave_out_primitive_restart() {
+        assert_eq!(CompactIndices::new(&[0, 1, 65534]), CompactIndices::U16(vec![0, 1, 65534]));
+        assert_eq!(CompactIndices::new(&[0, 1, 65535]), CompactIndices::U32(vec![0, 1, 65535]));
+        assert_eq!(CompactIndices::new(&[70000, 1, 2]), CompactIndices::U32(vec![70000, 1, 2]));
+        assert_eq!(CompactIndices::new(&[]), CompactIndices::U16(vec![]));
+    }
+}
@@ -0,0 +1,427 @@
+#![allow(unused)]
+//! Procedural meshes for when there is no model file, debug shapes, placeholders, colliders
+//! and so on. Everything comes out in the engine axis system (y up, front is -z), counter
+//! clockwise seen from the outside like loaded models, with v = 0 at the top of a texture.
+use std::collections::HashMap;
+use std::f32::consts::{PI, TAU};
+
+use drowsed_math::{FVec2, FVec3};
+
+use super::model_loader::safe_normalize;
+use super::vertex::Vertex3DNormalUV;
+use crate::vk_obj::rendering::mesh::Mesh;
+
+/// # PrimitiveMesh
+///
+/// Vertices and triangles of a generated shape, centered on the origin. Implements `Mesh`
+/// for both `u32` and `u16` indices so it can go straight into a `RenderBatchBuilder`.
+#[derive(Debug, Clone, Default)]
+pub struct PrimitiveMesh {
+    pub vertices: Vec<Vertex3DNormalUV>,
+    pub indices: Vec<u32>,
+}
+impl PrimitiveMesh {
+    /// Flat in the xz plane facing +y. `segments` is the amount of quads along each side.
+    pub fn plane(width: f32, depth: f32, segments: u32) -> Self {
+        let mut mesh = Self::default();
+        mesh.grid(
+            FVec3::new(-width * 0.5, 0.0, -depth * 0.5),
+            FVec3::new(width, 0.0, 0.0),
+            FVec3::new(0.0, 0.0, depth),
+            FVec3::new(0.0, 1.0, 0.0),
+            segments.max(1),
+        );
+        mesh
+    }
+    pub fn cube(size: f32, segments: u32) -> Self {
+        Self::cuboid(FVec3::new(size, size, size), segments)
+    }
+    /// A box with hard edges, every face gets the whole texture.
+    pub fn cuboid(size: FVec3, segments: u32) -> Self {
+        let mut mesh = Self::default();
+        let half = size * 0.5;
+        // normal, right and down of each face seen from the outside
+        let faces = [
+            (FVec3::new(1.0, 0.0, 0.0), FVec3::new(0.0, 0.0, -1.0), FVec3::new(0.0, -1.0, 0.0)),
+            (FVec3::new(-1.0, 0.0, 0.0), FVec3::new(0.0, 0.0, 1.0), FVec3::new(0.0, -1.0, 0.0)),
+            (FVec3::new(0.0, 1.0, 0.0), FVec3::new(1.0, 0.0, 0.0), FVec3::new(0.0, 0.0, 1.0)),
+            (FVec3::new(0.0, -1.0, 0.0), FVec3::new(1.0, 0.0, 0.0), FVec3::new(0.0, 0.0, -1.0)),
+            (FVec3::new(0.0, 0.0, 1.0), FVec3::new(1.0, 0.0, 0.0), FVec3::new(0.0, -1.0, 0.0)),
+            (FVec3::new(0.0, 0.0, -1.0), FVec3::new(-1.0, 0.0, 0.0), FVec3::new(0.0, -1.0, 0.0)),
+        ];
+        for (normal, right, down) in faces {
+            let scale = |v: FVec3| FVec3::new(v.x * half.x, v.y * half.y, v.z * half.z);
+            let (normal_offset, right, down) = (scale(normal), scale(right), scale(down));
+            mesh.grid(normal_offset - right - down, right * 2.0, down * 2.0, normal, segments.max(1));
+        }
+        mesh
+    }
+    /// Latitude and longitude sphere. `segments` go around the y axis, `rings` from pole to pole.
+    pub fn uv_sphere(radius: f32, segments: u32, rings: u32) -> Self {
+        let rings = rings.max(2);
+        let profile: Vec<ProfilePoint> = (0..=rings)
+            .map(|ring| {
+                let theta = ring as f32 / rings as f32 * PI;
+                ProfilePoint::new(theta.sin(), theta.cos(), radius)
+            })
+            .collect();
+        let mut mesh = Self::default();
+        mesh.lathe(&profile, segments);
+        mesh
+    }
+    /// Subdivided icosahedron, the triangles are a lot more even than on a uv sphere.
+    /// Every subdivision has 4 times the triangles, 0 is the plain icosahedron.
+    pub fn icosphere(radius: f32, subdivisions: u32) -> Self {
+        let t = (1.0 + 5.0f32.sqrt()) * 0.5;
+        let mut positions: Vec<FVec3> = [
+            (-1.0, t, 0.0), (1.0, t, 0.0), (-1.0, -t, 0.0), (1.0, -t, 0.0),
+            (0.0, -1.0, t), (0.0, 1.0, t), (0.0, -1.0, -t), (0.0, 1.0, -t),
+            (t, 0.0, -1.0), (t, 0.0, 1.0), (-t, 0.0, -1.0), (-t, 0.0, 1.0),
+        ]
+        .iter()
+        .map(|(x, y, z)| safe_normalize(FVec3::new(*x, *y, *z)))
+        .collect();
+        let mut triangles: Vec<[u32; 3]> = vec![
+            [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+            [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+            [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+            [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+        ];
+        for _ in 0..subdivisions {
+            // edges are shared by two triangles, the cache makes both use the same midpoint
+            let mut midpoints: HashMap<(u32, u32), u32> = HashMap::new();
+            let mut midpoint = |a: u32, b: u32, positions: &mut Vec<FVec3>| -> u32 {
+                *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                    let position = safe_normalize(positions[a as usize] + positions[b as usize]);
+                    positions.push(position);
+                    (positions.len() - 1) as u32
+                })
+            };
+            let mut split = Vec::with_capacity(triangles.len() * 4);
+            for [a, b, c] in triangles {
+                let ab = midpoint(a, b, &mut positions);
+                let bc = midpoint(b, c, &mut positions);
+                let ca = midpoint(c, a, &mut positions);
+                split.extend([[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]);
+            }
+            triangles = split;
+        }
+
+        // Same mapping as uv_sphere. Triangles that cross the seam at u = 0 get their left side
+        // moved past 1 and the poles take the u of the triangle they are in, so those corners
+        // need their own vertices.
+        let mut mesh = Self::default();
+        let mut welded: HashMap<(u32, u32), u32> = HashMap::new();
+        for triangle in triangles {
+            let mut uvs = triangle.map(|i| {
+                let p = positions[i as usize];
+                let u = (-p.z).atan2(p.x) / TAU;
+                FVec2::new(if u < 0.0 { u + 1.0 } else { u }, p.y.clamp(-1.0, 1.0).acos() / PI)
+            });
+            let is_pole = triangle.map(|i| positions[i as usize].x.abs() < 1e-6 && positions[i as usize].z.abs() < 1e-6);
+            let others: Vec<f32> = (0..3).filter(|c| !is_pole[*c]).map(|c| uvs[c].x).collect();
+            let (min, max) = others.iter().fold((f32::MAX, f32::MIN), |(min, max), u| (min.min(*u), max.max(*u)));
+            if max - min > 0.5 {
+                for uv in uvs.iter_mut() {
+                    if uv.x < 0.5 {
+                        uv.x += 1.0;
+                    }
+                }
+            }
+            for c in 0..3 {
+                if is_pole[c] {
+                    let sum: f32 = (0..3).filter(|o| !is_pole[*o]).map(|o| uvs[o].x).sum();
+                    uvs[c].x = sum / (3 - is_pole.iter().filter(|p| **p).count()) as f32;
+                }
+            }
+            for c in 0..3 {
+                let position = positions[triangle[c] as usize];
+                let vertices = &mut mesh.vertices;
+                let index = *welded.entry((triangle[c], uvs[c].x.to_bits())).or_insert_with(|| {
+                    vertices.push(Vertex3DNormalUV { pos: position * radius, normal: position, uv: uvs[c] });
+                    (vertices.len() - 1) as u32
+                });
+                mesh.indices.push(index);
+            }
+        }
+        mesh
+    }
+    /// Capped cylinder along the y axis. `rings` are the rows of quads on the side.
+    pub fn cylinder(radius: f32, height: f32, segments: u32, rings: u32) -> Self {
+        Self::frustum(radius, radius, height, segments, rings)
+    }
+    /// Tip at the top, base at the bottom, centered halfway up.
+    pub fn cone(radius: f32, height: f32, segments: u32, rings: u32) -> Self {
+        Self::frustum(0.0, radius, height, segments, rings)
+    }
+    /// Cylinder with different radii at the top and bottom. Ends with a radius of 0 dont get a cap.
+    pub fn frustum(top_radius: f32, bottom_radius: f32, height: f32, segments: u32, rings: u32) -> Self {
+        let rings = rings.max(1);
+        let half = height * 0.5;
+        // the side leans by the difference in radius, the normal leans up or down by the same amount
+        let normal = safe_normalize(FVec3::new(height, bottom_radius - top_radius, 0.0));
+        let profile: Vec<ProfilePoint> = (0..=rings)
+            .map(|ring| {
+                let f = ring as f32 / rings as f32;
+                ProfilePoint {
+                    radius: top_radius + (bottom_radius - top_radius) * f,
+                    y: half - height * f,
+                    normal: FVec2::new(normal.x, normal.y),
+                }
+            })
+            .collect();
+        let mut mesh = Self::default();
+        mesh.lathe(&profile, segments);
+        if top_radius > 0.0 {
+            mesh.cap(half, top_radius, segments, true);
+        }
+        if bottom_radius > 0.0 {
+            mesh.cap(-half, bottom_radius, segments, false);
+        }
+        mesh
+    }
+    /// Cylinder with half spheres on the ends. `height` is from the very top to the very bottom
+    /// like a character collider, so anything under `2 * radius` is just a sphere.
+    /// `rings` is per half sphere.
+    pub fn capsule(radius: f32, height: f32, segments: u32, rings: u32) -> Self {
+        let rings = rings.max(1);
+        let half = (height * 0.5 - radius).max(0.0);
+        let mut profile = Vec::with_capacity(rings as usize * 2 + 2);
+        for ring in 0..=rings {
+            let theta = ring as f32 / rings as f32 * PI * 0.5;
+            let mut point = ProfilePoint::new(theta.sin(), theta.cos(), radius);
+            point.y += half;
+            profile.push(point);
+        }
+        for ring in 0..=rings {
+            let theta = (ring as f32 / rings as f32 + 1.0) * PI * 0.5;
+            let mut point = ProfilePoint::new(theta.sin(), theta.cos(), radius);
+            point.y -= half;
+            profile.push(point);
+        }
+        let mut mesh = Self::default();
+        mesh.lathe(&profile, segments);
+        mesh
+    }
+    /// Ring around the y axis. `major_radius` is to the middle of the tube, `minor_radius` is
+    /// the thickness of the tube.
+    pub fn torus(major_radius: f32, minor_radius: f32, major_segments: u32, minor_segments: u32) -> Self {
+        let minor_segments = minor_segments.max(3);
+        // starts on top of the tube and goes around the outside first so v runs downwards like on the other shapes
+        let profile: Vec<ProfilePoint> = (0..=minor_segments)
+            .map(|segment| {
+                let angle = PI * 0.5 - segment as f32 / minor_segments as f32 * TAU;
+                let normal = FVec2::new(angle.cos(), angle.sin());
+                ProfilePoint { radius: major_radius + normal.x * minor_radius, y: normal.y * minor_radius, normal }
+            })
+            .collect();
+        let mut mesh = Self::default();
+        mesh.lathe(&profile, major_segments);
+        mesh
+    }
+
+    fn vertex(&mut self, pos: FVec3, normal: FVec3, uv: FVec2) -> u32 {
+        self.vertices.push(Vertex3DNormalUV { pos, normal, uv });
+        (self.vertices.len() - 1) as u32
+    }
+    /// Two triangles for the quad `top_left top_right bottom_right bottom_left`, as seen from the front.
+    /// Either triangle can be left out where a side has collapsed into a point.
+    fn quad(&mut self, top_left: u32, top_right: u32, bottom_right: u32, bottom_left: u32, upper: bool, lower: bool) {
+        if lower {
+            self.indices.extend([top_left, bottom_left, bottom_right]);
+        }
+        if upper {
+            self.indices.extend([top_left, bottom_right, top_right]);
+        }
+    }
+    /// Flat patch from `origin` that spans `right` and `down`, seen from the side `normal` points to.
+    fn grid(&mut self, origin: FVec3, right: FVec3, down: FVec3, normal: FVec3, segments: u32) {
+        let start = self.vertices.len() as u32;
+        let row = segments + 1;
+        for y in 0..=segments {
+            for x in 0..=segments {
+                let uv = FVec2::new(x as f32 / segments as f32, y as f32 / segments as f32);
+                self.vertex(origin + right * uv.x + down * uv.y, normal, uv);
+            }
+        }
+        for y in 0..segments {
+            for x in 0..segments {
+                let top_left = start + y * row + x;
+                self.quad(top_left, top_left + 1, top_left + row + 1, top_left + row, true, true);
+            }
+        }
+    }
+    /// Spins `profile` around the y axis. The profile goes from the top to the bottom with
+    /// the normals pointing away from the axis, u goes around and v follows the length of the profile.
+    /// The first and last column are at the same place so the texture can wrap.
+    fn lathe(&mut self, profile: &[ProfilePoint], segments: u32) {
+        let segments = segments.max(3);
+        let start = self.vertices.len() as u32;
+        let row = segments + 1;
+        let mut lengths = vec![0.0f32; profile.len()];
+        for i in 1..profile.len() {
+            let (dr, dy) = (profile[i].radius - profile[i - 1].radius, profile[i].y - profile[i - 1].y);
+            lengths[i] = lengths[i - 1] + (dr * dr + dy * dy).sqrt();
+        }
+        let total = lengths.last().copied().unwrap_or(0.0).max(f32::EPSILON);
+        for (point, length) in profile.iter().zip(lengths.iter()) {
+            for column in 0..=segments {
+                let u = column as f32 / segments as f32;
+                // going around counter clockwise seen from above, x towards -z
+                let (sin, cos) = (u * TAU).sin_cos();
+                let pos = FVec3::new(point.radius * cos, point.y, -point.radius * sin);
+                let normal = FVec3::new(point.normal.x * cos, point.normal.y, -point.normal.x * sin);
+                self.vertex(pos, normal, FVec2::new(u, length / total));
+            }
+        }
+        for ring in 0..profile.len().saturating_sub(1) {
+            // rows on the axis are poles, the triangle with two corners in the pole would have no area
+            let (upper, lower) = (profile[ring].radius > 0.0, profile[ring + 1].radius > 0.0);
+            for column in 0..segments {
+                let top_left = start + ring as u32 * row + column;
+                self.quad(top_left, top_left + 1, top_left + row + 1, top_left + row, upper, lower);
+            }
+        }
+    }
+    /// Flat disc at `y` facing up or down, the texture is laid over it seen from that side.
+    fn cap(&mut self, y: f32, radius: f32, segments: u32, top: bool) {
+        let segments = segments.max(3);
+        let (normal, flip) = match top {
+            true => (FVec3::new(0.0, 1.0, 0.0), 1.0),
+            false => (FVec3::new(0.0, -1.0, 0.0), -1.0),
+        };
+        let center = self.vertex(FVec3::new(0.0, y, 0.0), normal, FVec2::new(0.5, 0.5));
+        for column in 0..=segments {
+            let (sin, cos) = (column as f32 / segments as f32 * TAU).sin_cos();
+            let uv = FVec2::new(0.5 + cos * 0.5, 0.5 - sin * 0.5 * flip);
+            self.vertex(FVec3::new(radius * cos, y, -radius * sin), normal, uv);
+        }
+        for column in 0..segments {
+            let (a, b) = (center + 1 + column, center + 2 + column);
+            match top {
+                true => self.indices.extend([center, a, b]),
+                false => self.indices.extend([center, b, a]),
+            }
+        }
+    }
+}
+impl Mesh<Vertex3DNormalUV, u32> for PrimitiveMesh {
+    fn vertices(&self) -> Vec<Vertex3DNormalUV> {
+        self.vertices.clone()
+    }
+    fn indices(&self) -> Vec<u32> {
+        self.indices.clone()
+    }
+}
+/// Only for meshes with at most 65536 vertices, which is everything but very finely subdivided
+/// shapes. Panics on anything bigger instead of wrapping the indices around.
+impl Mesh<Vertex3DNormalUV, u16> for PrimitiveMesh {
+    fn vertices(&self) -> Vec<Vertex3DNormalUV> {
+        self.vertices.clone()
+    }
+    fn indices(&self) -> Vec<u16> {
+        self.indices.iter().map(|i| {
+            u16::try_from(*i).unwrap_or_else(|_| panic!("{} vertices dont fit in u16 indices", self.vertices.len()))
+        }).collect()
+    }
+}
+
+/// One point of the outline that `lathe` spins around, `normal` is (away from the axis, up).
+#[derive(Debug, Clone, Copy)]
+struct ProfilePoint {
+    radius: f32,
+    y: f32,
+    normal: FVec2,
+}
+impl ProfilePoint {
+    /// Point on a circle of `radius` around the origin from its sine and cosine.
+    fn new(sin: f32, cos: f32, radius: f32) -> Self {
+        // sin(pi) isnt quite 0, the poles have to end up exactly on the axis
+        let sin = if sin.abs() < 1e-6 { 0.0 } else { sin };
+        Self { radius: sin * radius, y: cos * radius, normal: FVec2::new(sin, cos) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use drowsed_math::{Vector, EuclideanGeometry};
+
+    use super::*;
+    use crate::model::geometry::triangle_normal;
+
+    /// Indices in range, unit normals, and every triangle facing away from `center` of the
+    /// surface it is on, both by its winding and by its vertex normals.
+    fn assert_outward(name: &str, mesh: &PrimitiveMesh, center: impl Fn(FVec3) -> FVec3) {
+        assert_eq!(mesh.indices.len() % 3, 0, "{}", name);
+        assert!(mesh.indices.iter().all(|index| (*index as usize) < mesh.vertices.len()), "{} has indices out of range", name);
+        for vertex in mesh.vertices.iter() {
+            assert!((vertex.normal.dot(&vertex.normal).sqrt() - 1.0).abs() < 1e-5, "{} normal {:?}", name, vertex.normal);
+        }
+        for triangle in mesh.indices.chunks_exact(3) {
+            let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|i| mesh.vertices[i as usize]);
+            let normal = triangle_normal(a.pos, b.pos, c.pos);
+            let centroid = (a.pos + b.pos + c.pos) * (1.0 / 3.0);
+            assert!(normal.dot(&(centroid - center(centroid))) > 0.0, "{} triangle {:?} faces inwards", name, triangle);
+            for vertex in [a, b, c] {
+                assert!(normal.dot(&vertex.normal) > 0.0, "{} triangle {:?} disagrees with its normals", name, triangle);
+            }
+        }
+    }
+    fn origin(_: FVec3) -> FVec3 {
+        FVec3::from(0.0)
+    }
+
+    #[test]
+    fn counts() {
+        let counts = |mesh: PrimitiveMesh| (mesh.vertices.len(), mesh.indices.len());
+        assert_eq!(counts(PrimitiveMesh::plane(2.0, 2.0, 3)), (16, 54));
+        assert_eq!(counts(PrimitiveMesh::cube(2.0, 2)), (54, 144));
+        // the triangles touching the poles lose the half that would have no area
+        assert_eq!(counts(PrimitiveMesh::uv_sphere(1.0, 8, 4)), (45, 144));
+        assert_eq!(counts(PrimitiveMesh::cylinder(1.0, 2.0, 8, 1)), (38, 96));
+        assert_eq!(counts(PrimitiveMesh::cone(1.0, 2.0, 8, 1)), (28, 48));
+        assert_eq!(counts(PrimitiveMesh::capsule(1.0, 4.0, 8, 2)), (54, 192));
+        assert_eq!(counts(PrimitiveMesh::torus(2.0, 0.5, 8, 4)), (45, 192));
+        for subdivisions in 0..3 {
+            let (vertices, indices) = counts(PrimitiveMesh::icosphere(1.0, subdivisions));
+            assert_eq!(indices, 20 * 4usize.pow(subdivisions) * 3);
+            // one per position plus the copies along the uv seam
+            assert!(vertices >= 10 * 4usize.pow(subdivisions) + 2);
+        }
+    }
+
+    #[test]
+    fn shapes_face_outwards() {
+        assert_outward("plane", &PrimitiveMesh::plane(2.0, 2.0, 3), |c| FVec3::new(c.x, -1.0, c.z));
+        assert_outward("cube", &PrimitiveMesh::cube(2.0, 2), origin);
+        assert_outward("cuboid", &PrimitiveMesh::cuboid(FVec3::new(1.0, 2.0, 3.0), 1), origin);
+        assert_outward("uv sphere", &PrimitiveMesh::uv_sphere(1.0, 8, 4), origin);
+        for subdivisions in 0..3 {
+            assert_outward("icosphere", &PrimitiveMesh::icosphere(1.0, subdivisions), origin);
+        }
+        assert_outward("cylinder", &PrimitiveMesh::cylinder(1.0, 2.0, 8, 1), origin);
+        assert_outward("cone", &PrimitiveMesh::cone(1.0, 2.0, 8, 1), origin);
+        assert_outward("frustum", &PrimitiveMesh::frustum(0.5, 1.0, 2.0, 8, 2), origin);
+        assert_outward("capsule", &PrimitiveMesh::capsule(1.0, 4.0, 8, 2), origin);
+        // the inside of the ring faces the origin, so it is measured from the middle of the tube
+        assert_outward("torus", &PrimitiveMesh::torus(2.0, 0.5, 8, 4), |c| {
+            let around = (c.x * c.x + c.z * c.z).sqrt();
+            FVec3::new(c.x / around * 2.0, 0.0, c.z / around * 2.0)
+        });
+    }
+
+    #[test]
+    fn u16_indices_match() {
+        let sphere = PrimitiveMesh::uv_sphere(1.0, 16, 8);
+        let wide: Vec<u32> = Mesh::<Vertex3DNormalUV, u32>::indices(&sphere);
+        let narrow: Vec<u16> = Mesh::<Vertex3DNormalUV, u16>::indices(&sphere);
+        assert_eq!(narrow.iter().map(|index| *index as u32).collect::<Vec<_>>(), wide);
+    }
+
+    #[test]
+    #[should_panic(expected = "dont fit in u16 indices")]
+    fn u16_indices_panic_when_too_big() {
+        let mesh = PrimitiveMesh { vertices: vec![], indices: vec![0, 1, 70000] };
+        Mesh::<Vertex3DNormalUV, u16>::indices(&mesh);
+    }
+}
@@ -0,0 +1,255 @@
+#![allow(unused)]
+use std::collections::HashMap;
+
+use drowsed_math::{FMat4, FVec3, SquareMatrix, TransformQuaternion3D};
+
+use super::animation::AnimationStack;
+use super::clip::AnimationClip;
+use super::gltf_loader;
+use super::error::ModelLoadError;
+use super::model_loader::{StandardModelData, LoadOptions};
+use super::transform::{self, translation_matrix, scaling_matrix, rotation_x, rotation_y, rotation_z, affine_inverse};
+
+/// # ModelTransform
+///
+/// All the transform properties of an FBX `Model` node. Rotations are euler angles
+/// in degrees like the file stores them. FBX puts these together as
+///
+/// `T * Roff * Rp * Rpre * R * Rpost^-1 * Rp^-1 * Soff * Sp * S * Sp^-1`
+///
+/// and the geometric part only moves the geometry of the model, not its children.
+#[derive(Debug, Clone, Copy)]
+pub struct ModelTransform {
+    pub translation: FVec3,
+    pub rotation: FVec3,
+    pub scaling: FVec3,
+    pub pre_rotation: FVec3,
+    pub post_rotation: FVec3,
+    pub rotation_offset: FVec3,
+    pub rotation_pivot: FVec3,
+    pub scaling_offset: FVec3,
+    pub scaling_pivot: FVec3,
+    pub geometric_translation: FVec3,
+    pub geometric_rotation: FVec3,
+    pub geometric_scaling: FVec3,
+    /// Only applies to `rotation`, pre and post rotation are always xyz.
+    pub rotation_order: RotationOrder,
+}
+impl Default for ModelTransform {
+    fn default() -> Self {
+        Self {
+            translation: FVec3::from(0.0),
+            rotation: FVec3::from(0.0),
+            scaling: FVec3::from(1.0),
+            pre_rotation: FVec3::from(0.0),
+            post_rotation: FVec3::from(0.0),
+            rotation_offset: FVec3::from(0.0),
+            rotation_pivot: FVec3::from(0.0),
+            scaling_offset: FVec3::from(0.0),
+            scaling_pivot: FVec3::from(0.0),
+            geometric_translation: FVec3::from(0.0),
+            geometric_rotation: FVec3::from(0.0),
+            geometric_scaling: FVec3::from(1.0),
+            rotation_order: RotationOrder::XYZ,
+        }
+    }
+}
+impl ModelTransform {
+    /// Transform of the model relative to its parent.
+    pub fn local_matrix(&self) -> FMat4 {
+        let rotation_pivot = translation_matrix(self.rotation_pivot);
+        let scaling_pivot = translation_matrix(self.scaling_pivot);
+        translation_matrix(self.translation)
+            * translation_matrix(self.rotation_offset)
+            * rotation_pivot
+            * euler_matrix(self.pre_rotation)
+            * self.rotation_order.matrix(self.rotation)
+            * affine_inverse(&euler_matrix(self.post_rotation))
+            * affine_inverse(&rotation_pivot)
+            * translation_matrix(self.scaling_offset)
+            * scaling_pivot
+            * scaling_matrix(self.scaling)
+            * affine_inverse(&scaling_pivot)
+    }
+    /// Extra transform that only gets applied to the geometry attached to the model.
+    pub fn geometric_matrix(&self) -> FMat4 {
+        translation_matrix(self.geometric_translation)
+            * euler_matrix(self.geometric_rotation)
+            * scaling_matrix(self.geometric_scaling)
+    }
+    pub fn has_geometric_transform(&self) -> bool {
+        self.geometric_translation != FVec3::from(0.0)
+            || self.geometric_rotation != FVec3::from(0.0)
+            || self.geometric_scaling != FVec3::from(1.0)
+    }
+}
+/// FBX default euler rotation, x first then y then z. Takes degrees.
+pub fn euler_matrix(degrees: FVec3) -> FMat4 {
+    RotationOrder::XYZ.matrix(degrees)
+}
+
+/// # RotationOrder
+///
+/// The `RotationOrder` property of a model. `XYZ` means x gets applied first.
+/// The spheric order FBX has is treated as `XYZ` like every other importer does.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotationOrder {
+    #[default]
+    XYZ,
+    XZY,
+    YZX,
+    YXZ,
+    ZXY,
+    ZYX,
+}
+impl RotationOrder {
+    pub fn from_fbx(value: i64) -> Self {
+        match value {
+            1 => Self::XZY,
+            2 => Self::YZX,
+            3 => Self::YXZ,
+            4 => Self::ZXY,
+            5 => Self::ZYX,
+            _ => Self::XYZ,
+        }
+    }
+    /// Rotation matrix of euler angles given in degrees.
+    pub fn matrix(self, degrees: FVec3) -> FMat4 {
+        let x = rotation_x(degrees.x.to_radians());
+        let y = rotation_y(degrees.y.to_radians());
+        let z = rotation_z(degrees.z.to_radians());
+        // The first axis to apply goes on the right.
+        match self {
+            Self::XYZ => z * y * x,
+            Self::XZY => y * z * x,
+            Self::YZX => x * z * y,
+            Self::YXZ => z * x * y,
+            Self::ZXY => y * x * z,
+            Self::ZYX => x * y * z,
+        }
+    }
+}
+
+/// # SceneNode
+///
+/// One model in the hierarchy. `parent` and `children` are indices into `SceneGraph::nodes`.
+#[derive(Debug, Clone)]
+pub struct SceneNode {
+    pub tag: String,
+    pub id: i64,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    pub transform: ModelTransform,
+    pub world: FMat4,
+}
+
+/// # SceneGraph
+///
+/// Parent/child relations between the models of a file, built from the OO
+/// connections that link one model to another.
+#[derive(Debug, Clone)]
+pub struct SceneGraph {
+    pub nodes: Vec<SceneNode>,
+    pub roots: Vec<usize>,
+    /// Takes the file's axis system and units into the ones the models got loaded in.
+    /// Everything the graph hands out already has this applied.
+    pub conversion: FMat4,
+}
+impl SceneGraph {
+    /// `models` is every model in the order it should appear in `nodes`. `links` are
+    /// the (child, parent) pairs from `Connections`, links that dont go from a model to
+    /// another model are ignored. `conversion` is usually `AxisSystem::conversion`,
+    /// pass the identity to keep the coordinates of the file.
+    pub fn build(models: Vec<(i64, String, ModelTransform)>, links: &[(i64, i64)], conversion: FMat4) -> Self {
+        let lookup: HashMap<i64, usize> = models.iter().enumerate().map(|(i, model)| (model.0, i)).collect();
+        let mut nodes: Vec<SceneNode> = models.into_iter().map(|(id, tag, transform)| SceneNode {
+            tag,
+            id,
+            parent: None,
+            children: vec![],
+            transform,
+            world: FMat4::identity(),
+        }).collect();
+
+        for (child, parent) in links.iter() {
+            let (Some(&child), Some(&parent)) = (lookup.get(child), lookup.get(parent)) else {
+                continue;
+            };
+            // A broken file could link a model under one of its own children,
+            // we keep the first link and drop the one that would close the loop.
+            if nodes[child].parent.is_some() || Self::is_ancestor(&nodes, child, parent) {
+                continue;
+            }
+            nodes[child].parent = Some(parent);
+            nodes[parent].children.push(child);
+        }
+        let roots = (0..nodes.len()).filter(|i| nodes[*i].parent.is_none()).collect();
+        let mut graph = Self { nodes, roots, conversion };
+        graph.update_world();
+        graph
+    }
+    fn is_ancestor(nodes: &[SceneNode], ancestor: usize, mut node: usize) -> bool {
+        loop {
+            if node == ancestor {
+                return true;
+            }
+            match nodes[node].parent {
+                Some(parent) => node = parent,
+                None => return false,
+            }
+        }
+    }
+    /// Recomputes `world` of every node from the local transforms. Call this after
+    /// changing a `transform`.
+    pub fn update_world(&mut self) {
+        let inverse = affine_inverse(&self.conversion);
+        let mut stack: Vec<(usize, FMat4)> = self.roots.iter().map(|root| (*root, FMat4::identity())).collect();
+        while let Some((node, parent_world)) = stack.pop() {
+            let world = parent_world * self.nodes[node].transform.local_matrix();
+            self.nodes[node].world = self.conversion * world * inverse;
+            for child in self.nodes[node].children.iter() {
+                stack.push((*child, world));
+            }
+        }
+    }
+    /// Transform of a node relative to its parent.
+    pub fn local_matrix(&self, node: usize) -> FMat4 {
+        self.conversion * self.nodes[node].transform.local_matrix() * affine_inverse(&self.conversion)
+    }
+    pub fn world_matrix(&self, node: usize) -> FMat4 {
+        self.nodes[node].world
+    }
+    /// World transform of a node split back into translation, rotation and scale.
+    pub fn world_transform(&self, node: usize) -> TransformQuaternion3D {
+        transform::decompose(&self.nodes[node].world)
+    }
+    /// Where the geometry of a node ends up, world transform plus the geometric transform.
+    pub fn geometry_matrix(&self, node: usize) -> FMat4 {
+        self.nodes[node].world * self.conversion * self.nodes[node].transform.geometric_matrix() * affine_inverse(&self.conversion)
+    }
+}
+
+/// # Scene
+///
+/// Every model of a file together with the hierarchy they are in and the animations
+/// that move them. `models[i]` belongs to `graph.nodes[i]`.
+pub struct Scene {
+    pub models: Vec<StandardModelData>,
+    pub graph: SceneGraph,
+    /// FBX animation stacks as they are in the file, `AnimationClip::from_stack` bakes them into clips.
+    pub animations: Vec<AnimationStack>,
+    /// Clips that are ready to sample. glTF animations end up here directly.
+    pub clips: Vec<AnimationClip>,
+}
+impl Scene {
+    pub fn try_new(filepath: &str) -> Result<Self, ModelLoadError> {
+        Self::try_new_with(filepath, &LoadOptions::default())
+    }
+    /// Loads an FBX file, or a glTF one when the extension is .gltf or .glb.
+    pub fn try_new_with(filepath: &str, options: &LoadOptions) -> Result<Self, ModelLoadError> {
+        if gltf_loader::is_gltf(filepath) {
+            return gltf_loader::try_parse_scene(filepath, options);
+        }
+        StandardModelData::try_parse_scene(StandardModelData::load_document(filepath)?, &options.for_file(filepath))
+    }
+}
@@ -0,0 +1,531 @@
+#![allow(unused)]
+//! Mesh simplification with quadric error metrics, and LOD chains built from it.
+//!
+//! Collapses only ever move a vertex onto one of its neighbours, vertices themselves are
+//! never changed or added. So every LOD is just another index buffer over the same
+//! vertices and uvs, normals, skin weights and so on stay exactly right.
+use std::collections::HashMap;
+
+use drowsed_math::{FVec3, Vector, EuclideanGeometry};
+
+use super::geometry::{triangle_normal, BoundingSphere};
+use super::model_loader::{StandardModelData, safe_normalize};
+use crate::vk_obj::rendering::mesh::SubMesh;
+
+/// How much border edges count compared to the faces next to them when borders arent locked.
+const BORDER_WEIGHT: f64 = 10.0;
+
+#[derive(Debug, Clone, Copy)]
+pub struct SimplifyOptions {
+    /// Never move vertices on open edges, so meshes that get stitched to other meshes
+    /// (and submeshes to each other) dont get gaps. When off, borders still only
+    /// collapse along themselves.
+    pub lock_borders: bool,
+    /// Stop once a collapse would cost more than this, measured the same way as
+    /// `Simplified::error`. `None` only stops at the target.
+    pub max_error: Option<f32>,
+}
+impl Default for SimplifyOptions {
+    fn default() -> Self {
+        Self { lock_borders: true, max_error: None }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Simplified {
+    pub indices: Vec<u32>,
+    /// Error of the most expensive collapse, in model units. This is the area weighted RMS
+    /// distance of the new vertex to the planes of the faces it replaced, not a hard bound,
+    /// so at sharp creases single points can end up further away than this.
+    pub error: f32,
+}
+
+/// Sum of squared distances to a set of planes, stored as the upper half of a 4x4 matrix.
+#[derive(Debug, Clone, Copy, Default)]
+struct Quadric {
+    a00: f64, a01: f64, a02: f64, a11: f64, a12: f64, a22: f64,
+    b0: f64, b1: f64, b2: f64,
+    c: f64,
+    weight: f64,
+}
+impl Quadric {
+    fn from_plane(n: FVec3, point: FVec3, weight: f64) -> Self {
+        let (x, y, z) = (n.x as f64, n.y as f64, n.z as f64);
+        let d = -(x * point.x as f64 + y * point.y as f64 + z * point.z as f64);
+        Self {
+            a00: weight * x * x, a01: weight * x * y, a02: weight * x * z,
+            a11: weight * y * y, a12: weight * y * z, a22: weight * z * z,
+            b0: weight * x * d, b1: weight * y * d, b2: weight * z * d,
+            c: weight * d * d,
+            weight,
+        }
+    }
+    fn add(&mut self, o: &Quadric) {
+        self.a00 += o.a00; self.a01 += o.a01; self.a02 += o.a02;
+        self.a11 += o.a11; self.a12 += o.a12; self.a22 += o.a22;
+        self.b0 += o.b0; self.b1 += o.b1; self.b2 += o.b2;
+        self.c += o.c;
+        self.weight += o.weight;
+    }
+    /// Weighted average squared distance from `p` to the planes.
+    fn error(&self, p: FVec3) -> f64 {
+        let (x, y, z) = (p.x as f64, p.y as f64, p.z as f64);
+        let e = self.a00 * x * x + self.a11 * y * y + self.a22 * z * z
+            + 2.0 * (self.a01 * x * y + self.a02 * x * z + self.a12 * y * z)
+            + 2.0 * (self.b0 * x + self.b1 * y + self.b2 * z)
+            + self.c;
+        if self.weight > 0.0 { e.max(0.0) / self.weight } else { 0.0 }
+    }
+}
+
+/// What a position is allowed to collapse along.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VertexKind {
+    /// Inside of a surface with a single vertex, can go to any neighbour.
+    Manifold,
+    /// On an open edge, only moves along it.
+    Border,
+    /// Split into more vertices by a uv or normal seam, only moves along the seam.
+    Seam,
+    /// Corners of borders and seams, places where they meet and non manifold geometry.
+    Locked,
+}
+
+#[derive(Default)]
+struct EdgeInfo {
+    triangles: u32,
+    /// Distinct vertex pairs used on this edge, more than one means it is a seam.
+    wedges: Vec<(u32, u32)>,
+}
+
+fn vector_key(p: FVec3) -> [u32; 3] {
+    [p.x, p.y, p.z].map(|v| if v == 0.0 { 0 } else { v.to_bits() })
+}
+
+/// # simplify
+///
+/// Collapses edges, cheapest first, until there are at most `target_index_count` indices
+/// left or nothing else can be collapsed without breaking a border, a seam or flipping a
+/// triangle. Vertices at the same position count as one for the topology, so uv and normal
+/// seams hold the mesh together instead of tearing it open. Everything happens in passes
+/// that only touch every neighbourhood once, each pass picks up where the last one stopped.
+pub fn simplify(positions: &[FVec3], indices: &[u32], target_index_count: usize, options: &SimplifyOptions) -> Simplified {
+    let vertex_count = positions.len();
+    // All vertices at one position share one "canonical" vertex, the first one.
+    let mut canonical = vec![0u32; vertex_count];
+    let mut lookup: HashMap<[u32; 3], u32> = HashMap::new();
+    for (vertex, position) in positions.iter().enumerate() {
+        canonical[vertex] = *lookup.entry(vector_key(*position)).or_insert(vertex as u32);
+    }
+    let position_of = |wedge: u32| canonical[wedge as usize];
+    let point = |p: u32| positions[p as usize];
+
+    let mut triangles: Vec<[u32; 3]> = indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]])
+        .filter(|t| {
+            let [a, b, c] = t.map(position_of);
+            a != b && b != c && a != c
+        })
+        .collect();
+    let mut quadrics = vec![Quadric::default(); vertex_count];
+    for t in triangles.iter() {
+        let [a, b, c] = t.map(position_of);
+        let n = triangle_normal(point(a), point(b), point(c));
+        let area = (n.dot(&n) as f64).sqrt() * 0.5;
+        let quadric = Quadric::from_plane(safe_normalize(n), point(a), area);
+        for p in [a, b, c] {
+            quadrics[p as usize].add(&quadric);
+        }
+    }
+    if !options.lock_borders {
+        // A plane through every border edge standing up from its face keeps borders from drifting inwards.
+        let edges = edge_info(&triangles, &canonical);
+        for t in triangles.iter() {
+            let p = t.map(position_of);
+            let n = safe_normalize(triangle_normal(point(p[0]), point(p[1]), point(p[2])));
+            for i in 0..3 {
+                let (a, b) = (p[i], p[(i + 1) % 3]);
+                if edges[&(a.min(b), a.max(b))].triangles != 1 {
+                    continue;
+                }
+                let edge = point(b) - point(a);
+                let quadric = Quadric::from_plane(safe_normalize(edge.cross(n)), point(a), edge.dot(&edge) as f64 * BORDER_WEIGHT);
+                quadrics[a as usize].add(&quadric);
+                quadrics[b as usize].add(&quadric);
+            }
+        }
+    }
+
+    let max_error = options.max_error.map(|e| e as f64 * e as f64);
+    let mut error = 0.0f64;
+    while triangles.len() * 3 > target_index_count {
+        let edges = edge_info(&triangles, &canonical);
+        let kinds = classify(&triangles, &edges, &canonical, vertex_count, options.lock_borders);
+        let mut vertex_triangles: Vec<Vec<usize>> = vec![vec![]; vertex_count];
+        for (i, t) in triangles.iter().enumerate() {
+            for p in t.map(position_of) {
+                vertex_triangles[p as usize].push(i);
+            }
+        }
+
+        let mut candidates: Vec<(f64, u32, u32)> = vec![];
+        for ((a, b), info) in edges.iter() {
+            let border = info.triangles == 1;
+            let seam = !border && info.wedges.len() > 1;
+            for (from, to) in [(*a, *b), (*b, *a)] {
+                let allowed = match kinds[from as usize] {
+                    VertexKind::Manifold => true,
+                    VertexKind::Border => border,
+                    VertexKind::Seam => seam,
+                    VertexKind::Locked => false,
+                };
+                if allowed {
+                    let mut quadric = quadrics[from as usize];
+                    quadric.add(&quadrics[to as usize]);
+                    candidates.push((quadric.error(point(to)), from, to));
+                }
+            }
+        }
+        // Ties get broken by the vertices so the result doesnt depend on the order of the hash map.
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+
+        let mut alive = vec![true; triangles.len()];
+        let mut alive_count = triangles.len();
+        let mut touched = vec![false; vertex_count];
+        let mut collapsed = false;
+        for (cost, from, to) in candidates {
+            if alive_count * 3 <= target_index_count || max_error.map_or(false, |max| cost > max) {
+                break;
+            }
+            if touched[from as usize] || touched[to as usize] {
+                continue;
+            }
+            let around = &vertex_triangles[from as usize];
+            // Every vertex at `from` needs a vertex at `to` it shares an edge with, that is where it goes.
+            let mut partners: Vec<(u32, u32)> = vec![];
+            for t in around.iter().map(|i| &triangles[*i]) {
+                for i in 0..3 {
+                    for (x, y) in [(t[i], t[(i + 1) % 3]), (t[(i + 1) % 3], t[i])] {
+                        if position_of(x) == from && position_of(y) == to && !partners.iter().any(|(w, _)| *w == x) {
+                            partners.push((x, y));
+                        }
+                    }
+                }
+            }
+            let complete = around.iter().all(|i| {
+                triangles[*i].iter().all(|w| position_of(*w) != from || partners.iter().any(|(x, _)| x == w))
+            });
+            if !complete || flips(&triangles, around, from, to, &canonical, positions) {
+                continue;
+            }
+            for i in around.iter() {
+                let t = &mut triangles[*i];
+                if t.iter().any(|w| position_of(*w) == to) {
+                    alive[*i] = false;
+                    alive_count -= 1;
+                }
+                for w in t.iter_mut() {
+                    if let Some((_, partner)) = partners.iter().find(|(x, _)| x == w) {
+                        *w = *partner;
+                    }
+                }
+                for w in t.iter() {
+                    touched[position_of(*w) as usize] = true;
+                }
+            }
+            touched[from as usize] = true;
+            let from_quadric = quadrics[from as usize];
+            quadrics[to as usize].add(&from_quadric);
+            error = error.max(cost);
+            collapsed = true;
+        }
+        let mut i = 0;
+        triangles.retain(|_| {
+            i += 1;
+            alive[i - 1]
+        });
+        if !collapsed {
+            break;
+        }
+    }
+    Simplified {
+        indices: triangles.iter().flatten().copied().collect(),
+        error: error.sqrt() as f32,
+    }
+}
+
+/// Every edge between two positions with how many triangles use it and through which vertices.
+fn edge_info(triangles: &[[u32; 3]], canonical: &[u32]) -> HashMap<(u32, u32), EdgeInfo> {
+    let mut edges: HashMap<(u32, u32), EdgeInfo> = HashMap::new();
+    for t in triangles.iter() {
+        for i in 0..3 {
+            let (wa, wb) = (t[i], t[(i + 1) % 3]);
+            let (a, b) = (canonical[wa as usize], canonical[wb as usize]);
+            let (key, wedges) = if a < b { ((a, b), (wa, wb)) } else { ((b, a), (wb, wa)) };
+            let info = edges.entry(key).or_default();
+            info.triangles += 1;
+            if !info.wedges.contains(&wedges) {
+                info.wedges.push(wedges);
+            }
+        }
+    }
+    edges
+}
+fn classify(triangles: &[[u32; 3]], edges: &HashMap<(u32, u32), EdgeInfo>, canonical: &[u32], vertex_count: usize, lock_borders: bool) -> Vec<VertexKind> {
+    let mut wedges: Vec<Vec<u32>> = vec![vec![]; vertex_count];
+    for w in triangles.iter().flatten() {
+        let list = &mut wedges[canonical[*w as usize] as usize];
+        if !list.contains(w) {
+            list.push(*w);
+        }
+    }
+    let (mut borders, mut seams, mut complex) = (vec![0u32; vertex_count], vec![0u32; vertex_count], vec![false; vertex_count]);
+    for ((a, b), info) in edges.iter() {
+        for p in [*a, *b] {
+            match info.triangles {
+                1 => borders[p as usize] += 1,
+                2 if info.wedges.len() > 1 => seams[p as usize] += 1,
+                2 => {}
+                _ => complex[p as usize] = true,
+            }
+        }
+    }
+    (0..vertex_count).map(|p| {
+        match (complex[p], wedges[p].len(), borders[p], seams[p]) {
+            (true, _, _, _) => VertexKind::Locked,
+            (_, 1, 0, 0) => VertexKind::Manifold,
+            (_, 1, 2, 0) if !lock_borders => VertexKind::Border,
+            (_, 2..=usize::MAX, 0, 2) => VertexKind::Seam,
+            _ => VertexKind::Locked,
+        }
+    }).collect()
+}
+/// Whether moving `from` onto `to` turns any of the surviving triangles around `from` over.
+fn flips(triangles: &[[u32; 3]], around: &[usize], from: u32, to: u32, canonical: &[u32], positions: &[FVec3]) -> bool {
+    around.iter().map(|i| triangles[*i].map(|w| canonical[w as usize])).filter(|t| !t.contains(&to)).any(|t| {
+        let before = t.map(|p| positions[p as usize]);
+        let after = t.map(|p| positions[if p == from { to } else { p } as usize]);
+        let (n0, n1) = (triangle_normal(before[0], before[1], before[2]), triangle_normal(after[0], after[1], after[2]));
+        // Also refuses to make slivers with no area left.
+        n0.dot(&n1) <= 0.0 || n1.dot(&n1) <= f32::EPSILON * n0.dot(&n0)
+    })
+}
+
+/// # LodLevel
+///
+/// One level of a LOD chain. Every level indexes the vertices of the model it came from,
+/// so they can all share one vertex buffer.
+#[derive(Debug, Clone)]
+pub struct LodLevel {
+    pub indices: Vec<u32>,
+    /// Same materials as the model, with ranges into `indices`.
+    pub submeshes: Vec<SubMesh>,
+    /// The ratio of triangles that was asked for, the level can have more when it ran
+    /// out of things to collapse.
+    pub ratio: f32,
+    /// `Simplified::error` of every level up to this one added together, in model units. An
+    /// area weighted RMS distance, not the furthest any point moved.
+    pub error: f32,
+    /// `error` divided by the radius of the bounding sphere, so it doesnt depend on the size of the model.
+    pub relative_error: f32,
+}
+impl LodLevel {
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+}
+
+/// Size of `error` on screen in pixels when the model is `distance` away, for a camera with
+/// a vertical field of view of `fov_y` radians drawing `viewport_height` pixels high.
+pub fn screen_space_error(error: f32, distance: f32, fov_y: f32, viewport_height: f32) -> f32 {
+    if distance <= 0.0 {
+        return f32::INFINITY;
+    }
+    error * viewport_height / (2.0 * distance * (fov_y * 0.5).tan())
+}
+/// # select_lod
+///
+/// The coarsest level whose error stays under `max_pixels` on screen. `error_scale` is the
+/// biggest scale of the model's world matrix since the errors are in model units.
+pub fn select_lod(levels: &[LodLevel], error_scale: f32, distance: f32, fov_y: f32, viewport_height: f32, max_pixels: f32) -> usize {
+    levels.iter().rposition(|level| screen_space_error(level.error * error_scale, distance, fov_y, viewport_height) <= max_pixels).unwrap_or(0)
+}
+
+impl StandardModelData {
+    /// # lod_chain
+    ///
+    /// Level 0 is the model as it is, then one level per entry of `ratios` (fractions of
+    /// the original triangle count, biggest first). Every level is simplified from the one
+    /// before it and every submesh on its own, so materials never bleed into each other.
+    pub fn lod_chain(&self, ratios: &[f32], options: &SimplifyOptions) -> Vec<LodLevel> {
+        let radius = BoundingSphere::from_points(&self.vertices).map_or(0.0, |sphere| sphere.radius);
+        let ranges: Vec<SubMesh> = match self.submeshes.is_empty() {
+            true => vec![SubMesh { material: 0, first_index: 0, index_count: self.indices.len() as u32 }],
+            false => self.submeshes.clone(),
+        };
+        let mut levels = vec![LodLevel {
+            indices: self.indices.clone(),
+            submeshes: ranges.clone(),
+            ratio: 1.0,
+            error: 0.0,
+            relative_error: 0.0,
+        }];
+        for ratio in ratios.iter() {
+            let previous = levels.last().unwrap();
+            let mut level = LodLevel { indices: vec![], submeshes: vec![], ratio: *ratio, error: previous.error, relative_error: 0.0 };
+            for (submesh, original) in previous.submeshes.iter().zip(ranges.iter()) {
+                let start = submesh.first_index as usize;
+                let range = &previous.indices[start..start + submesh.index_count as usize];
+                let target = ((original.index_count as f32 / 3.0 * ratio) as usize) * 3;
+                let simplified = simplify(&self.vertices, range, target, options);
+                level.submeshes.push(SubMesh {
+                    material: submesh.material,
+                    first_index: level.indices.len() as u32,
+                    index_count: simplified.indices.len() as u32,
+                });
+                level.indices.extend(simplified.indices);
+                // Errors of every level stack on top of the ones before it.
+                level.error = level.error.max(previous.error + simplified.error);
+            }
+            level.relative_error = if radius > 0.0 { level.error / radius } else { 0.0 };
+            levels.push(level);
+        }
+        levels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid(size: u32) -> (Vec<FVec3>, Vec<u32>) {
+        let row = size + 1;
+        let positions = (0..row * row).map(|i| FVec3::new((i % row) as f32, (i / row) as f32, 0.0)).collect();
+        let mut indices = vec![];
+        for y in 0..size {
+            for x in 0..size {
+                let a = y * row + x;
+                indices.extend_from_slice(&[a, a + 1, a + row + 1, a, a + row + 1, a + row]);
+            }
+        }
+        (positions, indices)
+    }
+    /// monke.fbx is flat shaded, so every position is split into a vertex per face and all
+    /// of them would be locked seams. The control points are the connected mesh underneath.
+    fn welded_monke() -> StandardModelData {
+        let models = StandardModelData::try_new(&format!("{}/monke.fbx", env!("CARGO_MANIFEST_DIR"))).unwrap();
+        let model = models.into_iter().find(|model| !model.vertices.is_empty()).unwrap();
+        let count = model.control_points.iter().max().map_or(0, |max| *max as usize + 1);
+        let mut vertices = vec![FVec3::from(0.0); count];
+        for (vertex, control_point) in model.control_points.iter().enumerate() {
+            vertices[*control_point as usize] = model.vertices[vertex];
+        }
+        let indices = model.indices.iter().map(|index| model.control_points[*index as usize]).collect();
+        StandardModelData { vertices, indices, submeshes: model.submeshes, ..Default::default() }
+    }
+
+    #[test]
+    fn monke_reaches_the_target() {
+        let model = welded_monke();
+        let triangles = model.indices.len() / 3;
+        for ratio in [0.5, 0.25] {
+            let target = (triangles as f32 * ratio) as usize * 3;
+            let simplified = simplify(&model.vertices, &model.indices, target, &SimplifyOptions::default());
+            assert_eq!(simplified.indices.len(), target, "ratio {}", ratio);
+            assert!(simplified.error > 0.0);
+            assert!(simplified.indices.iter().all(|index| (*index as usize) < model.vertices.len()));
+        }
+    }
+
+    #[test]
+    fn locked_borders_stay() {
+        let (positions, indices) = grid(8);
+        let border: Vec<u32> = (0..positions.len() as u32).filter(|i| [0, 8].contains(&(i % 9)) || [0, 8].contains(&(i / 9))).collect();
+        let locked = simplify(&positions, &indices, 0, &SimplifyOptions::default());
+        for vertex in border.iter() {
+            assert!(locked.indices.contains(vertex), "border vertex {} got collapsed", vertex);
+        }
+        // A flat grid costs nothing to collapse, the border is the only thing left holding it up.
+        assert!(locked.indices.len() < indices.len());
+        assert_eq!(locked.error, 0.0);
+        let unlocked = simplify(&positions, &indices, 0, &SimplifyOptions { lock_borders: false, max_error: None });
+        assert!(unlocked.indices.len() < locked.indices.len());
+    }
+
+    #[test]
+    fn uv_seams_dont_tear() {
+        // The middle column of the grid is split in two, the right half uses the copies
+        // like it would across a uv seam.
+        let (mut positions, indices) = grid(8);
+        let mut twins: HashMap<u32, u32> = HashMap::new();
+        for y in 0..9 {
+            twins.insert(y * 9 + 4, positions.len() as u32);
+            positions.push(positions[(y * 9 + 4) as usize]);
+        }
+        let mut split = vec![];
+        for (quad, corners) in indices.chunks_exact(6).enumerate() {
+            let right = quad % 8 >= 4;
+            split.extend(corners.iter().map(|v| if right { twins.get(v).copied().unwrap_or(*v) } else { *v }));
+        }
+        let indices = split;
+        let on_right = |v: u32| v >= 81 || v % 9 > 4;
+
+        for target in [0, 64 * 3, 32 * 3] {
+            let simplified = simplify(&positions, &indices, target, &SimplifyOptions::default());
+            assert!(simplified.indices.iter().all(|index| (*index as usize) < positions.len()));
+            for triangle in simplified.indices.chunks_exact(3) {
+                assert!(triangle.iter().all(|v| on_right(*v)) || triangle.iter().all(|v| !on_right(*v)), "{:?} crosses the seam", triangle);
+            }
+            // Both sides of the seam keep the same vertices, otherwise it opened up somewhere.
+            for (left, right) in twins.iter() {
+                assert_eq!(simplified.indices.contains(left), simplified.indices.contains(right), "seam vertex {} lost its twin", left);
+            }
+            // No new holes either, every open edge is still on the outside of the grid.
+            let canonical = |v: u32| if v >= 81 { twins.iter().find(|(_, twin)| **twin == v).map(|(left, _)| *left).unwrap() } else { v };
+            let mut edges: HashMap<(u32, u32), u32> = HashMap::new();
+            for triangle in simplified.indices.chunks_exact(3) {
+                for i in 0..3 {
+                    let (a, b) = (canonical(triangle[i]), canonical(triangle[(i + 1) % 3]));
+                    *edges.entry((a.min(b), a.max(b))).or_default() += 1;
+                }
+            }
+            let outside = |v: u32| [0, 8].contains(&(v % 9)) || [0, 8].contains(&(v / 9));
+            for ((a, b), count) in edges {
+                assert!(count == 2 || (outside(a) && outside(b)), "edge {}-{} opened up", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn lod_errors_grow_down_the_chain() {
+        let model = welded_monke();
+        let levels = model.lod_chain(&[0.5, 0.25, 0.1], &SimplifyOptions::default());
+        assert_eq!(levels.len(), 4);
+        for pair in levels.windows(2) {
+            assert!(pair[1].error >= pair[0].error, "{} then {}", pair[0].error, pair[1].error);
+            assert!(pair[1].triangle_count() < pair[0].triangle_count());
+        }
+        for level in levels.iter() {
+            let last = level.submeshes.last().unwrap();
+            assert_eq!((last.first_index + last.index_count) as usize, level.indices.len());
+        }
+
+        let fov = 60f32.to_radians();
+        let mut previous = 0;
+        for distance in [0.01, 1.0, 10.0, 100.0, 1000.0, 100000.0] {
+            let selected = select_lod(&levels, 1.0, distance, fov, 1080.0, 1.0);
+            assert!(selected >= previous, "{} picked level {} after {}", distance, selected, previous);
+            previous = selected;
+        }
+        assert_eq!(select_lod(&levels, 1.0, 0.01, fov, 1080.0, 1.0), 0);
+        assert_eq!(previous, levels.len() - 1);
+    }
+
+    #[test]
+    fn screen_space_error_shrinks_with_distance() {
+        let fov = 90f32.to_radians();
+        let near = screen_space_error(1.0, 10.0, fov, 1000.0);
+        assert!((near - 50.0).abs() < 1e-3, "{}", near);
+        assert!((screen_space_error(1.0, 20.0, fov, 1000.0) - near * 0.5).abs() < 1e-3);
+        assert_eq!(screen_space_error(1.0, 0.0, fov, 1000.0), f32::INFINITY);
+    }
+}
@@ -0,0 +1,188 @@
+#![allow(unused)]
+use std::collections::HashMap;
+
+use drowsed_math::{FMat4, FVec4, SquareMatrix};
+use fbxcel_dom::fbxcel::tree::v7400::NodeHandle;
+
+use super::error::ModelLoadError;
+use super::model_loader::NodeAttributes;
+use super::scene::SceneGraph;
+use super::transform::affine_inverse;
+
+/// How many joints can move a single vertex, anything past this gets dropped
+/// and the remaining weights get scaled back up to 1.
+pub const MAX_INFLUENCES: usize = 4;
+
+/// # Cluster
+///
+/// A `Deformer` of type `Cluster`. It says which control points one bone moves and by how much.
+/// `transform` is where the mesh was when it got bound and `transform_link` where the bone was.
+#[derive(Debug, Clone)]
+pub struct Cluster {
+    pub indices: Vec<i32>,
+    pub weights: Vec<f64>,
+    pub transform: FMat4,
+    pub transform_link: Option<FMat4>,
+}
+impl Cluster {
+    pub(crate) fn parse(node: &NodeHandle) -> Result<(i64, Cluster), ModelLoadError> {
+        let collection_id = node.attr_i64(0)?;
+        let mut cluster = Cluster {
+            indices: vec![],
+            weights: vec![],
+            transform: FMat4::identity(),
+            transform_link: None,
+        };
+        for child in node.children() {
+            match child.name() {
+                "Indexes" => cluster.indices = child.attr_arr_i32(0)?.to_vec(),
+                "Weights" => cluster.weights = child.attr_arr_f64(0)?.to_vec(),
+                "Transform" => cluster.transform = to_fmat4(&child, child.attr_arr_f64(0)?)?,
+                "TransformLink" => cluster.transform_link = Some(to_fmat4(&child, child.attr_arr_f64(0)?)?),
+                _ => {}
+            }
+        }
+        if cluster.indices.len() != cluster.weights.len() {
+            return Err(ModelLoadError::IndexOutOfRange {
+                path: format!("{}/Weights", node.path()),
+                index: cluster.indices.len(),
+                len: cluster.weights.len(),
+            });
+        }
+        Ok((collection_id, cluster))
+    }
+}
+/// Reads the `PoseNode`s of a `BindPose`, every model id with its world matrix at bind time.
+pub(crate) fn parse_bind_pose(node: &NodeHandle) -> Result<Vec<(i64, FMat4)>, ModelLoadError> {
+    let mut poses = vec![];
+    for pose_node in node.children_by_name("PoseNode") {
+        let id = pose_node.child("Node")?.attr_i64(0)?;
+        let matrix = pose_node.child("Matrix")?;
+        poses.push((id, to_fmat4(&matrix, matrix.attr_arr_f64(0)?)?));
+    }
+    Ok(poses)
+}
+/// FBX matrices are 16 doubles with the translation at the end, which is
+/// the same column major layout `FMat4` uses.
+pub(crate) fn to_fmat4(node: &NodeHandle, values: &[f64]) -> Result<FMat4, ModelLoadError> {
+    if values.len() != 16 {
+        return Err(ModelLoadError::IndexOutOfRange { path: node.path(), index: 16, len: values.len() });
+    }
+    let v = |i: usize| values[i] as f32;
+    let mut m = FMat4::identity();
+    m.x.x = v(0); m.x.y = v(1); m.x.z = v(2); m.x.w = v(3);
+    m.y.x = v(4); m.y.y = v(5); m.y.z = v(6); m.y.w = v(7);
+    m.z.x = v(8); m.z.y = v(9); m.z.z = v(10); m.z.w = v(11);
+    m.w.x = v(12); m.w.y = v(13); m.w.z = v(14); m.w.w = v(15);
+    Ok(m)
+}
+
+/// # Joint
+///
+/// One bone a skin is bound to. `node` is the index of the bone in the `SceneGraph`
+/// (and in the `Vec<StandardModelData>` it was loaded with), `parent` is the index of
+/// the closest ancestor that is also a joint of the same skin.
+#[derive(Debug, Clone)]
+pub struct Joint {
+    pub tag: String,
+    pub node: usize,
+    pub parent: Option<usize>,
+    /// World transform of the bone when the mesh got bound to it.
+    pub bind_pose: FMat4,
+    /// Takes a vertex from the space of the mesh into the space of the bone at bind time.
+    /// `world of the bone * inverse_bind` is the skinning matrix.
+    pub inverse_bind: FMat4,
+}
+
+/// # Skin
+///
+/// Joints of a skinned model plus up to `MAX_INFLUENCES` joint indices and weights
+/// per vertex. The weights of a vertex add up to 1 unless no joint moves it at all.
+#[derive(Debug, Clone, Default)]
+pub struct Skin {
+    pub joints: Vec<Joint>,
+    pub joint_indices: Vec<[u32; MAX_INFLUENCES]>,
+    pub joint_weights: Vec<FVec4>,
+}
+impl Skin {
+    /// # build
+    ///
+    /// `clusters` are the clusters of one `Skin` deformer each with the scene node of its bone.
+    /// `control_points` maps every vertex of the model to the control point it came from.
+    pub(crate) fn build(
+        clusters: &[(&Cluster, usize)],
+        graph: &SceneGraph,
+        bind_poses: &HashMap<i64, FMat4>,
+        control_point_count: usize,
+        control_points: &[u32],
+    ) -> Result<Self, ModelLoadError> {
+        let conversion = graph.conversion;
+        let inverse_conversion = affine_inverse(&conversion);
+        let convert = |m: FMat4| conversion * m * inverse_conversion;
+
+        let mut joints: Vec<Joint> = clusters.iter().map(|(cluster, node)| {
+            let scene_node = &graph.nodes[*node];
+            // The world transform of the graph is already converted, the matrices from the file are not.
+            let link = match cluster.transform_link.or_else(|| bind_poses.get(&scene_node.id).copied()) {
+                Some(link) => convert(link),
+                None => scene_node.world,
+            };
+            Joint {
+                tag: scene_node.tag.clone(),
+                node: *node,
+                parent: None,
+                bind_pose: link,
+                inverse_bind: affine_inverse(&link) * convert(cluster.transform),
+            }
+        }).collect();
+        let joint_of_node: HashMap<usize, usize> = joints.iter().enumerate().map(|(i, joint)| (joint.node, i)).collect();
+        for joint in joints.iter_mut() {
+            let mut current = graph.nodes[joint.node].parent;
+            while let Some(node) = current {
+                if let Some(parent) = joint_of_node.get(&node) {
+                    joint.parent = Some(*parent);
+                    break;
+                }
+                current = graph.nodes[node].parent;
+            }
+        }
+
+        let mut influences: Vec<Vec<(u32, f32)>> = vec![vec![]; control_point_count];
+        for (joint, (cluster, _)) in clusters.iter().enumerate() {
+            for (index, weight) in cluster.indices.iter().zip(cluster.weights.iter()) {
+                let influence = usize::try_from(*index).ok().and_then(|i| influences.get_mut(i)).ok_or_else(|| ModelLoadError::IndexOutOfRange {
+                    path: format!("Objects/Deformer({})/Indexes", joints[joint].tag),
+                    index: (*index).max(0) as usize,
+                    len: control_point_count,
+                })?;
+                if *weight > 0.0 {
+                    influence.push((joint as u32, *weight as f32));
+                }
+            }
+        }
+        let per_control_point: Vec<([u32; MAX_INFLUENCES], FVec4)> = influences.into_iter().map(|mut influence| {
+            influence.sort_by(|a, b| b.1.total_cmp(&a.1));
+            influence.truncate(MAX_INFLUENCES);
+            let total: f32 = influence.iter().map(|(_, weight)| weight).sum();
+            let mut indices = [0; MAX_INFLUENCES];
+            let mut weights = [0.0; MAX_INFLUENCES];
+            for (slot, (joint, weight)) in influence.into_iter().enumerate() {
+                indices[slot] = joint;
+                weights[slot] = if total > 0.0 { weight / total } else { 0.0 };
+            }
+            (indices, FVec4::new(weights[0], weights[1], weights[2], weights[3]))
+        }).collect();
+
+        let mut skin = Skin {
+            joints,
+            joint_indices: Vec::with_capacity(control_points.len()),
+            joint_weights: Vec::with_capacity(control_points.len()),
+        };
+        for control_point in control_points.iter() {
+            let (indices, weights) = per_control_point[*control_point as usize];
+            skin.joint_indices.push(indices);
+            skin.joint_weights.push(weights);
+        }
+        Ok(skin)
+    }
+}
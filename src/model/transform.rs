@@ -0,0 +1,248 @@
+#![allow(unused)]
+//! Small matrix helpers for the model loaders. Matrices are column major the
+//! same way `Camera` fills them, so `m.w` is the translation column and the
+//! element in row `r` column `c` is `m.c.r`.
+use drowsed_math::{FMat4, FVec3, SquareMatrix, TransformQuaternion3D, complex::quaternion::Quaternion};
+
+pub fn translation_matrix(t: FVec3) -> FMat4 {
+    let mut m = FMat4::identity();
+    m.w.x = t.x;
+    m.w.y = t.y;
+    m.w.z = t.z;
+    m
+}
+pub fn scaling_matrix(s: FVec3) -> FMat4 {
+    let mut m = FMat4::identity();
+    m.x.x = s.x;
+    m.y.y = s.y;
+    m.z.z = s.z;
+    m
+}
+/// Rotation around the x axis, in radians.
+pub fn rotation_x(angle: f32) -> FMat4 {
+    let (s, c) = angle.sin_cos();
+    let mut m = FMat4::identity();
+    m.y.y = c;
+    m.y.z = s;
+    m.z.y = -s;
+    m.z.z = c;
+    m
+}
+/// Rotation around the y axis, in radians.
+pub fn rotation_y(angle: f32) -> FMat4 {
+    let (s, c) = angle.sin_cos();
+    let mut m = FMat4::identity();
+    m.x.x = c;
+    m.x.z = -s;
+    m.z.x = s;
+    m.z.z = c;
+    m
+}
+/// Rotation around the z axis, in radians.
+pub fn rotation_z(angle: f32) -> FMat4 {
+    let (s, c) = angle.sin_cos();
+    let mut m = FMat4::identity();
+    m.x.x = c;
+    m.x.y = s;
+    m.y.x = -s;
+    m.y.y = c;
+    m
+}
+/// Rotation matrix of a unit quaternion.
+pub fn quaternion_matrix(q: Quaternion) -> FMat4 {
+    let (x, y, z, w) = (q.x, q.y, q.z, q.w);
+    let mut m = FMat4::identity();
+    m.x.x = 1.0 - 2.0 * (y * y + z * z);
+    m.x.y = 2.0 * (x * y + z * w);
+    m.x.z = 2.0 * (x * z - y * w);
+    m.y.x = 2.0 * (x * y - z * w);
+    m.y.y = 1.0 - 2.0 * (x * x + z * z);
+    m.y.z = 2.0 * (y * z + x * w);
+    m.z.x = 2.0 * (x * z + y * w);
+    m.z.y = 2.0 * (y * z - x * w);
+    m.z.z = 1.0 - 2.0 * (x * x + y * y);
+    m
+}
+/// Translation * rotation * scale of a transform.
+pub fn transform_matrix(transform: &TransformQuaternion3D) -> FMat4 {
+    translation_matrix(transform.translation) * quaternion_matrix(transform.rotation) * scaling_matrix(transform.scale)
+}
+pub fn transform_point(m: &FMat4, p: FVec3) -> FVec3 {
+    FVec3::new(
+        m.x.x * p.x + m.y.x * p.y + m.z.x * p.z + m.w.x,
+        m.x.y * p.x + m.y.y * p.y + m.z.y * p.z + m.w.y,
+        m.x.z * p.x + m.y.z * p.y + m.z.z * p.z + m.w.z,
+    )
+}
+/// Same as `transform_point` but ignores the translation.
+pub fn transform_vector(m: &FMat4, v: FVec3) -> FVec3 {
+    FVec3::new(
+        m.x.x * v.x + m.y.x * v.y + m.z.x * v.z,
+        m.x.y * v.x + m.y.y * v.y + m.z.y * v.z,
+        m.x.z * v.x + m.y.z * v.y + m.z.z * v.z,
+    )
+}
+/// Determinant of the upper 3x3, the part that rotates and scales.
+pub fn determinant3(m: &FMat4) -> f32 {
+    m.x.x * (m.y.y * m.z.z - m.z.y * m.y.z)
+        - m.y.x * (m.x.y * m.z.z - m.z.y * m.x.z)
+        + m.z.x * (m.x.y * m.y.z - m.y.y * m.x.z)
+}
+/// Whether the matrix mirrors, which turns the winding of triangles inside out.
+pub fn is_mirroring(m: &FMat4) -> bool {
+    determinant3(m) < 0.0
+}
+/// Inverse of a matrix whose last row is `0 0 0 1`, which is every matrix the loaders make.
+/// Singular matrices give back the identity instead of infinities.
+pub fn affine_inverse(m: &FMat4) -> FMat4 {
+    let (a, b, c) = (m.x.x, m.y.x, m.z.x);
+    let (d, e, f) = (m.x.y, m.y.y, m.z.y);
+    let (g, h, i) = (m.x.z, m.y.z, m.z.z);
+    let co_a = e * i - f * h;
+    let co_b = -(d * i - f * g);
+    let co_c = d * h - e * g;
+    let det = a * co_a + b * co_b + c * co_c;
+    if det.abs() <= f32::EPSILON {
+        return FMat4::identity();
+    }
+    let inv_det = 1.0 / det;
+    let mut r = FMat4::identity();
+    r.x.x = co_a * inv_det;
+    r.x.y = co_b * inv_det;
+    r.x.z = co_c * inv_det;
+    r.y.x = -(b * i - c * h) * inv_det;
+    r.y.y = (a * i - c * g) * inv_det;
+    r.y.z = -(a * h - b * g) * inv_det;
+    r.z.x = (b * f - c * e) * inv_det;
+    r.z.y = -(a * f - c * d) * inv_det;
+    r.z.z = (a * e - b * d) * inv_det;
+    let t = transform_vector(&r, FVec3::new(m.w.x, m.w.y, m.w.z));
+    r.w.x = -t.x;
+    r.w.y = -t.y;
+    r.w.z = -t.z;
+    r
+}
+/// Matrix for transforming normals, the transpose of the inverse. Only the upper 3x3 is useful.
+pub fn normal_matrix(m: &FMat4) -> FMat4 {
+    let inverse = affine_inverse(m);
+    let mut r = FMat4::identity();
+    r.x.x = inverse.x.x;
+    r.x.y = inverse.y.x;
+    r.x.z = inverse.z.x;
+    r.y.x = inverse.x.y;
+    r.y.y = inverse.y.y;
+    r.y.z = inverse.z.y;
+    r.z.x = inverse.x.z;
+    r.z.y = inverse.y.z;
+    r.z.z = inverse.z.z;
+    r
+}
+/// Quaternion of a pure rotation matrix.
+pub fn matrix_quaternion(m: &FMat4) -> Quaternion {
+    let trace = m.x.x + m.y.y + m.z.z;
+    let (x, y, z, w);
+    if trace > 0.0 {
+        let s = (trace + 1.0).sqrt() * 2.0;
+        w = 0.25 * s;
+        x = (m.y.z - m.z.y) / s;
+        y = (m.z.x - m.x.z) / s;
+        z = (m.x.y - m.y.x) / s;
+    } else if m.x.x > m.y.y && m.x.x > m.z.z {
+        let s = (1.0 + m.x.x - m.y.y - m.z.z).sqrt() * 2.0;
+        w = (m.y.z - m.z.y) / s;
+        x = 0.25 * s;
+        y = (m.y.x + m.x.y) / s;
+        z = (m.z.x + m.x.z) / s;
+    } else if m.y.y > m.z.z {
+        let s = (1.0 + m.y.y - m.x.x - m.z.z).sqrt() * 2.0;
+        w = (m.z.x - m.x.z) / s;
+        x = (m.y.x + m.x.y) / s;
+        y = 0.25 * s;
+        z = (m.z.y + m.y.z) / s;
+    } else {
+        let s = (1.0 + m.z.z - m.x.x - m.y.y).sqrt() * 2.0;
+        w = (m.x.y - m.y.x) / s;
+        x = (m.z.x + m.x.z) / s;
+        y = (m.z.y + m.y.z) / s;
+        z = 0.25 * s;
+    }
+    Quaternion { x, y, z, w }
+}
+/// Euler angles in degrees of a pure rotation matrix, in the x then y then z order
+/// `RotationOrder::XYZ` uses. Near gimbal lock the z angle ends up as 0.
+pub fn matrix_euler(m: &FMat4) -> FVec3 {
+    let sy = (-m.x.z).clamp(-1.0, 1.0);
+    let y = sy.asin();
+    let (x, z) = if sy.abs() < 0.9999 {
+        (m.y.z.atan2(m.z.z), m.x.y.atan2(m.x.x))
+    } else {
+        ((-m.z.y).atan2(m.y.y), 0.0)
+    };
+    FVec3::new(x.to_degrees(), y.to_degrees(), z.to_degrees())
+}
+/// Splits a matrix back into translation, rotation and scale.
+/// Shear (non uniform scale under a rotated parent) cant be represented and gets dropped.
+pub fn decompose(m: &FMat4) -> TransformQuaternion3D {
+    let length = |x: f32, y: f32, z: f32| (x * x + y * y + z * z).sqrt();
+    let mut scale = FVec3::new(
+        length(m.x.x, m.x.y, m.x.z),
+        length(m.y.x, m.y.y, m.y.z),
+        length(m.z.x, m.z.y, m.z.z),
+    );
+    // A mirrored axis gets put on x.
+    if is_mirroring(m) {
+        scale.x = -scale.x;
+    }
+    let mut rotation = FMat4::identity();
+    let safe = |s: f32| if s.abs() <= f32::EPSILON { 1.0 } else { s };
+    let (sx, sy, sz) = (safe(scale.x), safe(scale.y), safe(scale.z));
+    rotation.x.x = m.x.x / sx;
+    rotation.x.y = m.x.y / sx;
+    rotation.x.z = m.x.z / sx;
+    rotation.y.x = m.y.x / sy;
+    rotation.y.y = m.y.y / sy;
+    rotation.y.z = m.y.z / sy;
+    rotation.z.x = m.z.x / sz;
+    rotation.z.y = m.z.y / sz;
+    rotation.z.z = m.z.z / sz;
+    TransformQuaternion3D {
+        translation: FVec3::new(m.w.x, m.w.y, m.w.z),
+        rotation: matrix_quaternion(&rotation),
+        scale,
+    }
+}
+pub fn quaternion_dot(a: Quaternion, b: Quaternion) -> f32 {
+    a.x * b.x + a.y * b.y + a.z * b.z + a.w * b.w
+}
+/// Scales a quaternion back to unit length, the identity if it has none.
+pub fn quaternion_normalize(q: Quaternion) -> Quaternion {
+    let length = quaternion_dot(q, q).sqrt();
+    if length <= f32::EPSILON {
+        return Quaternion { x: 0.0, y: 0.0, z: 0.0, w: 1.0 };
+    }
+    Quaternion { x: q.x / length, y: q.y / length, z: q.z / length, w: q.w / length }
+}
+/// Spherical interpolation between two unit quaternions, always along the shorter arc.
+pub fn slerp(a: Quaternion, b: Quaternion, t: f32) -> Quaternion {
+    let mut dot = quaternion_dot(a, b);
+    let b = if dot < 0.0 {
+        dot = -dot;
+        Quaternion { x: -b.x, y: -b.y, z: -b.z, w: -b.w }
+    } else {
+        b
+    };
+    // Nearly the same rotation, the sine below would blow up so a normalized lerp does it.
+    let (wa, wb) = if dot > 0.9995 {
+        (1.0 - t, t)
+    } else {
+        let angle = dot.acos();
+        let sin = angle.sin();
+        (((1.0 - t) * angle).sin() / sin, (t * angle).sin() / sin)
+    };
+    quaternion_normalize(Quaternion {
+        x: a.x * wa + b.x * wb,
+        y: a.y * wa + b.y * wb,
+        z: a.z * wa + b.z * wb,
+        w: a.w * wa + b.w * wb,
+    })
+}
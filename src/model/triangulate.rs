@@ -0,0 +1,161 @@
+use std::ops::Range;
+
+use drowsed_math::{FVec3, Vector, EuclideanGeometry};
+
+/// # TriangleCorner
+///
+/// One corner of a triangle that came out of a polygon. It remembers where it came
+/// from so that anything stored per polygon vertex (normals, uvs, colors) or per polygon
+/// (materials) can still be looked up after the polygon got split.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TriangleCorner {
+    /// Position inside of `PolygonVertexIndex`.
+    pub polygon_vertex: usize,
+    /// The vertex `polygon_vertex` points at.
+    pub control_point: usize,
+    /// Which polygon the triangle belongs to.
+    pub polygon: usize,
+}
+
+/// Splits an FBX style polygon index list into the ranges of every polygon.
+/// The last index of a polygon is stored as `!index` so it is negative.
+/// If the list doesnt end on a negative index the leftovers are treated as one more polygon.
+pub fn polygon_ranges(polygon_indices: &[i32]) -> Vec<Range<usize>> {
+    let mut ranges = vec![];
+    let mut start = 0;
+    for (i, index) in polygon_indices.iter().enumerate() {
+        if index.is_negative() {
+            ranges.push(start..(i + 1));
+            start = i + 1;
+        }
+    }
+    if start < polygon_indices.len() {
+        ranges.push(start..polygon_indices.len());
+    }
+    ranges
+}
+
+/// # triangulate_polygon
+///
+/// Splits a single polygon into triangles. The returned indices point into `points`
+/// and keep the winding of the polygon. Triangles and convex polygons get a fan, concave
+/// polygons go through ear clipping. Points and lines give back nothing since there is nothing to draw.
+pub fn triangulate_polygon(points: &[FVec3]) -> Vec<[usize; 3]> {
+    let n = points.len();
+    if n < 3 {
+        return vec![];
+    }
+    if n == 3 {
+        return vec![[0, 1, 2]];
+    }
+    let normal = newell_normal(points);
+    if normal.dot(&normal) <= f32::EPSILON {
+        // Everything is on a line, nothing sensible to clip so just fan it.
+        return fan(n);
+    }
+    let projected = project(points, normal);
+    if is_convex(&projected) {
+        return fan(n);
+    }
+    ear_clip(&projected)
+}
+
+fn fan(n: usize) -> Vec<[usize; 3]> {
+    (1..(n - 1)).map(|i| [0, i, i + 1]).collect()
+}
+
+/// Newells method, works for concave and slightly non planar polygons.
+fn newell_normal(points: &[FVec3]) -> FVec3 {
+    let mut normal = FVec3::from(0.0);
+    for i in 0..points.len() {
+        let current = points[i];
+        let next = points[(i + 1) % points.len()];
+        normal.x += (current.y - next.y) * (current.z + next.z);
+        normal.y += (current.z - next.z) * (current.x + next.x);
+        normal.z += (current.x - next.x) * (current.y + next.y);
+    }
+    normal
+}
+
+/// Flattens the polygon onto the plane of its biggest normal axis. The result is
+/// always counter clockwise so the rest of the code only has to deal with one winding.
+fn project(points: &[FVec3], normal: FVec3) -> Vec<(f32, f32)> {
+    let (ax, ay, az) = (normal.x.abs(), normal.y.abs(), normal.z.abs());
+    let mut projected: Vec<(f32, f32)> = points.iter().map(|p| {
+        if az >= ax && az >= ay {
+            (p.x, p.y)
+        } else if ay >= ax {
+            (p.z, p.x)
+        } else {
+            (p.y, p.z)
+        }
+    }).collect();
+    if signed_area(&projected) < 0.0 {
+        for p in projected.iter_mut() {
+            p.0 = -p.0;
+        }
+    }
+    projected
+}
+
+fn signed_area(points: &[(f32, f32)]) -> f32 {
+    let mut area = 0.0;
+    for i in 0..points.len() {
+        let a = points[i];
+        let b = points[(i + 1) % points.len()];
+        area += a.0 * b.1 - b.0 * a.1;
+    }
+    area * 0.5
+}
+
+fn cross(a: (f32, f32), b: (f32, f32), c: (f32, f32)) -> f32 {
+    (b.0 - a.0) * (c.1 - a.1) - (b.1 - a.1) * (c.0 - a.0)
+}
+
+fn is_convex(points: &[(f32, f32)]) -> bool {
+    let n = points.len();
+    (0..n).all(|i| cross(points[(i + n - 1) % n], points[i], points[(i + 1) % n]) >= 0.0)
+}
+
+fn inside_triangle(p: (f32, f32), a: (f32, f32), b: (f32, f32), c: (f32, f32)) -> bool {
+    cross(a, b, p) >= 0.0 && cross(b, c, p) >= 0.0 && cross(c, a, p) >= 0.0
+}
+
+/// Plain O(n^2) ear clipping over a counter clockwise polygon.
+fn ear_clip(points: &[(f32, f32)]) -> Vec<[usize; 3]> {
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    let mut triangles = Vec::with_capacity(points.len() - 2);
+    let mut i = 0;
+    // Counts how many vertices we looked at without finding an ear. Once we went
+    // all the way around the polygon is self intersecting and we give up on being clever.
+    let mut misses = 0;
+    while remaining.len() > 3 {
+        let n = remaining.len();
+        let prev = remaining[(i + n - 1) % n];
+        let current = remaining[i % n];
+        let next = remaining[(i + 1) % n];
+        let (a, b, c) = (points[prev], points[current], points[next]);
+
+        let is_ear = cross(a, b, c) > 0.0 && !remaining.iter().any(|&other| {
+            other != prev && other != current && other != next
+                && points[other] != a && points[other] != b && points[other] != c
+                && inside_triangle(points[other], a, b, c)
+        });
+        if is_ear {
+            triangles.push([prev, current, next]);
+            remaining.remove(i % n);
+            misses = 0;
+            continue;
+        }
+        misses += 1;
+        if misses > n {
+            for j in 1..(n - 1) {
+                triangles.push([remaining[0], remaining[j], remaining[j + 1]]);
+            }
+            return triangles;
+        }
+        i = (i + 1) % n;
+    }
+    triangles.push([remaining[0], remaining[1], remaining[2]]);
+    triangles
+}
@@ -12,7 +12,14 @@ pub struct ImageTexture {
 }
 impl ImageTexture {
     pub fn new(device: std::sync::Arc<ReplacingDevice>, filepath: &str) -> Self {
-        let image = image::open(filepath).unwrap();
+        Self::from_image(device, image::open(filepath).unwrap())
+    }
+    /// Same as `new` but for an encoded png/jpeg that is already in memory, like the
+    /// images embedded in a .glb file.
+    pub fn from_memory(device: std::sync::Arc<ReplacingDevice>, bytes: &[u8]) -> Self {
+        Self::from_image(device, image::load_from_memory(bytes).unwrap())
+    }
+    fn from_image(device: std::sync::Arc<ReplacingDevice>, image: image::DynamicImage) -> Self {
         let rgba8 = image.into_rgba8();
         let vector = rgba8.clone().into_vec();
         let size =( rgba8.dimensions().0 * rgba8.dimensions().1 * 4) as usize;
@@ -77,6 +77,16 @@ impl<T> Buffer<T> {
             }
         }
     }
+    /// Overwrites the buffer from the start instead of appending, for data that changes every frame.
+    /// Panics in every build when `data` doesnt fit or the buffer isnt mapped, a skipped write
+    /// would leave last frame's data in place without anyone noticing.
+    pub fn write(&mut self, data: &[T]) {
+        let size = data.len() * std::mem::size_of::<T>();
+        assert!(size <= self.capacity as usize, "length of data should not exceed capacity that was specified at buffer allocation");
+        assert!(!self.mapped.is_null(), "buffer has to be mapped before writing to it");
+        unsafe { libc::memcpy(self.mapped as *mut libc::c_void, data.as_ptr() as *const libc::c_void, size) };
+        self.length = data.len();
+    }
     pub fn unmapping(&self, device: std::sync::Arc<ReplacingDevice>) {
         if !self.mapped.is_null() {
             unsafe { device.device.unmap_memory(self.memory) };
@@ -3,49 +3,115 @@ use ash::vk;
 use std::sync::Arc;
 use crate::vk_obj::{buffer, device::ReplacingDevice};
 
-use super::mesh::{VulkanIndexable, Mesh, Vertex};
+use num_traits::AsPrimitive;
+
+use super::mesh::{VulkanIndexable, Mesh, Vertex, SubMesh};
 
 pub struct RenderBatch<V: Vertex, I: VulkanIndexable> {
     pub vertices: buffer::raw::Buffer<V>,
     pub indices: buffer::raw::Buffer<I>,
     pub index_count: u32,
+    /// Material ranges of every mesh that was pushed, with `first_index` already
+    /// pointing into `indices`. Issue one `cmd_draw_submesh` per entry to draw per material.
+    pub submeshes: Vec<SubMesh>,
 }
 
 impl<V: Vertex, I: VulkanIndexable> RenderBatch<V, I> {
-    fn new(device: Arc<ReplacingDevice>, vertices: Vec<V>, indices: Vec<I>) -> Self {
+    pub(crate) fn new(device: Arc<ReplacingDevice>, vertices: Vec<V>, indices: Vec<I>, submeshes: Vec<SubMesh>) -> Self {
         let index_count = indices.len() as u32;
         Self { 
             vertices: buffer::raw::Buffer::from_vec(device.clone(), vk::BufferUsageFlags::VERTEX_BUFFER, vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT, &vertices), 
             indices: buffer::raw::Buffer::from_vec(device.clone(), vk::BufferUsageFlags::INDEX_BUFFER, vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT, &indices),
             index_count,
+            submeshes,
         }
     }
+    /// Records the draw for one submesh. The vertex and index buffers of the batch
+    /// need to be bound already, same as when drawing the whole batch.
+    pub fn cmd_draw_submesh(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, submesh: &SubMesh) {
+        unsafe { device.cmd_draw_indexed(command_buffer, submesh.index_count, 1, submesh.first_index, 0, 0) };
+    }
 }
 pub struct RenderBatchBuilder<V: Vertex, I: VulkanIndexable> {
     vertices: Vec<V>, 
     indices: Vec<I>,
-    index_offset: I,
+    submeshes: Vec<SubMesh>,
 }
 impl<V: Vertex, I: VulkanIndexable> RenderBatchBuilder<V, I> {
     pub fn new() -> Self {
-        Self { vertices: vec![], indices: vec![], index_offset: I::zero() }
+        Self { vertices: vec![], indices: vec![], submeshes: vec![] }
     }
+    /// Appends the mesh to the batch. Its indices get moved past the vertices that are
+    /// already in the batch, so this panics once the batch has more vertices than `I` can index.
     pub fn push(mut self, mesh: &dyn Mesh<V, I>) -> Self {
-        let mut vertices = mesh.vertices();
-
-        self.vertices.append(&mut vertices);
-
+        let first_index = self.indices.len() as u32;
         let mesh_indices = mesh.indices();
-        let mut indices = mesh_indices.iter().map(|index| {
-            *index + self.index_offset
-        }).collect();
-        self.indices.append(&mut indices);
+        let mut submeshes = mesh.submeshes();
+        // Meshes without material ranges still need one, otherwise drawing the batch per
+        // submesh would skip their triangles.
+        if submeshes.is_empty() {
+            submeshes.push(SubMesh { material: 0, first_index: 0, index_count: mesh_indices.len() as u32 });
+        }
+        self.submeshes.extend(submeshes.into_iter().map(|submesh| SubMesh {
+            first_index: submesh.first_index + first_index,
+            ..submesh
+        }));
 
-        let mut casted: *mut usize = &self.index_offset as *const _ as *mut _;
-        unsafe { *casted +=  indices.len() };
+        let mut vertices = mesh.vertices();
+        // Moved in usize so a u16 batch cant wrap around, then checked on the way back into `I`.
+        let index_offset = self.vertices.len();
+        let total = index_offset + vertices.len();
+        let indices = mesh_indices.into_iter().map(|index| {
+            let index: usize = index.as_();
+            I::from_usize(index + index_offset)
+                .unwrap_or_else(|| panic!("{} vertices in a batch are too many for its index type", total))
+        }).collect::<Vec<I>>();
+        self.vertices.append(&mut vertices);
+        self.indices.extend(indices);
         self
     }
+    /// Material ranges pushed so far, already moved to where they end up in the batch.
+    pub fn submeshes(&self) -> &[SubMesh] {
+        &self.submeshes
+    }
     pub fn build(mut self, device: Arc<ReplacingDevice>) -> RenderBatch<V, I> {
-        RenderBatch::<V, I>::new(device, self.vertices, self.indices)
+        RenderBatch::<V, I>::new(device, self.vertices, self.indices, self.submeshes)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::vertex::Vertex3D;
+
+    /// `count` vertices with one triangle using the last three.
+    struct Points(usize);
+    impl Mesh<Vertex3D, u16> for Points {
+        fn vertices(&self) -> Vec<Vertex3D> {
+            vec![Vertex3D::default(); self.0]
+        }
+        fn indices(&self) -> Vec<u16> {
+            (self.0 - 3..self.0).map(|index| index as u16).collect()
+        }
+    }
+
+    #[test]
+    fn push_moves_indices_past_the_batch() {
+        let builder = RenderBatchBuilder::new().push(&Points(3)).push(&Points(5));
+        assert_eq!(builder.indices, vec![0, 1, 2, 5, 6, 7]);
+        assert_eq!(builder.submeshes[1], SubMesh { material: 0, first_index: 3, index_count: 3 });
+    }
+
+    #[test]
+    fn push_fills_u16_exactly() {
+        let builder = RenderBatchBuilder::new().push(&Points(60000)).push(&Points(5536));
+        assert_eq!(builder.indices.last(), Some(&u16::MAX));
+    }
+
+    #[test]
+    #[should_panic(expected = "too many for its index type")]
+    fn push_panics_past_u16() {
+        // 60000 + 5537 vertices, the last index would wrap around to 0
+        RenderBatchBuilder::new().push(&Points(60000)).push(&Points(5537));
+    }
+}
@@ -2,7 +2,7 @@ use ash::vk;
 use num_traits;
 use std::sync::Arc;
 use crate::{vk_obj::{buffer::{raw, self}, device::{self}}, camera::Camera};
-pub trait VulkanIndexable: num_traits::Num + core::clone::Clone + num_traits::AsPrimitive<u8> + num_traits::AsPrimitive<u16> + num_traits::AsPrimitive<u32> + num_traits::AsPrimitive<usize> + core::clone::Clone {}
+pub trait VulkanIndexable: num_traits::Num + core::clone::Clone + num_traits::AsPrimitive<u8> + num_traits::AsPrimitive<u16> + num_traits::AsPrimitive<u32> + num_traits::AsPrimitive<usize> + num_traits::FromPrimitive + core::clone::Clone {}
 pub trait Vertex: Sized + Copy + Clone {
     fn binding_description() -> vk::VertexInputBindingDescription;
     fn attribute_description() -> Vec<vk::VertexInputAttributeDescription>;
@@ -11,7 +11,22 @@ pub trait Vertex: Sized + Copy + Clone {
 impl VulkanIndexable for u8 {}
 impl VulkanIndexable for u16 {}
 impl VulkanIndexable for u32 {}
+/// # SubMesh
+///
+/// A range of indices drawn with a single material. `first_index` is relative
+/// to the indices of the mesh it came from until it gets pushed into a `RenderBatchBuilder`.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubMesh {
+    pub material: usize,
+    pub first_index: u32,
+    pub index_count: u32,
+}
 pub trait Mesh<V: Vertex, I: VulkanIndexable> {
     fn vertices(&self) -> Vec<V>;
     fn indices(&self) -> Vec<I>;
+    /// Material ranges of the mesh. Meshes with a single material dont need to override this,
+    /// `RenderBatchBuilder::push` gives them one range over all of their indices using material 0.
+    fn submeshes(&self) -> Vec<SubMesh> {
+        vec![]
+    }
 }
\ No newline at end of file